@@ -25,6 +25,15 @@ impl BlockStatus {
     }
 }
 
+impl TransactionReceipt {
+    /// Returns true if the transaction execution was reverted.
+    ///
+    /// Receipts without an execution status are considered successful.
+    pub fn is_reverted(&self) -> bool {
+        self.execution_status() == ExecutionStatus::Reverted
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum FieldElementDecodeError {
     #[error("missing 0x prefix")]
@@ -54,6 +54,30 @@ impl Filter {
     }
 }
 
+impl TransactionFilter {
+    /// Include or exclude the transaction receipt.
+    pub fn with_include_receipt(mut self, include: bool) -> Self {
+        self.include_receipt = Some(include);
+        self
+    }
+
+    /// Include or exclude reverted transactions.
+    pub fn with_include_reverted(mut self, include: bool) -> Self {
+        self.include_reverted = Some(include);
+        self
+    }
+
+    /// Returns true if the receipt should be sent together with the transaction.
+    pub fn should_include_receipt(&self) -> bool {
+        self.include_receipt.unwrap_or(true)
+    }
+
+    /// Returns true if reverted transactions should be sent.
+    pub fn should_include_reverted(&self) -> bool {
+        self.include_reverted.unwrap_or(true)
+    }
+}
+
 impl InvokeTransactionV0Filter {
     /// Filter transaction with contract address.
     pub fn with_contract_address(mut self, address: FieldElement) -> Self {
@@ -88,6 +112,20 @@ impl InvokeTransactionV1Filter {
     }
 }
 
+impl InvokeTransactionV3Filter {
+    /// Filter transaction with sender address.
+    pub fn with_sender_address(mut self, address: FieldElement) -> Self {
+        self.sender_address = Some(address);
+        self
+    }
+
+    /// Filter with call data.
+    pub fn with_calldata(mut self, calldata: Vec<FieldElement>) -> Self {
+        self.calldata = calldata;
+        self
+    }
+}
+
 impl DeployTransactionFilter {
     /// Filter transaction with contract address salt.
     pub fn with_contract_address_salt(mut self, address: FieldElement) -> Self {
@@ -121,6 +159,46 @@ impl DeclareTransactionFilter {
     }
 }
 
+impl DeclareTransactionV2Filter {
+    /// Filter transaction with sender address.
+    pub fn with_sender_address(mut self, address: FieldElement) -> Self {
+        self.sender_address = Some(address);
+        self
+    }
+
+    /// Filter with class hash.
+    pub fn with_class_hash(mut self, class_hash: FieldElement) -> Self {
+        self.class_hash = Some(class_hash);
+        self
+    }
+
+    /// Filter with compiled class hash.
+    pub fn with_compiled_class_hash(mut self, compiled_class_hash: FieldElement) -> Self {
+        self.compiled_class_hash = Some(compiled_class_hash);
+        self
+    }
+}
+
+impl DeclareTransactionV3Filter {
+    /// Filter transaction with sender address.
+    pub fn with_sender_address(mut self, address: FieldElement) -> Self {
+        self.sender_address = Some(address);
+        self
+    }
+
+    /// Filter with class hash.
+    pub fn with_class_hash(mut self, class_hash: FieldElement) -> Self {
+        self.class_hash = Some(class_hash);
+        self
+    }
+
+    /// Filter with compiled class hash.
+    pub fn with_compiled_class_hash(mut self, compiled_class_hash: FieldElement) -> Self {
+        self.compiled_class_hash = Some(compiled_class_hash);
+        self
+    }
+}
+
 impl L1HandlerTransactionFilter {
     /// Filter transaction with contract address.
     pub fn with_contract_address(mut self, address: FieldElement) -> Self {
@@ -161,6 +239,26 @@ impl DeployAccountTransactionFilter {
     }
 }
 
+impl DeployAccountTransactionV3Filter {
+    /// Filter transaction with contract address salt.
+    pub fn with_contract_address_salt(mut self, address: FieldElement) -> Self {
+        self.contract_address_salt = Some(address);
+        self
+    }
+
+    /// Filter transaction with class hash.
+    pub fn with_class_hash(mut self, class_hash: FieldElement) -> Self {
+        self.class_hash = Some(class_hash);
+        self
+    }
+
+    /// Filter transaction with calldata.
+    pub fn with_constructor_calldata(mut self, constructor_calldata: Vec<FieldElement>) -> Self {
+        self.constructor_calldata = constructor_calldata;
+        self
+    }
+}
+
 impl EventFilter {
     /// Filter event from address.
     pub fn with_from_address(mut self, address: FieldElement) -> Self {
@@ -174,11 +272,72 @@ impl EventFilter {
         self
     }
 
+    /// Filter event with keys matching the given patterns, one for each key position.
+    pub fn with_key_patterns(mut self, key_patterns: Vec<KeyPattern>) -> Self {
+        self.key_patterns = key_patterns;
+        self
+    }
+
     /// Filter event with data.
     pub fn with_data(mut self, data: Vec<FieldElement>) -> Self {
         self.data = data;
         self
     }
+
+    /// Include or exclude the transaction that emitted the event.
+    pub fn with_include_transaction(mut self, include: bool) -> Self {
+        self.include_transaction = Some(include);
+        self
+    }
+
+    /// Include or exclude the receipt of the transaction that emitted the event.
+    pub fn with_include_receipt(mut self, include: bool) -> Self {
+        self.include_receipt = Some(include);
+        self
+    }
+
+    /// Include or exclude events emitted by reverted transactions.
+    pub fn with_include_reverted(mut self, include: bool) -> Self {
+        self.include_reverted = Some(include);
+        self
+    }
+
+    /// Returns true if the transaction should be sent together with the event.
+    pub fn should_include_transaction(&self) -> bool {
+        self.include_transaction.unwrap_or(true)
+    }
+
+    /// Returns true if the receipt should be sent together with the event.
+    pub fn should_include_receipt(&self) -> bool {
+        self.include_receipt.unwrap_or(true)
+    }
+
+    /// Returns true if events emitted by reverted transactions should be sent.
+    pub fn should_include_reverted(&self) -> bool {
+        self.include_reverted.unwrap_or(true)
+    }
+}
+
+impl KeyPattern {
+    /// Create a pattern that matches any key.
+    pub fn any() -> Self {
+        KeyPattern::default()
+    }
+
+    /// Create a pattern that matches only the given key.
+    pub fn exact(key: FieldElement) -> Self {
+        KeyPattern { values: vec![key] }
+    }
+
+    /// Create a pattern that matches any of the given keys.
+    pub fn one_of(keys: Vec<FieldElement>) -> Self {
+        KeyPattern { values: keys }
+    }
+
+    /// Returns true if the pattern matches any key.
+    pub fn is_any(&self) -> bool {
+        self.values.is_empty()
+    }
 }
 
 impl L2ToL1MessageFilter {
@@ -193,6 +352,28 @@ impl L2ToL1MessageFilter {
         self.payload = payload;
         self
     }
+
+    /// Include or exclude the transaction that sent the message.
+    pub fn with_include_transaction(mut self, include: bool) -> Self {
+        self.include_transaction = Some(include);
+        self
+    }
+
+    /// Include or exclude the receipt of the transaction that sent the message.
+    pub fn with_include_receipt(mut self, include: bool) -> Self {
+        self.include_receipt = Some(include);
+        self
+    }
+
+    /// Returns true if the transaction should be sent together with the message.
+    pub fn should_include_transaction(&self) -> bool {
+        self.include_transaction.unwrap_or(true)
+    }
+
+    /// Returns true if the receipt should be sent together with the message.
+    pub fn should_include_receipt(&self) -> bool {
+        self.include_receipt.unwrap_or(true)
+    }
 }
 
 impl StateUpdateFilter {
@@ -331,8 +512,17 @@ impl TransactionFilter {
             Some(transaction_filter::Filter::Declare(filter)) => filter.matches(tx),
             Some(transaction_filter::Filter::L1Handler(filter)) => filter.matches(tx),
             Some(transaction_filter::Filter::DeployAccount(filter)) => filter.matches(tx),
+            Some(transaction_filter::Filter::InvokeV3(filter)) => filter.matches(tx),
+            Some(transaction_filter::Filter::DeclareV2(filter)) => filter.matches(tx),
+            Some(transaction_filter::Filter::DeclareV3(filter)) => filter.matches(tx),
+            Some(transaction_filter::Filter::DeployAccountV3(filter)) => filter.matches(tx),
         }
     }
+
+    /// Returns true if the filter accepts transactions with the receipt's execution status.
+    pub fn matches_execution_status(&self, receipt: &TransactionReceipt) -> bool {
+        self.should_include_reverted() || !receipt.is_reverted()
+    }
 }
 
 impl InvokeTransactionV0Filter {
@@ -360,6 +550,18 @@ impl InvokeTransactionV1Filter {
     }
 }
 
+impl InvokeTransactionV3Filter {
+    pub fn matches(&self, tx: &Transaction) -> bool {
+        match tx.transaction.as_ref() {
+            Some(transaction::Transaction::InvokeV3(tx)) => {
+                self.sender_address.matches(&tx.sender_address)
+                    && self.calldata.prefix_matches(&tx.calldata)
+            }
+            _ => false,
+        }
+    }
+}
+
 impl DeployTransactionFilter {
     pub fn matches(&self, tx: &Transaction) -> bool {
         match tx.transaction.as_ref() {
@@ -389,6 +591,32 @@ impl DeclareTransactionFilter {
     }
 }
 
+impl DeclareTransactionV2Filter {
+    pub fn matches(&self, tx: &Transaction) -> bool {
+        match tx.transaction.as_ref() {
+            Some(transaction::Transaction::DeclareV2(tx)) => {
+                self.class_hash.matches(&tx.class_hash)
+                    && self.sender_address.matches(&tx.sender_address)
+                    && self.compiled_class_hash.matches(&tx.compiled_class_hash)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl DeclareTransactionV3Filter {
+    pub fn matches(&self, tx: &Transaction) -> bool {
+        match tx.transaction.as_ref() {
+            Some(transaction::Transaction::DeclareV3(tx)) => {
+                self.class_hash.matches(&tx.class_hash)
+                    && self.sender_address.matches(&tx.sender_address)
+                    && self.compiled_class_hash.matches(&tx.compiled_class_hash)
+            }
+            _ => false,
+        }
+    }
+}
+
 impl L1HandlerTransactionFilter {
     pub fn matches(&self, tx: &Transaction) -> bool {
         match tx.transaction.as_ref() {
@@ -419,12 +647,53 @@ impl DeployAccountTransactionFilter {
     }
 }
 
+impl DeployAccountTransactionV3Filter {
+    pub fn matches(&self, tx: &Transaction) -> bool {
+        match tx.transaction.as_ref() {
+            Some(transaction::Transaction::DeployAccountV3(tx)) => {
+                self.class_hash.matches(&tx.class_hash)
+                    && self
+                        .contract_address_salt
+                        .matches(&tx.contract_address_salt)
+                    && self
+                        .constructor_calldata
+                        .prefix_matches(&tx.constructor_calldata)
+            }
+            _ => false,
+        }
+    }
+}
+
 impl EventFilter {
     pub fn matches(&self, event: &Event) -> bool {
         self.from_address.matches(&event.from_address)
             && self.keys.prefix_matches(&event.keys)
+            && self.key_patterns_match(&event.keys)
             && self.data.prefix_matches(&event.data)
     }
+
+    /// Returns true if the filter accepts events from transactions with the receipt's
+    /// execution status.
+    pub fn matches_execution_status(&self, receipt: &TransactionReceipt) -> bool {
+        self.should_include_reverted() || !receipt.is_reverted()
+    }
+
+    fn key_patterns_match(&self, keys: &[FieldElement]) -> bool {
+        if self.key_patterns.len() > keys.len() {
+            return false;
+        }
+
+        self.key_patterns
+            .iter()
+            .zip(keys)
+            .all(|(pattern, key)| pattern.matches(key))
+    }
+}
+
+impl KeyPattern {
+    pub fn matches(&self, key: &FieldElement) -> bool {
+        self.is_any() || self.values.contains(key)
+    }
 }
 
 impl L2ToL1MessageFilter {
@@ -460,3 +729,220 @@ impl NonceUpdateFilter {
         self.contract_address.matches(&nonce.contract_address) && self.nonce.matches(&nonce.nonce)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::starknet::v1alpha2::{
+        transaction, transaction_filter, DeclareTransactionV2, DeclareTransactionV2Filter,
+        DeclareTransactionV3, DeclareTransactionV3Filter, DeployAccountTransactionV3,
+        DeployAccountTransactionV3Filter, Event, EventFilter, ExecutionStatus, FieldElement,
+        InvokeTransactionV1, InvokeTransactionV3, InvokeTransactionV3Filter, KeyPattern,
+        Transaction, TransactionFilter, TransactionReceipt,
+    };
+
+    fn new_event(keys: &[u64]) -> Event {
+        Event {
+            from_address: Some(FieldElement::from_u64(1)),
+            keys: keys.iter().copied().map(FieldElement::from_u64).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_event_keys_prefix_match() {
+        let filter = EventFilter::default().with_keys(vec![FieldElement::from_u64(10)]);
+        assert!(filter.matches(&new_event(&[10])));
+        assert!(filter.matches(&new_event(&[10, 20])));
+        assert!(!filter.matches(&new_event(&[20, 10])));
+        assert!(!filter.matches(&new_event(&[])));
+    }
+
+    #[test]
+    fn test_event_key_patterns() {
+        // selector = 10 AND to = 30, any from.
+        let filter = EventFilter::default().with_key_patterns(vec![
+            KeyPattern::exact(FieldElement::from_u64(10)),
+            KeyPattern::any(),
+            KeyPattern::exact(FieldElement::from_u64(30)),
+        ]);
+        assert!(filter.matches(&new_event(&[10, 20, 30])));
+        assert!(filter.matches(&new_event(&[10, 21, 30, 40])));
+        assert!(!filter.matches(&new_event(&[10, 20, 31])));
+        assert!(!filter.matches(&new_event(&[10, 20])));
+
+        // key0 in {10, 11}.
+        let filter = EventFilter::default().with_key_patterns(vec![KeyPattern::one_of(vec![
+            FieldElement::from_u64(10),
+            FieldElement::from_u64(11),
+        ])]);
+        assert!(filter.matches(&new_event(&[10])));
+        assert!(filter.matches(&new_event(&[11, 20])));
+        assert!(!filter.matches(&new_event(&[12])));
+    }
+
+    #[test]
+    fn test_event_keys_and_key_patterns() {
+        let filter = EventFilter::default()
+            .with_keys(vec![FieldElement::from_u64(10)])
+            .with_key_patterns(vec![
+                KeyPattern::any(),
+                KeyPattern::exact(FieldElement::from_u64(20)),
+            ]);
+        assert!(filter.matches(&new_event(&[10, 20])));
+        assert!(!filter.matches(&new_event(&[11, 20])));
+        assert!(!filter.matches(&new_event(&[10, 21])));
+    }
+
+    #[test]
+    fn test_include_reverted() {
+        let succeeded = TransactionReceipt {
+            execution_status: ExecutionStatus::Succeeded as i32,
+            ..Default::default()
+        };
+        let reverted = TransactionReceipt {
+            execution_status: ExecutionStatus::Reverted as i32,
+            ..Default::default()
+        };
+        // receipts stored before the execution status existed.
+        let unspecified = TransactionReceipt::default();
+
+        // reverted transactions are included unless the client opts out.
+        let filter = TransactionFilter::default();
+        assert!(filter.matches_execution_status(&succeeded));
+        assert!(filter.matches_execution_status(&reverted));
+
+        let filter = TransactionFilter::default().with_include_reverted(false);
+        assert!(filter.matches_execution_status(&succeeded));
+        assert!(filter.matches_execution_status(&unspecified));
+        assert!(!filter.matches_execution_status(&reverted));
+
+        let filter = EventFilter::default();
+        assert!(filter.matches_execution_status(&reverted));
+
+        let filter = EventFilter::default().with_include_reverted(false);
+        assert!(filter.matches_execution_status(&succeeded));
+        assert!(!filter.matches_execution_status(&reverted));
+    }
+
+    fn new_transaction(tx: transaction::Transaction) -> Transaction {
+        Transaction {
+            meta: None,
+            transaction: Some(tx),
+        }
+    }
+
+    fn felts(values: &[u64]) -> Vec<FieldElement> {
+        values.iter().copied().map(FieldElement::from_u64).collect()
+    }
+
+    #[test]
+    fn test_invoke_v3_filter() {
+        let filter = TransactionFilter {
+            filter: Some(transaction_filter::Filter::InvokeV3(
+                InvokeTransactionV3Filter::default()
+                    .with_sender_address(FieldElement::from_u64(1))
+                    .with_calldata(felts(&[10])),
+            )),
+            ..Default::default()
+        };
+
+        let invoke = |sender: u64, calldata: &[u64]| {
+            new_transaction(transaction::Transaction::InvokeV3(InvokeTransactionV3 {
+                sender_address: Some(FieldElement::from_u64(sender)),
+                calldata: felts(calldata),
+                ..Default::default()
+            }))
+        };
+        assert!(filter.matches(&invoke(1, &[10, 20])));
+        assert!(!filter.matches(&invoke(2, &[10, 20])));
+        assert!(!filter.matches(&invoke(1, &[20, 10])));
+
+        // same fields, older version.
+        let invoke_v1 = new_transaction(transaction::Transaction::InvokeV1(InvokeTransactionV1 {
+            sender_address: Some(FieldElement::from_u64(1)),
+            calldata: felts(&[10]),
+            ..Default::default()
+        }));
+        assert!(!filter.matches(&invoke_v1));
+    }
+
+    #[test]
+    fn test_declare_v2_and_v3_filters() {
+        let filter_v2 = TransactionFilter {
+            filter: Some(transaction_filter::Filter::DeclareV2(
+                DeclareTransactionV2Filter::default()
+                    .with_class_hash(FieldElement::from_u64(1))
+                    .with_compiled_class_hash(FieldElement::from_u64(2)),
+            )),
+            ..Default::default()
+        };
+        let filter_v3 = TransactionFilter {
+            filter: Some(transaction_filter::Filter::DeclareV3(
+                DeclareTransactionV3Filter::default()
+                    .with_sender_address(FieldElement::from_u64(3))
+                    .with_class_hash(FieldElement::from_u64(1)),
+            )),
+            ..Default::default()
+        };
+
+        let declare_v2 =
+            new_transaction(transaction::Transaction::DeclareV2(DeclareTransactionV2 {
+                class_hash: Some(FieldElement::from_u64(1)),
+                sender_address: Some(FieldElement::from_u64(3)),
+                compiled_class_hash: Some(FieldElement::from_u64(2)),
+            }));
+        let other_declare_v2 =
+            new_transaction(transaction::Transaction::DeclareV2(DeclareTransactionV2 {
+                class_hash: Some(FieldElement::from_u64(1)),
+                sender_address: Some(FieldElement::from_u64(3)),
+                compiled_class_hash: Some(FieldElement::from_u64(4)),
+            }));
+        let declare_v3 =
+            new_transaction(transaction::Transaction::DeclareV3(DeclareTransactionV3 {
+                class_hash: Some(FieldElement::from_u64(1)),
+                sender_address: Some(FieldElement::from_u64(3)),
+                compiled_class_hash: Some(FieldElement::from_u64(2)),
+                ..Default::default()
+            }));
+        let other_declare_v3 =
+            new_transaction(transaction::Transaction::DeclareV3(DeclareTransactionV3 {
+                class_hash: Some(FieldElement::from_u64(1)),
+                sender_address: Some(FieldElement::from_u64(4)),
+                compiled_class_hash: Some(FieldElement::from_u64(2)),
+                ..Default::default()
+            }));
+
+        assert!(filter_v2.matches(&declare_v2));
+        assert!(!filter_v2.matches(&other_declare_v2));
+        assert!(!filter_v2.matches(&declare_v3));
+
+        assert!(filter_v3.matches(&declare_v3));
+        assert!(!filter_v3.matches(&other_declare_v3));
+        assert!(!filter_v3.matches(&declare_v2));
+    }
+
+    #[test]
+    fn test_deploy_account_v3_filter() {
+        let filter = TransactionFilter {
+            filter: Some(transaction_filter::Filter::DeployAccountV3(
+                DeployAccountTransactionV3Filter::default()
+                    .with_class_hash(FieldElement::from_u64(1))
+                    .with_constructor_calldata(felts(&[10])),
+            )),
+            ..Default::default()
+        };
+
+        let deploy = |class_hash: u64, calldata: &[u64]| {
+            new_transaction(transaction::Transaction::DeployAccountV3(
+                DeployAccountTransactionV3 {
+                    class_hash: Some(FieldElement::from_u64(class_hash)),
+                    contract_address_salt: Some(FieldElement::from_u64(5)),
+                    constructor_calldata: felts(calldata),
+                },
+            ))
+        };
+        assert!(filter.matches(&deploy(1, &[10, 20])));
+        assert!(!filter.matches(&deploy(2, &[10, 20])));
+        assert!(!filter.matches(&deploy(1, &[])));
+    }
+}
@@ -3,7 +3,7 @@ use apibara_core::{
     node::v1alpha2::DataFinality,
     starknet::v1alpha2::{Block, FieldElement, Filter, HeaderFilter},
 };
-use apibara_sdk::{ClientBuilder, Configuration, DataMessage};
+use apibara_sdk::{ClientBuilder, Configuration, DataMessage, ReconnectConfig};
 use chrono::{DateTime, Utc};
 use tokio_stream::StreamExt;
 
@@ -18,10 +18,12 @@ fn build_filter(f: Filter) -> Filter {
         FieldElement::from_hex("0x99cd8bde557814842a3121e8ddfd433a539b8c9f14bf31ebf108d12e6196e9")
             .unwrap();
 
-    // filter all transfers from the eth address, include the block header
+    // filter all transfers from the eth address, include the block header.
+    // the transaction receipt is not used, so don't request it.
     f.with_header(HeaderFilter::weak()).add_event(|ev| {
         ev.with_from_address(eth_address.clone())
             .with_keys(vec![transfer_key.clone()])
+            .with_include_receipt(false)
     })
 }
 
@@ -38,7 +40,9 @@ async fn main() -> Result<()> {
 
     // connnect to the mainnet stream
     let uri = "https://mainnet.starknet.a5a.ch".parse()?;
+    // reconnect and resume from the last batch if the connection drops
     let (mut data_stream, data_client) = ClientBuilder::<Filter, Block>::default()
+        .with_reconnect(ReconnectConfig::default())
         .connect(uri)
         .await
         .unwrap();
@@ -47,17 +51,19 @@ async fn main() -> Result<()> {
     data_client.send(configuration).await.unwrap();
 
     // stream data from server
-    while let Some(message) = data_stream.try_next().await.unwrap() {
+    while let Some(message) = data_stream.try_next().await? {
         // messages can be either data or invalidate
         // - data: new data produced
         // - invalidate: a chain reorganization happened and some previously sent data is not valid
         // anymore
+        // - error: the server closed the stream
         match message {
             DataMessage::Data {
                 cursor,
                 end_cursor,
                 finality,
                 batch,
+                ..
             } => {
                 // cursor that generated the batch. if cursor = `None`, then it's the start of the
                 // chain (includes genesis block).
@@ -77,9 +83,9 @@ async fn main() -> Result<()> {
 
                     // go through all events in the block
                     for event_with_tx in block.events {
-                        // event includes the tx that triggered the event emission
-                        // it also include the receipt in `event_with_tx.receipt`, but
-                        // it's not used in this example
+                        // event includes the tx that triggered the event emission.
+                        // the receipt in `event_with_tx.receipt` is excluded by the
+                        // filter, so it's always `None` here
                         let event = event_with_tx.event.unwrap_or_default();
                         let tx = event_with_tx.transaction.unwrap_or_default();
                         let tx_hash = tx
@@ -101,9 +107,16 @@ async fn main() -> Result<()> {
                     }
                 }
             }
-            DataMessage::Invalidate { cursor } => {
+            DataMessage::Invalidate { cursor, .. } => {
                 println!("Chain reorganization detected: {cursor:?}");
             }
+            DataMessage::Error { message, .. } => {
+                println!("Stream failed: {message}");
+                break;
+            }
+            DataMessage::Reconnected { cursors, .. } => {
+                println!("Reconnected to stream: {cursors:?}");
+            }
         }
     }
 
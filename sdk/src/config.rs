@@ -4,6 +4,11 @@ use prost::Message;
 /// Data stream configuration.
 #[derive(Debug, Clone)]
 pub struct Configuration<F: Message + Default> {
+    /// The stream configured.
+    ///
+    /// Streams with different ids share the same connection, each starting
+    /// from its own cursor.
+    pub stream_id: u64,
     /// Number of blocks per batch.
     pub batch_size: u64,
     /// Starting cursor.
@@ -12,6 +17,12 @@ pub struct Configuration<F: Message + Default> {
     pub finality: Option<DataFinality>,
     /// The data filter.
     pub filter: F,
+    /// Include the data of rejected blocks when invalidating data.
+    pub include_rejected: bool,
+    /// Number of messages the server sends before waiting for more credits.
+    ///
+    /// If `None`, the stream is not flow controlled.
+    pub credits: Option<u64>,
 }
 
 impl<F> Configuration<F>
@@ -26,13 +37,24 @@ where
         filter: F,
     ) -> Self {
         Self {
+            stream_id: 0,
             batch_size,
             starting_cursor,
             finality,
             filter,
+            include_rejected: false,
+            credits: None,
         }
     }
 
+    /// Set the id of the stream to configure.
+    ///
+    /// If a stream with the same id exists, it's replaced by the new configuration.
+    pub fn with_stream_id(mut self, stream_id: u64) -> Self {
+        self.stream_id = stream_id;
+        self
+    }
+
     /// Set the batch size.
     pub fn with_batch_size(mut self, batch_size: u64) -> Self {
         self.batch_size = batch_size;
@@ -60,6 +82,22 @@ where
         self
     }
 
+    /// Receive the data of the blocks removed from the canonical chain
+    /// together with invalidate messages.
+    pub fn with_rejected_data(mut self, include_rejected: bool) -> Self {
+        self.include_rejected = include_rejected;
+        self
+    }
+
+    /// Only receive `credits` messages before the client grants more with
+    /// [DataStreamClient::grant_credits](crate::DataStreamClient::grant_credits).
+    ///
+    /// A stream waiting for credits doesn't pause the other streams on the connection.
+    pub fn with_credits(mut self, credits: u64) -> Self {
+        self.credits = Some(credits);
+        self
+    }
+
     /// Configure the data filter.
     pub fn with_filter<G>(mut self, filter_closure: G) -> Self
     where
@@ -76,10 +114,13 @@ where
 {
     fn default() -> Self {
         Self {
+            stream_id: 0,
             batch_size: 1,
             starting_cursor: None,
             finality: None,
             filter: F::default(),
+            include_rejected: false,
+            credits: None,
         }
     }
 }
@@ -96,7 +137,10 @@ mod tests {
     #[test]
     fn test_config() {
         let config = Configuration::<Filter>::default();
+        assert_eq!(0, config.stream_id);
         assert_eq!(1, config.batch_size);
+        assert!(!config.include_rejected);
+        assert_eq!(None, config.credits);
     }
 
     #[test]
@@ -109,9 +153,12 @@ mod tests {
     #[test]
     fn test_config_can_be_configured() {
         let config = Configuration::<Filter>::default()
+            .with_stream_id(3)
             .with_batch_size(10)
             .with_starting_block(111)
             .with_finality(DataFinality::DataStatusAccepted)
+            .with_rejected_data(true)
+            .with_credits(5)
             .with_filter(|filter| {
                 filter
                     .with_header(HeaderFilter { weak: true })
@@ -123,9 +170,12 @@ mod tests {
                     })
             });
 
+        assert_eq!(3, config.stream_id);
         assert_eq!(10, config.batch_size);
         assert_eq!(111, config.starting_cursor.unwrap().order_key);
         assert_eq!(DataFinality::DataStatusAccepted, config.finality.unwrap());
+        assert!(config.include_rejected);
+        assert_eq!(Some(5), config.credits);
         assert_eq!(true, config.filter.header.unwrap().weak);
     }
 }
@@ -0,0 +1,383 @@
+//! Run indexers on top of a data stream.
+//!
+//! An [Indexer] transforms the data received from the stream into records,
+//! and an [IndexerRunner] writes them to a [Sink] together with the stream
+//! cursor. Since records and cursor are written atomically, the indexer can
+//! be restarted at any time and resumes from the last batch written.
+//!
+//! Pending data is skipped, since the same block is sent again once it's
+//! accepted.
+use apibara_core::node::v1alpha2::{Cursor, DataFinality};
+use async_trait::async_trait;
+use futures::{Stream, TryStreamExt};
+use prost::Message;
+use tracing::{debug, info};
+
+use crate::{
+    sink::Sink, ClientBuilder, ClientBuilderError, Configuration, DataMessage, DataStreamError, Uri,
+};
+
+/// A value produced by an indexer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record<T> {
+    /// The order key (block number) of the block that produced the value.
+    ///
+    /// Used to remove the record if the block is removed by a chain reorganization.
+    pub order_key: u64,
+    /// The value.
+    pub value: T,
+}
+
+impl<T> Record<T> {
+    /// Creates a new record produced by the block with the given order key.
+    pub fn new(order_key: u64, value: T) -> Self {
+        Record { order_key, value }
+    }
+}
+
+/// A batch of data received from the stream.
+#[derive(Debug)]
+pub struct Batch<D> {
+    /// The batch starting cursor.
+    pub cursor: Option<Cursor>,
+    /// The batch end cursor.
+    pub end_cursor: Cursor,
+    /// The data finality.
+    pub finality: DataFinality,
+    /// The batch of data.
+    pub data: Vec<D>,
+}
+
+/// Transforms stream data into records.
+#[async_trait]
+pub trait Indexer<D>: Send
+where
+    D: Message + Default,
+{
+    /// The values written to the sink.
+    type Item: Send;
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Handles a new batch of data, returning the records to write to the sink.
+    async fn handle_data(
+        &mut self,
+        batch: Batch<D>,
+    ) -> Result<Vec<Record<Self::Item>>, Self::Error>;
+
+    /// Handles a chain reorganization.
+    ///
+    /// All records produced by blocks after `cursor` are removed from the
+    /// sink after this method returns, so most indexers don't need to
+    /// implement it.
+    async fn handle_invalidate(
+        &mut self,
+        _cursor: Option<&Cursor>,
+        _rejected: Vec<D>,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IndexerError<I, S>
+where
+    I: std::error::Error + 'static,
+    S: std::error::Error + 'static,
+{
+    #[error("Indexer failed to handle message")]
+    Indexer(#[source] I),
+    #[error("Failed to write to sink")]
+    Sink(#[source] S),
+    #[error(transparent)]
+    Connect(#[from] ClientBuilderError),
+    #[error(transparent)]
+    Stream(#[from] DataStreamError),
+    #[error("Stream closed by the server: {message}")]
+    StreamClosed { message: String },
+}
+
+/// Runs an [Indexer], persisting its records to a [Sink].
+///
+/// The sink stores a single cursor, so the indexer should consume a single stream.
+pub struct IndexerRunner<I, S> {
+    indexer: I,
+    sink: S,
+}
+
+impl<I, S> IndexerRunner<I, S>
+where
+    S: Sink,
+{
+    /// Creates a new runner that writes the records produced by `indexer` to `sink`.
+    pub fn new(indexer: I, sink: S) -> Self {
+        IndexerRunner { indexer, sink }
+    }
+
+    /// Returns the indexer and sink.
+    pub fn into_inner(self) -> (I, S) {
+        (self.indexer, self.sink)
+    }
+
+    /// Connects to the stream at the given url and runs the indexer until the stream ends.
+    ///
+    /// If the sink contains a cursor, the stream starts from it instead of the
+    /// configuration starting cursor.
+    pub async fn run<F, D>(
+        &mut self,
+        url: Uri,
+        builder: ClientBuilder<F, D>,
+        configuration: Configuration<F>,
+    ) -> Result<(), IndexerError<I::Error, S::Error>>
+    where
+        F: Message + Default + Clone,
+        D: Message + Default,
+        I: Indexer<D, Item = S::Item>,
+    {
+        let configuration = match self.sink.cursor().await.map_err(IndexerError::Sink)? {
+            None => configuration,
+            Some(cursor) => {
+                info!(cursor = ?cursor, "resume indexing from sink cursor");
+                configuration.with_starting_cursor(cursor)
+            }
+        };
+
+        // the stream ends when the client is dropped, so keep it around.
+        let (stream, _client) = builder
+            .with_configuration(configuration)
+            .connect(url)
+            .await?;
+
+        self.run_stream(stream).await
+    }
+
+    /// Runs the indexer on the messages of the given stream.
+    ///
+    /// Pending batches are not passed to the indexer.
+    pub async fn run_stream<D, St>(
+        &mut self,
+        mut stream: St,
+    ) -> Result<(), IndexerError<I::Error, S::Error>>
+    where
+        D: Message + Default,
+        I: Indexer<D, Item = S::Item>,
+        St: Stream<Item = Result<DataMessage<D>, DataStreamError>> + Unpin,
+    {
+        while let Some(message) = stream.try_next().await? {
+            match message {
+                DataMessage::Data {
+                    cursor,
+                    end_cursor,
+                    finality,
+                    batch,
+                    ..
+                } => {
+                    if finality == DataFinality::DataStatusPending {
+                        debug!(end_cursor = ?end_cursor, "skip pending data");
+                        continue;
+                    }
+
+                    debug!(end_cursor = ?end_cursor, "handle data");
+                    let batch = Batch {
+                        cursor,
+                        end_cursor: end_cursor.clone(),
+                        finality,
+                        data: batch,
+                    };
+                    let records = self
+                        .indexer
+                        .handle_data(batch)
+                        .await
+                        .map_err(IndexerError::Indexer)?;
+                    self.sink
+                        .write(&end_cursor, records)
+                        .await
+                        .map_err(IndexerError::Sink)?;
+                }
+                DataMessage::Invalidate {
+                    cursor, rejected, ..
+                } => {
+                    info!(cursor = ?cursor, "invalidate data");
+                    self.indexer
+                        .handle_invalidate(cursor.as_ref(), rejected)
+                        .await
+                        .map_err(IndexerError::Indexer)?;
+                    self.sink
+                        .invalidate(cursor.as_ref())
+                        .await
+                        .map_err(IndexerError::Sink)?;
+                }
+                DataMessage::Error { message, .. } => {
+                    return Err(IndexerError::StreamClosed { message });
+                }
+                DataMessage::Reconnected { cursors, attempts } => {
+                    info!(cursors = ?cursors, attempts = attempts, "stream reconnected");
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use apibara_core::{
+        node::v1alpha2::{Cursor, DataFinality},
+        starknet::v1alpha2::{Block, BlockHeader},
+    };
+    use async_trait::async_trait;
+
+    use crate::{
+        sink::{JsonlSink, Sink},
+        DataMessage, DataStreamError,
+    };
+
+    use super::{Batch, Indexer, IndexerError, IndexerRunner, Record};
+
+    /// Indexes the block numbers, counting the invalidations.
+    #[derive(Default)]
+    struct BlockNumberIndexer {
+        invalidations: usize,
+    }
+
+    #[async_trait]
+    impl Indexer<Block> for BlockNumberIndexer {
+        type Item = u64;
+        type Error = Infallible;
+
+        async fn handle_data(
+            &mut self,
+            batch: Batch<Block>,
+        ) -> Result<Vec<Record<u64>>, Self::Error> {
+            let records = batch
+                .data
+                .into_iter()
+                .map(|block| {
+                    let number = block.header.unwrap_or_default().block_number;
+                    Record::new(number, number)
+                })
+                .collect();
+            Ok(records)
+        }
+
+        async fn handle_invalidate(
+            &mut self,
+            _cursor: Option<&Cursor>,
+            _rejected: Vec<Block>,
+        ) -> Result<(), Self::Error> {
+            self.invalidations += 1;
+            Ok(())
+        }
+    }
+
+    fn cursor(block: u64) -> Cursor {
+        Cursor {
+            order_key: block,
+            unique_key: vec![block as u8],
+        }
+    }
+
+    fn data(start_block: u64, end_block: u64) -> Result<DataMessage<Block>, DataStreamError> {
+        data_with_finality(start_block, end_block, DataFinality::DataStatusAccepted)
+    }
+
+    fn data_with_finality(
+        start_block: u64,
+        end_block: u64,
+        finality: DataFinality,
+    ) -> Result<DataMessage<Block>, DataStreamError> {
+        let batch = (start_block..=end_block)
+            .map(|block_number| Block {
+                header: Some(BlockHeader {
+                    block_number,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .collect();
+        Ok(DataMessage::Data {
+            stream_id: 0,
+            cursor: Some(cursor(start_block - 1)),
+            end_cursor: cursor(end_block),
+            finality,
+            batch,
+        })
+    }
+
+    fn invalidate(block: u64) -> Result<DataMessage<Block>, DataStreamError> {
+        Ok(DataMessage::Invalidate {
+            stream_id: 0,
+            cursor: Some(cursor(block)),
+            rejected: Vec::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_write_records_and_rollback() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = JsonlSink::<u64>::open(dir.path()).unwrap();
+        let mut runner = IndexerRunner::new(BlockNumberIndexer::default(), sink);
+
+        let messages = vec![data(1, 5), data(6, 10), invalidate(7), data(8, 9)];
+        runner
+            .run_stream(futures::stream::iter(messages))
+            .await
+            .unwrap();
+
+        let (indexer, mut sink) = runner.into_inner();
+        assert_eq!(indexer.invalidations, 1);
+        assert_eq!(sink.cursor().await.unwrap(), Some(cursor(9)));
+        let values = sink
+            .records()
+            .unwrap()
+            .into_iter()
+            .map(|record| record.value)
+            .collect::<Vec<_>>();
+        assert_eq!(values, (1..=9).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_skip_pending_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = JsonlSink::<u64>::open(dir.path()).unwrap();
+        let mut runner = IndexerRunner::new(BlockNumberIndexer::default(), sink);
+
+        let messages = vec![
+            data(1, 5),
+            data_with_finality(6, 6, DataFinality::DataStatusPending),
+            data(6, 6),
+        ];
+        runner
+            .run_stream(futures::stream::iter(messages))
+            .await
+            .unwrap();
+
+        let (_, mut sink) = runner.into_inner();
+        assert_eq!(sink.cursor().await.unwrap(), Some(cursor(6)));
+        let values = sink
+            .records()
+            .unwrap()
+            .into_iter()
+            .map(|record| record.value)
+            .collect::<Vec<_>>();
+        assert_eq!(values, (1..=6).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_stop_on_stream_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = JsonlSink::<u64>::open(dir.path()).unwrap();
+        let mut runner = IndexerRunner::new(BlockNumberIndexer::default(), sink);
+
+        let status = tonic::Status::unavailable("connection lost");
+        let messages = vec![data(1, 5), Err(status.into()), data(6, 10)];
+        let result = runner.run_stream(futures::stream::iter(messages)).await;
+        assert!(matches!(result, Err(IndexerError::Stream(_))));
+
+        // only the batch received before the error is written.
+        let (_, mut sink) = runner.into_inner();
+        assert_eq!(sink.cursor().await.unwrap(), Some(cursor(5)));
+    }
+}
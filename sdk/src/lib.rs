@@ -1,9 +1,17 @@
 pub mod config;
+pub mod indexer;
+pub mod reconnect;
+pub mod sink;
+#[cfg(test)]
+mod test_server;
 
 use std::{
+    collections::BTreeMap,
+    future::Future,
     marker::PhantomData,
     pin::Pin,
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 use apibara_core::node::v1alpha2::{
@@ -13,19 +21,35 @@ use apibara_core::node::v1alpha2::{
 use futures::Stream;
 use pin_project::pin_project;
 use prost::Message;
-use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::{
+    sync::mpsc::{
+        self,
+        error::{SendError, TrySendError},
+        Receiver, Sender,
+    },
+    time::Sleep,
+};
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::{
     metadata::{errors::InvalidMetadataValue, MetadataValue},
     transport::Channel,
     Streaming,
 };
-use tracing::debug;
+use tracing::{debug, warn};
 
 // Re-export tonic Uri
 pub use tonic::transport::Uri;
 
-pub use crate::config::Configuration;
+pub use crate::{
+    config::Configuration,
+    indexer::{Indexer, IndexerRunner},
+    reconnect::ReconnectConfig,
+};
+
+use crate::reconnect::is_transient;
+
+/// The server sends a heartbeat every 30 seconds if there is no data.
+pub const DEFAULT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(45);
 
 #[derive(Debug, thiserror::Error)]
 pub enum ClientBuilderError {
@@ -34,18 +58,40 @@ pub enum ClientBuilderError {
     #[error(transparent)]
     TonicError(#[from] tonic::transport::Error),
     #[error(transparent)]
-    FailedToConfigureStream(Box<dyn std::error::Error>),
+    FailedToConfigureStream(Box<dyn std::error::Error + Send + Sync + 'static>),
     #[error(transparent)]
     InvalidMetadata(#[from] InvalidMetadataValue),
     #[error(transparent)]
     StreamError(#[from] tonic::Status),
 }
 
+/// An error generated by [DataStream].
+#[derive(Debug, thiserror::Error)]
+pub enum DataStreamError {
+    #[error(transparent)]
+    Transport(#[from] tonic::transport::Error),
+    #[error(transparent)]
+    Status(#[from] tonic::Status),
+    #[error("Failed to decode block data at index {index}")]
+    Decode {
+        /// Index of the block in the batch.
+        index: usize,
+        #[source]
+        source: prost::DecodeError,
+    },
+    #[error("Failed to send configuration to the server")]
+    FailedToSendConfiguration(#[from] TrySendError<StreamDataRequest>),
+    #[error("No message received from the server in {0:?}")]
+    HeartbeatTimeout(Duration),
+}
+
 /// A message generated by [DataStream].
 #[derive(Debug)]
 pub enum DataMessage<D: Message + Default> {
     /// A new batch of data.
     Data {
+        /// The stream that produced the data.
+        stream_id: u64,
         /// The batch starting cursor.
         cursor: Option<Cursor>,
         /// The batch end cursor.
@@ -59,8 +105,32 @@ pub enum DataMessage<D: Message + Default> {
     },
     /// Invalidate all data received after the given cursor.
     Invalidate {
+        /// The stream that received the data.
+        stream_id: u64,
         /// The cursor.
         cursor: Option<Cursor>,
+        /// The data of the blocks removed from the canonical chain, oldest first.
+        ///
+        /// Only populated if the stream was configured to include rejected data.
+        rejected: Vec<D>,
+    },
+    /// The stream failed and was closed by the server.
+    ///
+    /// The other streams on the connection keep receiving data.
+    Error {
+        /// The stream that failed.
+        stream_id: u64,
+        /// The error message sent by the server.
+        message: String,
+    },
+    /// The stream lost its connection and reconnected to the server.
+    ///
+    /// Only sent if the stream was built with [ClientBuilder::with_reconnect].
+    Reconnected {
+        /// The cursor each stream resumed from, by stream id.
+        cursors: BTreeMap<u64, Option<Cursor>>,
+        /// Number of attempts needed to reconnect.
+        attempts: usize,
     },
 }
 
@@ -75,27 +145,86 @@ where
 {
     token: Option<String>,
     configuration: Option<Configuration<F>>,
+    reconnect: Option<ReconnectConfig>,
+    heartbeat_timeout: Option<Duration>,
     _data: PhantomData<D>,
 }
 
 /// A stream of on-chain data.
-#[derive(Debug)]
 #[pin_project]
 pub struct DataStream<F, D>
 where
     F: Message + Default,
     D: Message + Default,
 {
-    stream_id: u64,
-    configuration_rx: Receiver<Configuration<F>>,
+    /// The id sent to the server by the most recent stream.
+    last_wire_id: u64,
+    /// Open streams, by the id sent to the server.
+    streams: BTreeMap<u64, OpenStream<F>>,
+    configuration_rx: Receiver<ClientMessage<F>>,
     #[pin]
     inner: Streaming<StreamDataResponse>,
     inner_tx: Sender<StreamDataRequest>,
+    reconnect: Option<ReconnectState>,
+    heartbeat_timeout: Duration,
+    heartbeat_deadline: Pin<Box<Sleep>>,
+    last_message_at: Option<Instant>,
+    last_heartbeat_at: Option<Instant>,
     _data: PhantomData<D>,
 }
 
+impl<F, D> std::fmt::Debug for DataStream<F, D>
+where
+    F: Message + Default,
+    D: Message + Default,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DataStream")
+            .field("streams", &self.streams.len())
+            .field("reconnect", &self.reconnect.is_some())
+            .field("heartbeat_timeout", &self.heartbeat_timeout)
+            .field("last_message_at", &self.last_message_at)
+            .finish_non_exhaustive()
+    }
+}
+
 /// A client used to control a data stream.
-pub type DataStreamClient<F> = Sender<Configuration<F>>;
+#[derive(Debug)]
+pub struct DataStreamClient<F: Message + Default> {
+    tx: Sender<ClientMessage<F>>,
+}
+
+#[derive(Debug)]
+enum ClientMessage<F: Message + Default> {
+    Configure(Configuration<F>),
+    Close(u64),
+    Credit { stream_id: u64, credits: u64 },
+}
+
+/// A stream configured by the client.
+struct OpenStream<F: Message + Default> {
+    /// The stream id chosen by the client.
+    stream_id: u64,
+    configuration: Configuration<F>,
+    /// The cursor of the last message received for the stream.
+    cursor: Option<Cursor>,
+    /// Number of messages the server can still send, if flow controlled.
+    credits: Option<u64>,
+}
+
+type InnerStream = (Streaming<StreamDataResponse>, Sender<StreamDataRequest>);
+
+type ConnectFuture = Pin<Box<dyn Future<Output = Result<InnerStream, DataStreamError>> + Send>>;
+
+/// Tracks what is needed to resume the stream after the connection is lost.
+struct ReconnectState {
+    config: ReconnectConfig,
+    url: Uri,
+    token: Option<String>,
+    /// Number of consecutive reconnection attempts.
+    attempts: usize,
+    connecting: Option<ConnectFuture>,
+}
 
 impl<F, D> ClientBuilder<F, D>
 where
@@ -114,6 +243,25 @@ where
         self
     }
 
+    /// Reconnect automatically when the connection to the server is lost.
+    ///
+    /// The stream resumes from the last cursor received and sends a
+    /// [DataMessage::Reconnected] message after reconnecting.
+    pub fn with_reconnect(mut self, config: ReconnectConfig) -> Self {
+        self.reconnect = Some(config);
+        self
+    }
+
+    /// Fail the stream if the server sends no message, including heartbeats,
+    /// for longer than `timeout`.
+    ///
+    /// If the stream reconnects automatically, it reconnects instead of failing.
+    /// Defaults to [DEFAULT_HEARTBEAT_TIMEOUT].
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = Some(timeout);
+        self
+    }
+
     /// Create and connect to the stream at the given url.
     ///
     /// If a configuration was provided, the client will immediately send it to the server upon
@@ -122,120 +270,515 @@ where
         self,
         url: Uri,
     ) -> Result<(DataStream<F, D>, DataStreamClient<F>), ClientBuilderError> {
-        let channel = Channel::builder(url).connect().await?;
-
-        let mut default_client =
-            StreamClient::with_interceptor(channel, move |mut req: tonic::Request<()>| {
-                if let Some(token) = self.token.clone() {
-                    let token: MetadataValue<_> = format!("Bearer {token}").parse().unwrap();
-                    req.metadata_mut().insert("authorization", token);
-                }
-                Ok(req)
-            });
+        let (inner_stream, inner_tx) =
+            open_stream::<ClientBuilderError>(url.clone(), self.token.clone(), Vec::default())
+                .await?;
 
         let (configuration_tx, configuration_rx) = mpsc::channel(128);
-        let (inner_tx, inner_rx) = mpsc::channel(128);
+        let client = DataStreamClient {
+            tx: configuration_tx,
+        };
 
         if let Some(configuration) = self.configuration {
-            configuration_tx.send(configuration).await.unwrap();
+            client.send(configuration).await.unwrap();
         }
 
-        let inner_stream = default_client
-            .stream_data(ReceiverStream::new(inner_rx))
-            .await?
-            .into_inner();
+        let reconnect = self.reconnect.map(|config| ReconnectState {
+            config,
+            url,
+            token: self.token,
+            attempts: 0,
+            connecting: None,
+        });
+
+        let heartbeat_timeout = self.heartbeat_timeout.unwrap_or(DEFAULT_HEARTBEAT_TIMEOUT);
 
         let stream = DataStream {
-            stream_id: 0,
+            last_wire_id: 0,
+            streams: BTreeMap::default(),
             configuration_rx,
             inner: inner_stream,
             inner_tx,
+            reconnect,
+            heartbeat_timeout,
+            heartbeat_deadline: Box::pin(tokio::time::sleep(heartbeat_timeout)),
+            last_message_at: None,
+            last_heartbeat_at: None,
             _data: PhantomData::default(),
         };
 
-        Ok((stream, configuration_tx))
+        Ok((stream, client))
     }
 }
 
-impl<F, D> Stream for DataStream<F, D>
+impl<F> DataStreamClient<F>
 where
     F: Message + Default,
+{
+    /// Configures the stream with the configuration stream id.
+    ///
+    /// The stream is started if it doesn't exist, or replaced otherwise.
+    pub async fn send(
+        &self,
+        configuration: Configuration<F>,
+    ) -> Result<(), SendError<Configuration<F>>> {
+        self.tx
+            .send(ClientMessage::Configure(configuration))
+            .await
+            .map_err(|SendError(message)| match message {
+                ClientMessage::Configure(configuration) => SendError(configuration),
+                ClientMessage::Close(_) | ClientMessage::Credit { .. } => {
+                    unreachable!("sent a configure message")
+                }
+            })
+    }
+
+    /// Stops the stream with the given id.
+    pub async fn close_stream(&self, stream_id: u64) -> Result<(), SendError<u64>> {
+        self.tx
+            .send(ClientMessage::Close(stream_id))
+            .await
+            .map_err(|_| SendError(stream_id))
+    }
+
+    /// Lets the stream with the given id receive `credits` more messages.
+    ///
+    /// Only streams configured with [Configuration::with_credits] wait for credits.
+    pub async fn grant_credits(&self, stream_id: u64, credits: u64) -> Result<(), SendError<u64>> {
+        self.tx
+            .send(ClientMessage::Credit { stream_id, credits })
+            .await
+            .map_err(|_| SendError(stream_id))
+    }
+}
+
+impl<F> Clone for DataStreamClient<F>
+where
+    F: Message + Default,
+{
+    fn clone(&self) -> Self {
+        DataStreamClient {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+/// Connects to the server and starts streaming data.
+///
+/// The given requests are sent to the server as soon as the stream starts.
+async fn open_stream<E>(
+    url: Uri,
+    token: Option<String>,
+    requests: Vec<StreamDataRequest>,
+) -> Result<InnerStream, E>
+where
+    E: From<tonic::transport::Error> + From<tonic::Status>,
+{
+    let channel = Channel::builder(url).connect().await?;
+
+    let mut default_client =
+        StreamClient::with_interceptor(channel, move |mut req: tonic::Request<()>| {
+            if let Some(token) = token.clone() {
+                let token: MetadataValue<_> = format!("Bearer {token}").parse().unwrap();
+                req.metadata_mut().insert("authorization", token);
+            }
+            Ok(req)
+        });
+
+    let (inner_tx, inner_rx) = mpsc::channel(requests.len().max(128));
+
+    for request in requests {
+        inner_tx
+            .try_send(request)
+            .expect("new channel has capacity");
+    }
+
+    let inner_stream = default_client
+        .stream_data(ReceiverStream::new(inner_rx))
+        .await?
+        .into_inner();
+
+    Ok((inner_stream, inner_tx))
+}
+
+/// Decodes the data received from the server, failing on the first invalid block.
+fn decode_batch<D: Message + Default>(data: Vec<Vec<u8>>) -> Result<Vec<D>, DataStreamError> {
+    data.into_iter()
+        .enumerate()
+        .map(|(index, b)| {
+            D::decode(b.as_slice()).map_err(|source| DataStreamError::Decode { index, source })
+        })
+        .collect()
+}
+
+/// Creates the request to send to the server for the given configuration.
+fn stream_data_request<F: Message + Default>(
+    stream_id: u64,
+    configuration: &Configuration<F>,
+    starting_cursor: Option<Cursor>,
+    initial_credits: Option<u64>,
+) -> StreamDataRequest {
+    StreamDataRequest {
+        stream_id: Some(stream_id),
+        batch_size: Some(configuration.batch_size),
+        starting_cursor,
+        finality: configuration.finality.map(|f| f as i32),
+        filter: configuration.filter.encode_to_vec(),
+        include_rejected: Some(configuration.include_rejected),
+        close: None,
+        initial_credits,
+        credits: None,
+    }
+}
+
+/// Creates the request that stops the given stream.
+fn close_stream_request(stream_id: u64) -> StreamDataRequest {
+    StreamDataRequest {
+        stream_id: Some(stream_id),
+        close: Some(true),
+        ..StreamDataRequest::default()
+    }
+}
+
+/// Creates the request that lets the given stream send more messages.
+fn credit_request(stream_id: u64, credits: u64) -> StreamDataRequest {
+    StreamDataRequest {
+        stream_id: Some(stream_id),
+        credits: Some(credits),
+        ..StreamDataRequest::default()
+    }
+}
+
+/// Returns the requests that resume the streams from their last cursor, with
+/// the credits they had left.
+fn resume_requests<F: Message + Default>(
+    streams: &BTreeMap<u64, OpenStream<F>>,
+) -> Vec<StreamDataRequest> {
+    streams
+        .iter()
+        .map(|(wire_id, stream)| {
+            stream_data_request(
+                *wire_id,
+                &stream.configuration,
+                stream.cursor.clone(),
+                stream.credits,
+            )
+        })
+        .collect()
+}
+
+impl<F: Message + Default> OpenStream<F> {
+    /// Tracks the credit used by the server to send a data or invalidate message.
+    fn use_credit(&mut self) {
+        if let Some(credits) = self.credits.as_mut() {
+            *credits = credits.saturating_sub(1);
+        }
+    }
+}
+
+impl ReconnectState {
+    /// Starts a new connection attempt that resumes the streams with the given
+    /// requests, returning `false` if the stream should give up.
+    fn start_reconnect(&mut self, requests: Vec<StreamDataRequest>) -> bool {
+        self.attempts += 1;
+        if !self.config.can_retry(self.attempts) {
+            return false;
+        }
+
+        let delay = self.config.delay(self.attempts);
+        warn!(attempt = self.attempts, delay = ?delay, "stream disconnected. reconnecting");
+
+        let url = self.url.clone();
+        let token = self.token.clone();
+        self.connecting = Some(Box::pin(async move {
+            tokio::time::sleep(delay).await;
+            open_stream::<DataStreamError>(url, token, requests).await
+        }));
+
+        true
+    }
+}
+
+impl<F, D> DataStream<F, D>
+where
+    F: Message + Default,
+    D: Message + Default,
+{
+    /// Returns when the last message, of any kind, was received from the server.
+    pub fn last_message_at(&self) -> Option<Instant> {
+        self.last_message_at
+    }
+
+    /// Returns when the last heartbeat was received from the server.
+    pub fn last_heartbeat_at(&self) -> Option<Instant> {
+        self.last_heartbeat_at
+    }
+
+    /// Returns the id sent to the server by the stream with the given id.
+    fn wire_id(&self, stream_id: u64) -> Option<u64> {
+        self.streams
+            .iter()
+            .find(|(_, stream)| stream.stream_id == stream_id)
+            .map(|(wire_id, _)| *wire_id)
+    }
+
+    fn configure_stream(&mut self, configuration: Configuration<F>) -> Result<(), DataStreamError> {
+        // responses of the replaced stream may still be in flight. use a new id
+        // so that they can be told apart and dropped.
+        self.close_stream(configuration.stream_id)?;
+        self.last_wire_id += 1;
+        let wire_id = self.last_wire_id;
+
+        let request = stream_data_request(
+            wire_id,
+            &configuration,
+            configuration.starting_cursor.clone(),
+            configuration.credits,
+        );
+        self.inner_tx.try_send(request)?;
+
+        let stream = OpenStream {
+            stream_id: configuration.stream_id,
+            cursor: configuration.starting_cursor.clone(),
+            credits: configuration.credits,
+            configuration,
+        };
+        self.streams.insert(wire_id, stream);
+        Ok(())
+    }
+
+    fn close_stream(&mut self, stream_id: u64) -> Result<(), DataStreamError> {
+        if let Some(wire_id) = self.wire_id(stream_id) {
+            self.streams.remove(&wire_id);
+            self.inner_tx.try_send(close_stream_request(wire_id))?;
+        }
+        Ok(())
+    }
+
+    fn grant_credits(&mut self, stream_id: u64, credits: u64) -> Result<(), DataStreamError> {
+        let wire_id = match self.wire_id(stream_id) {
+            None => return Ok(()),
+            Some(wire_id) => wire_id,
+        };
+        let stream = self
+            .streams
+            .get_mut(&wire_id)
+            .expect("wire id is in the map");
+        // streams that are not flow controlled don't wait for credits.
+        if let Some(current) = stream.credits.as_mut() {
+            *current = current.saturating_add(credits);
+            self.inner_tx.try_send(credit_request(wire_id, credits))?;
+        }
+        Ok(())
+    }
+
+    fn reset_heartbeat_deadline(&mut self) {
+        let deadline = tokio::time::Instant::now() + self.heartbeat_timeout;
+        self.heartbeat_deadline.as_mut().reset(deadline);
+    }
+}
+
+impl<F, D> Stream for DataStream<F, D>
+where
+    F: Message + Default + Clone,
     D: Message + Default,
 {
-    type Item = Result<DataMessage<D>, Box<dyn std::error::Error>>;
+    type Item = Result<DataMessage<D>, DataStreamError>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        match self.configuration_rx.poll_recv(cx) {
+        let this = &mut *self;
+
+        // while reconnecting, there is no stream to read from.
+        if let Some(reconnect) = this.reconnect.as_mut() {
+            if let Some(connecting) = reconnect.connecting.as_mut() {
+                match connecting.as_mut().poll(cx) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(Ok((inner, inner_tx))) => {
+                        reconnect.connecting = None;
+                        let cursors = this
+                            .streams
+                            .values()
+                            .map(|stream| (stream.stream_id, stream.cursor.clone()))
+                            .collect();
+                        let message = DataMessage::Reconnected {
+                            cursors,
+                            attempts: reconnect.attempts,
+                        };
+                        this.inner = inner;
+                        this.inner_tx = inner_tx;
+                        this.reset_heartbeat_deadline();
+                        return Poll::Ready(Some(Ok(message)));
+                    }
+                    Poll::Ready(Err(err)) => {
+                        reconnect.connecting = None;
+                        if !reconnect.start_reconnect(resume_requests(&this.streams)) {
+                            return Poll::Ready(Some(Err(err)));
+                        }
+                        cx.waker().wake_by_ref();
+                        return Poll::Pending;
+                    }
+                }
+            }
+        }
+
+        match this.configuration_rx.poll_recv(cx) {
             Poll::Ready(None) => return Poll::Ready(None),
-            Poll::Ready(Some(configuration)) => {
-                self.stream_id += 1;
-                let request = StreamDataRequest {
-                    stream_id: Some(self.stream_id),
-                    batch_size: Some(configuration.batch_size),
-                    starting_cursor: configuration.starting_cursor,
-                    finality: configuration.finality.map(|f| f as i32),
-                    filter: configuration.filter.encode_to_vec(),
+            Poll::Ready(Some(message)) => {
+                let result = match message {
+                    ClientMessage::Configure(configuration) => this.configure_stream(configuration),
+                    ClientMessage::Close(stream_id) => this.close_stream(stream_id),
+                    ClientMessage::Credit { stream_id, credits } => {
+                        this.grant_credits(stream_id, credits)
+                    }
                 };
-
-                self.inner_tx.try_send(request)?;
+                if let Err(err) = result {
+                    return Poll::Ready(Some(Err(err)));
+                }
                 cx.waker().wake_by_ref();
                 return Poll::Pending;
             }
             Poll::Pending => {}
         }
 
-        match Pin::new(&mut self.inner).poll_next(cx) {
-            Poll::Ready(None) => Poll::Ready(None),
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(Box::new(e)))),
+        let disconnected = match Pin::new(&mut this.inner).poll_next(cx) {
+            Poll::Pending => match this.heartbeat_deadline.as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(_) => {
+                    warn!(timeout = ?this.heartbeat_timeout, "heartbeat deadline elapsed");
+                    // fail again if the server stays silent.
+                    this.reset_heartbeat_deadline();
+                    Some(DataStreamError::HeartbeatTimeout(this.heartbeat_timeout))
+                }
+            },
+            Poll::Ready(None) => None,
+            Poll::Ready(Some(Err(status))) => Some(status.into()),
             Poll::Ready(Some(Ok(response))) => {
-                if response.stream_id != self.stream_id {
+                if let Some(reconnect) = this.reconnect.as_mut() {
+                    reconnect.attempts = 0;
+                }
+
+                // any message shows the connection is alive, including
+                // heartbeats that are not tied to a stream id.
+                let now = Instant::now();
+                this.last_message_at = Some(now);
+                this.reset_heartbeat_deadline();
+
+                if let Some(stream_data_response::Message::Heartbeat(_)) = response.message {
+                    debug!("received heartbeat");
+                    this.last_heartbeat_at = Some(now);
                     cx.waker().wake_by_ref();
                     return Poll::Pending;
                 }
 
-                match response.message {
+                // drop responses of streams that were closed or replaced.
+                let stream = match this.streams.get_mut(&response.stream_id) {
+                    Some(stream) => stream,
                     None => {
                         cx.waker().wake_by_ref();
-                        Poll::Pending
+                        return Poll::Pending;
+                    }
+                };
+
+                match response.message {
+                    None | Some(stream_data_response::Message::Heartbeat(_)) => {
+                        cx.waker().wake_by_ref();
+                        return Poll::Pending;
                     }
                     Some(stream_data_response::Message::Data(data)) => {
-                        let batch = data
-                            .data
-                            .into_iter()
-                            .map(|b| D::decode(b.as_slice()))
-                            .filter_map(|b| b.ok())
-                            .collect::<Vec<D>>();
+                        let batch = match decode_batch(data.data) {
+                            Ok(batch) => batch,
+                            Err(err) => return Poll::Ready(Some(Err(err))),
+                        };
+                        stream.use_credit();
+                        let end_cursor = data.end_cursor.unwrap_or_default();
+                        let finality = DataFinality::from_i32(data.finality).unwrap_or_default();
+                        // the pending block has no hash and is sent again once accepted,
+                        // so resume from the last accepted or finalized batch.
+                        if finality != DataFinality::DataStatusPending {
+                            stream.cursor = Some(end_cursor.clone());
+                        }
                         let message = DataMessage::Data {
+                            stream_id: stream.stream_id,
                             cursor: data.cursor,
-                            end_cursor: data.end_cursor.unwrap_or_default(),
-                            finality: DataFinality::from_i32(data.finality).unwrap_or_default(),
+                            end_cursor,
+                            finality,
                             batch,
                         };
-                        Poll::Ready(Some(Ok(message)))
+                        return Poll::Ready(Some(Ok(message)));
                     }
                     Some(stream_data_response::Message::Invalidate(invalidate)) => {
+                        let rejected = match decode_batch(invalidate.rejected_data) {
+                            Ok(rejected) => rejected,
+                            Err(err) => return Poll::Ready(Some(Err(err))),
+                        };
+                        stream.use_credit();
+                        stream.cursor = invalidate.cursor.clone();
                         let message = DataMessage::Invalidate {
+                            stream_id: stream.stream_id,
                             cursor: invalidate.cursor,
+                            rejected,
                         };
-                        Poll::Ready(Some(Ok(message)))
+                        return Poll::Ready(Some(Ok(message)));
                     }
-                    Some(stream_data_response::Message::Heartbeat(_)) => {
-                        debug!("received heartbeat");
-                        cx.waker().wake_by_ref();
-                        Poll::Pending
+                    Some(stream_data_response::Message::Error(error)) => {
+                        // the server closed the stream, don't resume it after reconnecting.
+                        let message = DataMessage::Error {
+                            stream_id: stream.stream_id,
+                            message: error.message,
+                        };
+                        this.streams.remove(&response.stream_id);
+                        return Poll::Ready(Some(Ok(message)));
                     }
                 }
             }
+        };
+
+        // the connection was lost, reconnect if the error is not permanent.
+        let can_reconnect = match &disconnected {
+            None | Some(DataStreamError::HeartbeatTimeout(_)) => true,
+            Some(DataStreamError::Status(status)) => is_transient(status),
+            Some(_) => false,
+        };
+        let reconnect = match this.reconnect.as_mut() {
+            Some(reconnect) if can_reconnect => reconnect,
+            _ => return Poll::Ready(disconnected.map(Err)),
+        };
+
+        if !reconnect.start_reconnect(resume_requests(&this.streams)) {
+            return Poll::Ready(disconnected.map(Err));
         }
+        cx.waker().wake_by_ref();
+        Poll::Pending
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{ClientBuilder, Configuration, Uri};
-    use apibara_core::starknet::v1alpha2::{Block, Filter, HeaderFilter};
+    use std::time::Duration;
+
+    use crate::{
+        test_server::{
+            cursor, data, error, heartbeat, pending_data, raw_data, ServerEvent, TestServer,
+        },
+        ClientBuilder, Configuration, DataMessage, DataStreamError, ReconnectConfig, Uri,
+    };
+    use apibara_core::starknet::v1alpha2::{Block, BlockHeader, Filter, HeaderFilter};
     use futures_util::{StreamExt, TryStreamExt};
+    use prost::Message;
+    use tonic::{Code, Status};
+
+    fn reconnect_config() -> ReconnectConfig {
+        ReconnectConfig::default()
+            .with_initial_interval(Duration::from_millis(10))
+            .with_max_interval(Duration::from_millis(50))
+    }
+
+    fn end_block(message: &DataMessage<Block>) -> u64 {
+        match message {
+            DataMessage::Data { end_cursor, .. } => end_cursor.order_key,
+            _ => panic!("expected data, got {message:?}"),
+        }
+    }
 
     #[tokio::test]
     async fn test_apibara_high_level_api() -> Result<(), Box<dyn std::error::Error>> {
@@ -260,4 +803,399 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_reconnect_from_last_cursor() {
+        let server = TestServer::start(vec![
+            vec![
+                data(1, 10),
+                data(1, 20),
+                ServerEvent::Fail(Status::unavailable("connection lost")),
+            ],
+            vec![data(1, 30)],
+        ])
+        .await;
+
+        let configuration = Configuration::<Filter>::default().with_starting_block(5);
+        let (mut stream, _client) = ClientBuilder::<Filter, Block>::default()
+            .with_configuration(configuration)
+            .with_reconnect(reconnect_config())
+            .connect(server.uri())
+            .await
+            .unwrap();
+
+        assert_eq!(end_block(&stream.try_next().await.unwrap().unwrap()), 10);
+        assert_eq!(end_block(&stream.try_next().await.unwrap().unwrap()), 20);
+        match stream.try_next().await.unwrap().unwrap() {
+            DataMessage::Reconnected { cursors, attempts } => {
+                assert_eq!(cursors.get(&0), Some(&Some(cursor(20))));
+                assert_eq!(attempts, 1);
+            }
+            message => panic!("expected reconnected, got {message:?}"),
+        }
+        assert_eq!(end_block(&stream.try_next().await.unwrap().unwrap()), 30);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].starting_cursor, Some(cursor(5)));
+        assert_eq!(requests[1].starting_cursor, Some(cursor(20)));
+        assert_eq!(requests[1].stream_id, requests[0].stream_id);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_after_pending_data() {
+        let server = TestServer::start(vec![
+            vec![
+                data(1, 20),
+                pending_data(1, 21),
+                ServerEvent::Fail(Status::unavailable("connection lost")),
+            ],
+            vec![data(1, 21)],
+        ])
+        .await;
+
+        let configuration = Configuration::<Filter>::default().with_starting_block(5);
+        let (mut stream, _client) = ClientBuilder::<Filter, Block>::default()
+            .with_configuration(configuration)
+            .with_reconnect(reconnect_config())
+            .connect(server.uri())
+            .await
+            .unwrap();
+
+        assert_eq!(end_block(&stream.try_next().await.unwrap().unwrap()), 20);
+        assert_eq!(end_block(&stream.try_next().await.unwrap().unwrap()), 21);
+        match stream.try_next().await.unwrap().unwrap() {
+            DataMessage::Reconnected { cursors, .. } => {
+                assert_eq!(cursors.get(&0), Some(&Some(cursor(20))));
+            }
+            message => panic!("expected reconnected, got {message:?}"),
+        }
+        assert_eq!(end_block(&stream.try_next().await.unwrap().unwrap()), 21);
+
+        // the pending block is requested again, now that it's accepted.
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].starting_cursor, Some(cursor(20)));
+    }
+
+    #[tokio::test]
+    async fn test_grant_credits() {
+        let server = TestServer::start(vec![
+            vec![
+                data(1, 10),
+                ServerEvent::Fail(Status::unavailable("connection lost")),
+            ],
+            vec![ServerEvent::Sleep(Duration::from_millis(100)), data(1, 11)],
+        ])
+        .await;
+
+        let configuration = Configuration::<Filter>::default().with_credits(3);
+        let (mut stream, client) = ClientBuilder::<Filter, Block>::default()
+            .with_configuration(configuration)
+            .with_reconnect(reconnect_config())
+            .connect(server.uri())
+            .await
+            .unwrap();
+
+        assert_eq!(end_block(&stream.try_next().await.unwrap().unwrap()), 10);
+        match stream.try_next().await.unwrap().unwrap() {
+            DataMessage::Reconnected { .. } => {}
+            message => panic!("expected reconnected, got {message:?}"),
+        }
+
+        client.grant_credits(0, 5).await.unwrap();
+        assert_eq!(end_block(&stream.try_next().await.unwrap().unwrap()), 11);
+
+        let requests = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let requests = server.requests();
+                if requests.len() >= 3 {
+                    return requests;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(requests[0].initial_credits, Some(3));
+        // the stream resumes with the credits it had left.
+        assert_eq!(requests[1].initial_credits, Some(2));
+        assert_eq!(requests[2].stream_id, requests[1].stream_id);
+        assert_eq!(requests[2].credits, Some(5));
+    }
+
+    #[tokio::test]
+    async fn test_give_up_reconnecting() {
+        let server = TestServer::start(vec![vec![ServerEvent::Fail(Status::unavailable(
+            "connection lost",
+        ))]])
+        .await;
+
+        let (mut stream, _client) = ClientBuilder::<Filter, Block>::default()
+            .with_configuration(Configuration::default())
+            .with_reconnect(reconnect_config().with_max_retries(2))
+            .connect(server.uri())
+            .await
+            .unwrap();
+
+        // the server refuses all new connections.
+        match stream.try_next().await {
+            Err(DataStreamError::Status(status)) => assert_eq!(status.code(), Code::Unavailable),
+            result => panic!("expected status error, got {result:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_do_not_reconnect_on_permanent_errors() {
+        let server = TestServer::start(vec![
+            vec![ServerEvent::Fail(Status::invalid_argument(
+                "invalid filter",
+            ))],
+            vec![data(1, 10)],
+        ])
+        .await;
+
+        let (mut stream, _client) = ClientBuilder::<Filter, Block>::default()
+            .with_configuration(Configuration::default())
+            .with_reconnect(reconnect_config())
+            .connect(server.uri())
+            .await
+            .unwrap();
+
+        match stream.try_next().await {
+            Err(DataStreamError::Status(status)) => {
+                assert_eq!(status.code(), Code::InvalidArgument)
+            }
+            result => panic!("expected status error, got {result:?}"),
+        }
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fail_without_reconnect() {
+        let server = TestServer::start(vec![
+            vec![ServerEvent::Fail(Status::unavailable("connection lost"))],
+            vec![data(1, 10)],
+        ])
+        .await;
+
+        let (mut stream, _client) = ClientBuilder::<Filter, Block>::default()
+            .with_configuration(Configuration::default())
+            .connect(server.uri())
+            .await
+            .unwrap();
+
+        assert!(stream.try_next().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_surface_decode_errors() {
+        let block = Block {
+            header: Some(BlockHeader {
+                block_number: 10,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let server = TestServer::start(vec![vec![
+            raw_data(1, 10, vec![block.encode_to_vec()]),
+            raw_data(1, 11, vec![block.encode_to_vec(), vec![0xff]]),
+        ]])
+        .await;
+
+        let (mut stream, _client) = ClientBuilder::<Filter, Block>::default()
+            .with_configuration(Configuration::default())
+            .connect(server.uri())
+            .await
+            .unwrap();
+
+        // the stream can be moved to another task.
+        let handle = tokio::spawn(async move {
+            let first = stream.try_next().await;
+            let second = stream.try_next().await;
+            (first, second)
+        });
+        let (first, second) = handle.await.unwrap();
+
+        match first.unwrap().unwrap() {
+            DataMessage::Data { batch, .. } => assert_eq!(batch, vec![block]),
+            message => panic!("expected data, got {message:?}"),
+        }
+        match second {
+            Err(DataStreamError::Decode { index, .. }) => assert_eq!(index, 1),
+            result => panic!("expected decode error, got {result:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_heartbeats_keep_stream_alive() {
+        let wait = ServerEvent::Sleep(Duration::from_millis(60));
+        let server = TestServer::start(vec![vec![
+            heartbeat(),
+            wait.clone(),
+            heartbeat(),
+            wait.clone(),
+            heartbeat(),
+            wait,
+            data(1, 10),
+        ]])
+        .await;
+
+        let (mut stream, _client) = ClientBuilder::<Filter, Block>::default()
+            .with_configuration(Configuration::default())
+            .with_heartbeat_timeout(Duration::from_millis(100))
+            .connect(server.uri())
+            .await
+            .unwrap();
+
+        assert!(stream.last_message_at().is_none());
+        assert_eq!(end_block(&stream.try_next().await.unwrap().unwrap()), 10);
+        let last_heartbeat_at = stream.last_heartbeat_at().unwrap();
+        assert!(stream.last_message_at().unwrap() > last_heartbeat_at);
+    }
+
+    #[tokio::test]
+    async fn test_fail_on_heartbeat_timeout() {
+        let server = TestServer::start(vec![vec![data(1, 10)]]).await;
+
+        let (mut stream, _client) = ClientBuilder::<Filter, Block>::default()
+            .with_configuration(Configuration::default())
+            .with_heartbeat_timeout(Duration::from_millis(100))
+            .connect(server.uri())
+            .await
+            .unwrap();
+
+        assert_eq!(end_block(&stream.try_next().await.unwrap().unwrap()), 10);
+        match stream.try_next().await {
+            Err(DataStreamError::HeartbeatTimeout(timeout)) => {
+                assert_eq!(timeout, Duration::from_millis(100))
+            }
+            result => panic!("expected heartbeat timeout, got {result:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_on_heartbeat_timeout() {
+        let server = TestServer::start(vec![vec![data(1, 10)], vec![data(1, 20)]]).await;
+
+        let (mut stream, _client) = ClientBuilder::<Filter, Block>::default()
+            .with_configuration(Configuration::default())
+            .with_heartbeat_timeout(Duration::from_millis(100))
+            .with_reconnect(reconnect_config())
+            .connect(server.uri())
+            .await
+            .unwrap();
+
+        assert_eq!(end_block(&stream.try_next().await.unwrap().unwrap()), 10);
+        match stream.try_next().await.unwrap().unwrap() {
+            DataMessage::Reconnected { cursors, .. } => {
+                assert_eq!(cursors.get(&0), Some(&Some(cursor(10))))
+            }
+            message => panic!("expected reconnected, got {message:?}"),
+        }
+        assert_eq!(end_block(&stream.try_next().await.unwrap().unwrap()), 20);
+    }
+
+    #[tokio::test]
+    async fn test_multiple_streams_per_connection() {
+        let server = TestServer::start(vec![vec![
+            data(1, 5),
+            data(2, 7),
+            ServerEvent::Sleep(Duration::from_millis(100)),
+            data(1, 6),
+            data(2, 8),
+        ]])
+        .await;
+
+        let (mut stream, client) = ClientBuilder::<Filter, Block>::default()
+            .with_configuration(Configuration::default().with_stream_id(10))
+            .connect(server.uri())
+            .await
+            .unwrap();
+        client
+            .send(Configuration::default().with_stream_id(20))
+            .await
+            .unwrap();
+
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            match stream.try_next().await.unwrap().unwrap() {
+                DataMessage::Data {
+                    stream_id,
+                    end_cursor,
+                    ..
+                } => received.push((stream_id, end_cursor.order_key)),
+                message => panic!("expected data, got {message:?}"),
+            }
+        }
+        assert_eq!(received, vec![(10, 5), (20, 7)]);
+
+        // data sent for a closed stream is dropped.
+        client.close_stream(10).await.unwrap();
+        match stream.try_next().await.unwrap().unwrap() {
+            DataMessage::Data {
+                stream_id,
+                end_cursor,
+                ..
+            } => assert_eq!((stream_id, end_cursor.order_key), (20, 8)),
+            message => panic!("expected data, got {message:?}"),
+        }
+
+        // the server records the requests received while replaying the script
+        // only after it's done.
+        let requests = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let requests = server.requests();
+                if requests.len() >= 3 {
+                    return requests;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].stream_id, Some(1));
+        assert_eq!(requests[1].stream_id, Some(2));
+        assert_eq!(requests[2].stream_id, Some(1));
+        assert_eq!(requests[2].close, Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_stream_error_closes_stream() {
+        let server = TestServer::start(vec![vec![
+            error(1, "cursor not found"),
+            data(1, 5),
+            data(2, 7),
+        ]])
+        .await;
+
+        let (mut stream, client) = ClientBuilder::<Filter, Block>::default()
+            .with_configuration(Configuration::default().with_stream_id(10))
+            .connect(server.uri())
+            .await
+            .unwrap();
+        client
+            .send(Configuration::default().with_stream_id(20))
+            .await
+            .unwrap();
+
+        match stream.try_next().await.unwrap().unwrap() {
+            DataMessage::Error { stream_id, message } => {
+                assert_eq!(stream_id, 10);
+                assert_eq!(message, "cursor not found");
+            }
+            message => panic!("expected error, got {message:?}"),
+        }
+
+        // the other stream keeps receiving data.
+        match stream.try_next().await.unwrap().unwrap() {
+            DataMessage::Data {
+                stream_id,
+                end_cursor,
+                ..
+            } => assert_eq!((stream_id, end_cursor.order_key), (20, 7)),
+            message => panic!("expected data, got {message:?}"),
+        }
+    }
 }
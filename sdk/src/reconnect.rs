@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use tonic::Code;
+
+/// Automatic reconnection configuration.
+#[derive(Debug, Clone)]
+pub struct ReconnectConfig {
+    /// Delay before the first reconnection attempt.
+    pub initial_interval: Duration,
+    /// Maximum delay between reconnection attempts.
+    pub max_interval: Duration,
+    /// Maximum number of consecutive reconnection attempts.
+    ///
+    /// If `None`, the stream tries to reconnect forever.
+    pub max_retries: Option<usize>,
+}
+
+impl ReconnectConfig {
+    /// Set the delay before the first reconnection attempt.
+    pub fn with_initial_interval(mut self, interval: Duration) -> Self {
+        self.initial_interval = interval;
+        self
+    }
+
+    /// Set the maximum delay between reconnection attempts.
+    pub fn with_max_interval(mut self, interval: Duration) -> Self {
+        self.max_interval = interval;
+        self
+    }
+
+    /// Set the maximum number of consecutive reconnection attempts.
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
+    /// Returns true if the stream can try to reconnect once more.
+    pub(crate) fn can_retry(&self, attempt: usize) -> bool {
+        self.max_retries.map(|max| attempt <= max).unwrap_or(true)
+    }
+
+    /// Returns the delay before the given reconnection attempt (starting at 1).
+    ///
+    /// The delay doubles at each attempt, up to `max_interval`.
+    pub(crate) fn delay(&self, attempt: usize) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16) as u32;
+        self.initial_interval
+            .saturating_mul(2u32.pow(exponent))
+            .min(self.max_interval)
+    }
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_interval: Duration::from_millis(500),
+            max_interval: Duration::from_secs(30),
+            max_retries: None,
+        }
+    }
+}
+
+/// Returns true if the stream can recover from the given error by reconnecting.
+pub(crate) fn is_transient(status: &tonic::Status) -> bool {
+    matches!(
+        status.code(),
+        Code::Unknown
+            | Code::Unavailable
+            | Code::Internal
+            | Code::Aborted
+            | Code::Cancelled
+            | Code::DeadlineExceeded
+            | Code::ResourceExhausted
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ReconnectConfig;
+
+    #[test]
+    fn test_exponential_delay() {
+        let config = ReconnectConfig::default()
+            .with_initial_interval(Duration::from_secs(1))
+            .with_max_interval(Duration::from_secs(5));
+        assert_eq!(config.delay(1), Duration::from_secs(1));
+        assert_eq!(config.delay(2), Duration::from_secs(2));
+        assert_eq!(config.delay(3), Duration::from_secs(4));
+        assert_eq!(config.delay(4), Duration::from_secs(5));
+        assert_eq!(config.delay(100), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_max_retries() {
+        let config = ReconnectConfig::default();
+        assert!(config.can_retry(1_000));
+
+        let config = config.with_max_retries(2);
+        assert!(config.can_retry(1));
+        assert!(config.can_retry(2));
+        assert!(!config.can_retry(3));
+    }
+}
@@ -0,0 +1,335 @@
+//! Store records in a JSON Lines file.
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use apibara_core::node::v1alpha2::Cursor;
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::indexer::Record;
+
+use super::Sink;
+
+static RECORDS_FILE: &str = "records.jsonl";
+static CURSOR_FILE: &str = "cursor.json";
+
+/// A [Sink] that appends records to a JSON Lines file.
+///
+/// The sink stores two files in its directory: `records.jsonl`, with one
+/// record per line, and `cursor.json`, with the cursor of the last batch.
+/// The cursor is updated after the records are written, and records
+/// written after the cursor, including a partially written last line, are
+/// discarded when the sink is opened.
+pub struct JsonlSink<T> {
+    records_path: PathBuf,
+    cursor_path: PathBuf,
+    _item: PhantomData<fn(T)>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum JsonlSinkError {
+    #[error("I/O error")]
+    Io(#[from] io::Error),
+    #[error("Failed to serialize or deserialize json")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid cursor unique key")]
+    InvalidCursor(#[from] hex::FromHexError),
+}
+
+#[derive(Serialize)]
+struct RecordLine<'a, T> {
+    order_key: u64,
+    value: &'a T,
+}
+
+#[derive(Deserialize)]
+struct RecordKey {
+    order_key: u64,
+}
+
+#[derive(Deserialize)]
+struct OwnedRecordLine<T> {
+    order_key: u64,
+    value: T,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredCursor {
+    order_key: u64,
+    unique_key: String,
+}
+
+impl<T> JsonlSink<T>
+where
+    T: Serialize,
+{
+    /// Opens the sink in the given directory, creating it if it doesn't exist.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, JsonlSinkError> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let sink = JsonlSink {
+            records_path: dir.join(RECORDS_FILE),
+            cursor_path: dir.join(CURSOR_FILE),
+            _item: PhantomData::default(),
+        };
+
+        // records written after the cursor belong to a batch that was not
+        // completely written.
+        let cursor = sink.read_cursor()?;
+        sink.retain_until(cursor.as_ref())?;
+
+        Ok(sink)
+    }
+
+    fn read_cursor(&self) -> Result<Option<Cursor>, JsonlSinkError> {
+        let content = match fs::read(&self.cursor_path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let cursor: StoredCursor = serde_json::from_slice(&content)?;
+        Ok(Some(Cursor {
+            order_key: cursor.order_key,
+            unique_key: hex::decode(cursor.unique_key)?,
+        }))
+    }
+
+    fn write_cursor(&self, cursor: Option<&Cursor>) -> Result<(), JsonlSinkError> {
+        let cursor = match cursor {
+            None => return remove_if_exists(&self.cursor_path),
+            Some(cursor) => StoredCursor {
+                order_key: cursor.order_key,
+                unique_key: hex::encode(&cursor.unique_key),
+            },
+        };
+        let content = serde_json::to_vec(&cursor)?;
+        replace_file(&self.cursor_path, |file| file.write_all(&content))
+    }
+
+    /// Removes all records produced after the given cursor.
+    fn retain_until(&self, cursor: Option<&Cursor>) -> Result<(), JsonlSinkError> {
+        let file = match File::open(&self.records_path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut lines = BufReader::new(file).lines().peekable();
+        let mut retained = Vec::new();
+        while let Some(line) = lines.next() {
+            // the process stopped while writing the last line, possibly in the
+            // middle of a multi-byte character.
+            let is_last = lines.peek().is_none();
+            let line = match line {
+                Ok(line) => line,
+                Err(err) if is_last && err.kind() == io::ErrorKind::InvalidData => break,
+                Err(err) => return Err(err.into()),
+            };
+            let record: RecordKey = match serde_json::from_str(&line) {
+                Ok(record) => record,
+                Err(_) if is_last => break,
+                Err(err) => return Err(err.into()),
+            };
+            if cursor
+                .map(|c| record.order_key <= c.order_key)
+                .unwrap_or(false)
+            {
+                retained.push(line);
+            }
+        }
+
+        replace_file(&self.records_path, |file| {
+            for line in &retained {
+                writeln!(file, "{line}")?;
+            }
+            Ok(())
+        })
+    }
+
+    fn append_records(&self, records: &[Record<T>]) -> Result<(), JsonlSinkError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.records_path)?;
+        let mut writer = BufWriter::new(file);
+        for record in records {
+            let line = RecordLine {
+                order_key: record.order_key,
+                value: &record.value,
+            };
+            serde_json::to_writer(&mut writer, &line)?;
+            writer.write_all(b"\n")?;
+        }
+        let file = writer.into_inner().map_err(|err| err.into_error())?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
+
+impl<T> JsonlSink<T>
+where
+    T: DeserializeOwned,
+{
+    /// Returns all records stored in the sink.
+    pub fn records(&self) -> Result<Vec<Record<T>>, JsonlSinkError> {
+        let file = match File::open(&self.records_path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::default()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut records = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let record: OwnedRecordLine<T> = serde_json::from_str(&line?)?;
+            records.push(Record::new(record.order_key, record.value));
+        }
+        Ok(records)
+    }
+}
+
+#[async_trait]
+impl<T> Sink for JsonlSink<T>
+where
+    T: Serialize + Send,
+{
+    type Item = T;
+    type Error = JsonlSinkError;
+
+    async fn cursor(&mut self) -> Result<Option<Cursor>, Self::Error> {
+        self.read_cursor()
+    }
+
+    async fn write(
+        &mut self,
+        end_cursor: &Cursor,
+        records: Vec<Record<Self::Item>>,
+    ) -> Result<(), Self::Error> {
+        self.append_records(&records)?;
+        self.write_cursor(Some(end_cursor))
+    }
+
+    async fn invalidate(&mut self, cursor: Option<&Cursor>) -> Result<(), Self::Error> {
+        // update the cursor first so that, if the process stops before the
+        // records are removed, they are removed when the sink is opened.
+        self.write_cursor(cursor)?;
+        self.retain_until(cursor)
+    }
+}
+
+/// Atomically replaces the content of the file at `path`.
+fn replace_file<F>(path: &Path, write: F) -> Result<(), JsonlSinkError>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let tmp_path = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    write(&mut writer)?;
+    let file = writer.into_inner().map_err(|err| err.into_error())?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), JsonlSinkError> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, io::Write};
+
+    use apibara_core::node::v1alpha2::Cursor;
+
+    use crate::{indexer::Record, sink::Sink};
+
+    use super::JsonlSink;
+
+    fn cursor(block: u64) -> Cursor {
+        Cursor {
+            order_key: block,
+            unique_key: vec![0xab, block as u8],
+        }
+    }
+
+    fn records(start_block: u64, end_block: u64) -> Vec<Record<String>> {
+        (start_block..=end_block)
+            .map(|block| Record::new(block, format!("block {block}")))
+            .collect()
+    }
+
+    fn order_keys(sink: &JsonlSink<String>) -> Vec<u64> {
+        sink.records()
+            .unwrap()
+            .iter()
+            .map(|record| record.order_key)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_write_and_invalidate() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = JsonlSink::<String>::open(dir.path()).unwrap();
+        assert_eq!(sink.cursor().await.unwrap(), None);
+
+        sink.write(&cursor(5), records(1, 5)).await.unwrap();
+        sink.write(&cursor(10), records(6, 10)).await.unwrap();
+        assert_eq!(sink.cursor().await.unwrap(), Some(cursor(10)));
+        assert_eq!(sink.records().unwrap(), records(1, 10));
+
+        sink.invalidate(Some(&cursor(7))).await.unwrap();
+        assert_eq!(sink.cursor().await.unwrap(), Some(cursor(7)));
+        assert_eq!(order_keys(&sink), (1..=7).collect::<Vec<_>>());
+
+        sink.invalidate(None).await.unwrap();
+        assert_eq!(sink.cursor().await.unwrap(), None);
+        assert!(sink.records().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_discard_records_after_cursor_on_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = JsonlSink::<String>::open(dir.path()).unwrap();
+        sink.write(&cursor(5), records(1, 5)).await.unwrap();
+
+        // simulate a crash after the records were written but before the cursor was updated.
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("records.jsonl"))
+            .unwrap();
+        writeln!(file, r#"{{"order_key":6,"value":"block 6"}}"#).unwrap();
+        drop(sink);
+
+        let mut sink = JsonlSink::<String>::open(dir.path()).unwrap();
+        assert_eq!(sink.cursor().await.unwrap(), Some(cursor(5)));
+        assert_eq!(sink.records().unwrap(), records(1, 5));
+    }
+
+    #[tokio::test]
+    async fn test_discard_partially_written_line_on_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = JsonlSink::<String>::open(dir.path()).unwrap();
+        sink.write(&cursor(5), records(1, 5)).await.unwrap();
+
+        // simulate a crash while the last record was written.
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("records.jsonl"))
+            .unwrap();
+        write!(file, r#"{{"order_key":6,"val"#).unwrap();
+        drop(sink);
+
+        let mut sink = JsonlSink::<String>::open(dir.path()).unwrap();
+        assert_eq!(sink.records().unwrap(), records(1, 5));
+
+        sink.write(&cursor(6), records(6, 6)).await.unwrap();
+        assert_eq!(sink.records().unwrap(), records(1, 6));
+    }
+}
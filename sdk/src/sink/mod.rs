@@ -0,0 +1,39 @@
+//! Persist indexer records.
+mod jsonl;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use apibara_core::node::v1alpha2::Cursor;
+use async_trait::async_trait;
+
+use crate::indexer::Record;
+
+pub use self::jsonl::{JsonlSink, JsonlSinkError};
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::{SqliteRecord, SqliteSink, SqliteSinkError};
+
+/// Stores the records produced by an indexer, together with the stream cursor.
+///
+/// Implementations must update records and cursor atomically, so that
+/// the stream can resume from the cursor after a crash.
+#[async_trait]
+pub trait Sink: Send {
+    /// The values stored by the sink.
+    type Item: Send;
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Returns the cursor of the last batch written to the sink.
+    async fn cursor(&mut self) -> Result<Option<Cursor>, Self::Error>;
+
+    /// Writes the records of the batch ending at `end_cursor`.
+    async fn write(
+        &mut self,
+        end_cursor: &Cursor,
+        records: Vec<Record<Self::Item>>,
+    ) -> Result<(), Self::Error>;
+
+    /// Removes all records produced by blocks after `cursor`.
+    ///
+    /// If `cursor` is `None`, removes all records.
+    async fn invalidate(&mut self, cursor: Option<&Cursor>) -> Result<(), Self::Error>;
+}
@@ -0,0 +1,268 @@
+//! Store records in a SQLite database.
+use std::{
+    marker::PhantomData,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use apibara_core::node::v1alpha2::Cursor;
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+
+use crate::indexer::Record;
+
+use super::Sink;
+
+/// A value that can be stored in a [SqliteSink].
+///
+/// Records are stored in tables managed by the implementation, and must
+/// keep track of the order key of the block that produced them to
+/// support chain reorganizations.
+pub trait SqliteRecord: Send + 'static {
+    /// Creates the tables used to store the records, if they don't exist.
+    fn create_tables(conn: &Connection) -> rusqlite::Result<()>;
+
+    /// Inserts the record produced by the block with the given order key.
+    fn insert(&self, txn: &Transaction, order_key: u64) -> rusqlite::Result<()>;
+
+    /// Deletes all records produced by blocks after the given order key.
+    ///
+    /// If `order_key` is `None`, deletes all records.
+    fn delete_after(txn: &Transaction, order_key: Option<u64>) -> rusqlite::Result<()>;
+}
+
+/// A [Sink] that stores records in a SQLite database.
+///
+/// Records and cursor are written in the same database transaction.
+/// Database operations run on the tokio blocking thread pool, so that they
+/// don't block the async runtime.
+pub struct SqliteSink<T> {
+    conn: Arc<Mutex<Connection>>,
+    _record: PhantomData<fn(T)>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SqliteSinkError {
+    #[error("SQLite error")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("SQLite task failed")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+impl<T> SqliteSink<T>
+where
+    T: SqliteRecord,
+{
+    /// Opens the database at the given path, creating it if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, SqliteSinkError> {
+        let conn = Connection::open(path)?;
+        Self::from_connection(conn)
+    }
+
+    /// Opens a new in-memory database.
+    pub fn open_in_memory() -> Result<Self, SqliteSinkError> {
+        let conn = Connection::open_in_memory()?;
+        Self::from_connection(conn)
+    }
+
+    /// Creates a new sink that uses the given connection.
+    pub fn from_connection(conn: Connection) -> Result<Self, SqliteSinkError> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS apibara_cursor (
+                id INTEGER PRIMARY KEY CHECK (id = 0),
+                order_key INTEGER NOT NULL,
+                unique_key BLOB NOT NULL
+            )",
+            [],
+        )?;
+        T::create_tables(&conn)?;
+        Ok(SqliteSink {
+            conn: Arc::new(Mutex::new(conn)),
+            _record: PhantomData::default(),
+        })
+    }
+
+    /// Returns the database connection, used to query the records.
+    pub fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap()
+    }
+
+    /// Runs `f` with the database connection on the blocking thread pool.
+    async fn with_connection<R, F>(&self, f: F) -> Result<R, SqliteSinkError>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<R> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap();
+            f(&mut conn)
+        })
+        .await?;
+        Ok(result?)
+    }
+}
+
+fn write_cursor(txn: &Transaction, cursor: Option<&Cursor>) -> rusqlite::Result<()> {
+    match cursor {
+        None => txn.execute("DELETE FROM apibara_cursor", [])?,
+        Some(cursor) => txn.execute(
+            "INSERT OR REPLACE INTO apibara_cursor (id, order_key, unique_key) VALUES (0, ?1, ?2)",
+            params![cursor.order_key as i64, cursor.unique_key],
+        )?,
+    };
+    Ok(())
+}
+
+#[async_trait]
+impl<T> Sink for SqliteSink<T>
+where
+    T: SqliteRecord,
+{
+    type Item = T;
+    type Error = SqliteSinkError;
+
+    async fn cursor(&mut self) -> Result<Option<Cursor>, Self::Error> {
+        self.with_connection(|conn| {
+            conn.query_row(
+                "SELECT order_key, unique_key FROM apibara_cursor WHERE id = 0",
+                [],
+                |row| {
+                    Ok(Cursor {
+                        order_key: row.get::<_, i64>(0)? as u64,
+                        unique_key: row.get(1)?,
+                    })
+                },
+            )
+            .optional()
+        })
+        .await
+    }
+
+    async fn write(
+        &mut self,
+        end_cursor: &Cursor,
+        records: Vec<Record<Self::Item>>,
+    ) -> Result<(), Self::Error> {
+        let end_cursor = end_cursor.clone();
+        self.with_connection(move |conn| {
+            let txn = conn.transaction()?;
+            for record in &records {
+                record.value.insert(&txn, record.order_key)?;
+            }
+            write_cursor(&txn, Some(&end_cursor))?;
+            txn.commit()
+        })
+        .await
+    }
+
+    async fn invalidate(&mut self, cursor: Option<&Cursor>) -> Result<(), Self::Error> {
+        let cursor = cursor.cloned();
+        self.with_connection(move |conn| {
+            let txn = conn.transaction()?;
+            T::delete_after(&txn, cursor.as_ref().map(|c| c.order_key))?;
+            write_cursor(&txn, cursor.as_ref())?;
+            txn.commit()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use apibara_core::node::v1alpha2::Cursor;
+    use rusqlite::{params, Connection, Transaction};
+
+    use crate::{indexer::Record, sink::Sink};
+
+    use super::{SqliteRecord, SqliteSink};
+
+    struct Transfer {
+        amount: u64,
+    }
+
+    impl SqliteRecord for Transfer {
+        fn create_tables(conn: &Connection) -> rusqlite::Result<()> {
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS transfers (block INTEGER NOT NULL, amount INTEGER NOT NULL)",
+                [],
+            )?;
+            Ok(())
+        }
+
+        fn insert(&self, txn: &Transaction, order_key: u64) -> rusqlite::Result<()> {
+            txn.execute(
+                "INSERT INTO transfers (block, amount) VALUES (?1, ?2)",
+                params![order_key as i64, self.amount as i64],
+            )?;
+            Ok(())
+        }
+
+        fn delete_after(txn: &Transaction, order_key: Option<u64>) -> rusqlite::Result<()> {
+            match order_key {
+                None => txn.execute("DELETE FROM transfers", [])?,
+                Some(order_key) => txn.execute(
+                    "DELETE FROM transfers WHERE block > ?1",
+                    params![order_key as i64],
+                )?,
+            };
+            Ok(())
+        }
+    }
+
+    fn cursor(block: u64) -> Cursor {
+        Cursor {
+            order_key: block,
+            unique_key: vec![0xab, block as u8],
+        }
+    }
+
+    fn transfers(start_block: u64, end_block: u64) -> Vec<Record<Transfer>> {
+        (start_block..=end_block)
+            .map(|block| Record::new(block, Transfer { amount: block * 10 }))
+            .collect()
+    }
+
+    fn total_amount(sink: &SqliteSink<Transfer>) -> i64 {
+        sink.connection()
+            .query_row(
+                "SELECT COALESCE(SUM(amount), 0) FROM transfers",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_write_and_invalidate() {
+        let mut sink = SqliteSink::<Transfer>::open_in_memory().unwrap();
+        assert_eq!(sink.cursor().await.unwrap(), None);
+
+        sink.write(&cursor(3), transfers(1, 3)).await.unwrap();
+        sink.write(&cursor(5), transfers(4, 5)).await.unwrap();
+        assert_eq!(sink.cursor().await.unwrap(), Some(cursor(5)));
+        assert_eq!(total_amount(&sink), 150);
+
+        sink.invalidate(Some(&cursor(2))).await.unwrap();
+        assert_eq!(sink.cursor().await.unwrap(), Some(cursor(2)));
+        assert_eq!(total_amount(&sink), 30);
+
+        sink.invalidate(None).await.unwrap();
+        assert_eq!(sink.cursor().await.unwrap(), None);
+        assert_eq!(total_amount(&sink), 0);
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_stored_cursor() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("indexer.db");
+
+        let mut sink = SqliteSink::<Transfer>::open(&path).unwrap();
+        sink.write(&cursor(3), transfers(1, 3)).await.unwrap();
+        drop(sink);
+
+        let mut sink = SqliteSink::<Transfer>::open(&path).unwrap();
+        assert_eq!(sink.cursor().await.unwrap(), Some(cursor(3)));
+        assert_eq!(total_amount(&sink), 60);
+    }
+}
@@ -0,0 +1,220 @@
+//! A scripted stream server used to test the client.
+
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use apibara_core::node::v1alpha2::{
+    stream_data_response, stream_server, Cursor, Data, DataFinality, Error, GetBlocksRequest,
+    GetBlocksResponse, GetReorganizationsRequest, GetReorganizationsResponse, Heartbeat,
+    StatusRequest, StatusResponse, StreamDataRequest, StreamDataResponse,
+};
+use futures::{Stream, StreamExt};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tokio_util::sync::CancellationToken;
+use tonic::{transport::Server, Request, Response, Status, Streaming};
+
+use crate::Uri;
+
+/// What the server does on a connection, in order.
+#[derive(Debug, Clone)]
+pub enum ServerEvent {
+    /// Send the response to the client.
+    Send(StreamDataResponse),
+    /// Terminate the stream with the given error.
+    Fail(Status),
+    /// Wait before moving to the next event.
+    Sleep(Duration),
+}
+
+/// A server that replays one script for each client connection.
+///
+/// After a script completes, the connection is kept open without sending
+/// any more data. Connections without a script are refused.
+pub struct TestServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<StreamDataRequest>>>,
+    ct: CancellationToken,
+}
+
+struct TestService {
+    scripts: Arc<Mutex<VecDeque<Vec<ServerEvent>>>>,
+    requests: Arc<Mutex<Vec<StreamDataRequest>>>,
+}
+
+impl TestServer {
+    pub async fn start(scripts: Vec<Vec<ServerEvent>>) -> TestServer {
+        let requests = Arc::new(Mutex::new(Vec::default()));
+        let service = TestService {
+            scripts: Arc::new(Mutex::new(scripts.into())),
+            requests: requests.clone(),
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let ct = CancellationToken::new();
+
+        tokio::spawn({
+            let ct = ct.clone();
+            async move {
+                Server::builder()
+                    .add_service(stream_server::StreamServer::new(service))
+                    .serve_with_incoming_shutdown(TcpListenerStream::new(listener), ct.cancelled())
+                    .await
+            }
+        });
+
+        TestServer { addr, requests, ct }
+    }
+
+    pub fn uri(&self) -> Uri {
+        format!("http://{}", self.addr).parse().unwrap()
+    }
+
+    /// Returns all requests received by the server, across connections.
+    pub fn requests(&self) -> Vec<StreamDataRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.ct.cancel();
+    }
+}
+
+/// Returns a data response for the given stream, ending at the given block.
+pub fn data(stream_id: u64, end_block: u64) -> ServerEvent {
+    raw_data(stream_id, end_block, Vec::default())
+}
+
+/// Returns a data response with the given encoded blocks.
+pub fn raw_data(stream_id: u64, end_block: u64, data: Vec<Vec<u8>>) -> ServerEvent {
+    data_with_finality(stream_id, end_block, DataFinality::DataStatusAccepted, data)
+}
+
+/// Returns a data response for the pending block, like the node sends it.
+pub fn pending_data(stream_id: u64, block: u64) -> ServerEvent {
+    data_with_finality(
+        stream_id,
+        block,
+        DataFinality::DataStatusPending,
+        Vec::default(),
+    )
+}
+
+fn data_with_finality(
+    stream_id: u64,
+    end_block: u64,
+    finality: DataFinality,
+    data: Vec<Vec<u8>>,
+) -> ServerEvent {
+    let data = Data {
+        cursor: None,
+        end_cursor: Some(cursor(end_block)),
+        finality: finality as i32,
+        data,
+    };
+    ServerEvent::Send(StreamDataResponse {
+        stream_id,
+        message: Some(stream_data_response::Message::Data(data)),
+    })
+}
+
+/// Returns an error response for the given stream.
+pub fn error(stream_id: u64, message: &str) -> ServerEvent {
+    ServerEvent::Send(StreamDataResponse {
+        stream_id,
+        message: Some(stream_data_response::Message::Error(Error {
+            message: message.to_string(),
+        })),
+    })
+}
+
+/// Returns a heartbeat response.
+pub fn heartbeat() -> ServerEvent {
+    ServerEvent::Send(StreamDataResponse {
+        stream_id: 0,
+        message: Some(stream_data_response::Message::Heartbeat(Heartbeat {})),
+    })
+}
+
+pub fn cursor(block: u64) -> Cursor {
+    Cursor {
+        order_key: block,
+        unique_key: Vec::default(),
+    }
+}
+
+#[tonic::async_trait]
+impl stream_server::Stream for TestService {
+    type StreamDataStream =
+        Pin<Box<dyn Stream<Item = Result<StreamDataResponse, Status>> + Send + 'static>>;
+
+    async fn stream_data(
+        &self,
+        request: Request<Streaming<StreamDataRequest>>,
+    ) -> Result<Response<Self::StreamDataStream>, Status> {
+        let script = self
+            .scripts
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| Status::unavailable("no more connections"))?;
+
+        let mut incoming = request.into_inner();
+        let requests = self.requests.clone();
+
+        let response = async_stream::stream! {
+            // start replaying the script only after the client configured the stream.
+            match incoming.next().await {
+                Some(Ok(request)) => requests.lock().unwrap().push(request),
+                _ => return,
+            }
+
+            for event in script {
+                match event {
+                    ServerEvent::Send(response) => yield Ok(response),
+                    ServerEvent::Fail(status) => {
+                        yield Err(status);
+                        return;
+                    }
+                    ServerEvent::Sleep(duration) => tokio::time::sleep(duration).await,
+                }
+            }
+
+            // keep recording requests until the client disconnects.
+            while let Some(Ok(request)) = incoming.next().await {
+                requests.lock().unwrap().push(request);
+            }
+        };
+
+        Ok(Response::new(Box::pin(response)))
+    }
+
+    async fn status(
+        &self,
+        _request: Request<StatusRequest>,
+    ) -> Result<Response<StatusResponse>, Status> {
+        Err(Status::unimplemented("status"))
+    }
+
+    async fn get_blocks(
+        &self,
+        _request: Request<GetBlocksRequest>,
+    ) -> Result<Response<GetBlocksResponse>, Status> {
+        Err(Status::unimplemented("get_blocks"))
+    }
+
+    async fn get_reorganizations(
+        &self,
+        _request: Request<GetReorganizationsRequest>,
+    ) -> Result<Response<GetReorganizationsResponse>, Status> {
+        Err(Status::unimplemented("get_reorganizations"))
+    }
+}
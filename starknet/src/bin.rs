@@ -1,13 +1,17 @@
-use std::path::PathBuf;
+use std::{fs, net::SocketAddr, path::PathBuf, time::Duration};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use apibara_node::{db::default_data_dir, o11y::init_opentelemetry};
 use apibara_starknet::{
+    node::{StarkNetNodeBuilder, DEFAULT_SERVER_ADDRESS},
+    provider::{FailoverConfig, Provider},
     server::{MetadataKeyRequestObserver, SimpleRequestObserver},
-    HttpProvider, NoWriteMap, StarkNetNode,
+    FailoverProvider, FeederGatewayProvider, HttpProvider, NoWriteMap, StarkNetNode,
 };
 use clap::{Args, Parser, Subcommand};
 use tokio_util::sync::CancellationToken;
+use tonic::transport::{Identity, ServerTlsConfig};
+use url::Url;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -24,9 +28,21 @@ enum CliCommand {
 
 #[derive(Args)]
 struct StartCommand {
-    /// StarkNet RPC address.
-    #[arg(long, env)]
-    rpc: String,
+    /// StarkNet RPC address. Pass multiple comma-separated addresses to fail over
+    /// between them, in order of preference.
+    #[arg(
+        long,
+        env,
+        value_delimiter = ',',
+        required_unless_present = "feeder_gateway"
+    )]
+    rpc: Vec<String>,
+    /// Number of RPC nodes that must agree on a block before it's ingested.
+    #[arg(long, env, requires = "rpc")]
+    rpc_quorum: Option<usize>,
+    /// StarkNet feeder gateway address, used instead of the RPC.
+    #[arg(long, env, conflicts_with = "rpc")]
+    feeder_gateway: Option<Url>,
     /// Data directory. Defaults to `$XDG_DATA_HOME`.
     #[arg(long, env)]
     data: Option<PathBuf>,
@@ -36,14 +52,91 @@ struct StartCommand {
     /// Wait for RPC to be available before starting.
     #[arg(long, env)]
     wait_for_rpc: bool,
+    /// gRPC server listen address.
+    #[arg(long, env, default_value = DEFAULT_SERVER_ADDRESS)]
+    address: SocketAddr,
+    /// Path to the PEM-encoded TLS certificate used by the gRPC server.
+    #[arg(long, env, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// Path to the PEM-encoded TLS private key used by the gRPC server.
+    #[arg(long, env, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// Number of concurrent RPC requests used while ingesting a block.
+    #[arg(long, env, conflicts_with = "feeder_gateway")]
+    rpc_concurrency: Option<usize>,
+    /// How often to refresh the head block, in milliseconds.
+    #[arg(long, env)]
+    head_refresh_interval_ms: Option<u64>,
+    /// How often to send a batch without data while scanning finalized blocks, in milliseconds.
+    #[arg(long, env)]
+    stream_progress_interval_ms: Option<u64>,
+    /// Number of blocks the node can lag behind the RPC head before it's reported as unhealthy.
+    #[arg(long, env)]
+    max_head_lag: Option<u64>,
+    /// Maximum number of blocks a chain reorganization can remove before ingestion halts.
+    #[arg(long, env)]
+    max_reorg_depth: Option<u64>,
+    /// Minimum database size, in GiB.
+    #[arg(long, env, default_value_t = 10)]
+    db_min_size_gib: usize,
+    /// Maximum database size, in GiB.
+    #[arg(long, env, default_value_t = 100)]
+    db_max_size_gib: usize,
 }
 
 async fn start(args: StartCommand) -> Result<()> {
     init_opentelemetry()?;
 
+    if let Some(feeder_gateway) = args.feeder_gateway.clone() {
+        let provider = FeederGatewayProvider::new(feeder_gateway);
+        let node =
+            StarkNetNode::<_, SimpleRequestObserver, NoWriteMap>::builder_with_provider(provider);
+        return start_node(node, args).await;
+    }
+
+    if args.rpc.len() > 1 || args.rpc_quorum.is_some() {
+        let providers = args
+            .rpc
+            .iter()
+            .map(|url| -> Result<HttpProvider> {
+                let mut provider = HttpProvider::new(url.parse()?);
+                if let Some(rpc_concurrency) = args.rpc_concurrency {
+                    provider.with_rpc_concurrency(rpc_concurrency);
+                }
+                Ok(provider)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let config = FailoverConfig {
+            quorum: args.rpc_quorum.unwrap_or(1),
+            ..FailoverConfig::default()
+        };
+        let provider = FailoverProvider::new(providers, config)?;
+        let node =
+            StarkNetNode::<_, SimpleRequestObserver, NoWriteMap>::builder_with_provider(provider);
+        return start_node(node, args).await;
+    }
+
+    let rpc = args
+        .rpc
+        .first()
+        .cloned()
+        .expect("clap requires one of --rpc or --feeder-gateway");
+    let mut node = StarkNetNode::<HttpProvider, SimpleRequestObserver, NoWriteMap>::builder(&rpc)?;
+    if let Some(rpc_concurrency) = args.rpc_concurrency {
+        node.with_rpc_concurrency(rpc_concurrency);
+    }
+    start_node(node, args).await
+}
+
+async fn start_node<G>(
+    node: StarkNetNodeBuilder<G, SimpleRequestObserver, NoWriteMap>,
+    args: StartCommand,
+) -> Result<()>
+where
+    G: Provider + Send + Sync + 'static,
+{
     let mut node =
-        StarkNetNode::<HttpProvider, SimpleRequestObserver, NoWriteMap>::builder(&args.rpc)?
-            .with_request_observer(MetadataKeyRequestObserver::new("x-api-key".to_string()));
+        node.with_request_observer(MetadataKeyRequestObserver::new("x-api-key".to_string()));
 
     // give precedence to --data
     if let Some(datadir) = args.data {
@@ -55,6 +148,34 @@ async fn start(args: StartCommand) -> Result<()> {
         node.with_datadir(datadir);
     }
 
+    node.with_server_address(args.address);
+    node.with_db_size_gib(args.db_min_size_gib, args.db_max_size_gib);
+
+    if let Some(interval) = args.head_refresh_interval_ms {
+        node.with_poll_interval(Duration::from_millis(interval));
+    }
+
+    if let Some(interval) = args.stream_progress_interval_ms {
+        node.with_stream_progress_interval(Duration::from_millis(interval));
+    }
+
+    if let Some(max_head_lag) = args.max_head_lag {
+        node.with_max_head_lag(max_head_lag);
+    }
+
+    if let Some(max_reorg_depth) = args.max_reorg_depth {
+        node.with_max_reorg_depth(max_reorg_depth);
+    }
+
+    if let (Some(cert), Some(key)) = (args.tls_cert, args.tls_key) {
+        let cert = fs::read(&cert)
+            .with_context(|| format!("failed to read TLS certificate {}", cert.display()))?;
+        let key =
+            fs::read(&key).with_context(|| format!("failed to read TLS key {}", key.display()))?;
+        let identity = Identity::from_pem(cert, key);
+        node.with_server_tls(ServerTlsConfig::new().identity(identity));
+    }
+
     // Setup cancellation for graceful shutdown
     let cts = CancellationToken::new();
     ctrlc::set_handler({
@@ -43,10 +43,28 @@ pub struct RawBloom {
 pub struct BlockReceipts {
     #[prost(message, repeated, tag = "1")]
     pub receipts: prost::alloc::vec::Vec<v1alpha2::TransactionReceipt>,
+    /// Events bloom, only set on blocks ingested before [BlockBloom] existed.
     #[prost(message, tag = "2")]
     pub bloom: Option<RawBloom>,
 }
 
+/// Bloom filters used to quickly check if a block may contain data.
+#[derive(Clone, PartialEq, Message)]
+pub struct BlockBloom {
+    /// Transactions addresses, entry point selectors, class hashes and salts.
+    #[prost(message, tag = "1")]
+    pub transactions: Option<RawBloom>,
+    /// Events addresses and keys.
+    #[prost(message, tag = "2")]
+    pub events: Option<RawBloom>,
+    /// L2 to L1 messages destination addresses.
+    #[prost(message, tag = "3")]
+    pub messages: Option<RawBloom>,
+    /// State update contract addresses and class hashes.
+    #[prost(message, tag = "4")]
+    pub state_update: Option<RawBloom>,
+}
+
 /// Store block status.
 #[derive(Debug, Clone, Copy, Default)]
 pub struct BlockStatusTable {}
@@ -61,6 +79,10 @@ pub struct BlockStatus {
 #[derive(Debug, Clone, Copy, Default)]
 pub struct BlockHeaderTable {}
 
+/// Store block bloom filters.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BlockBloomTable {}
+
 impl TableKey for BlockHash {
     type Encoded = [u8; 32];
 
@@ -120,3 +142,12 @@ impl Table for BlockHeaderTable {
         "BlockHeader"
     }
 }
+
+impl Table for BlockBloomTable {
+    type Key = GlobalBlockId;
+    type Value = BlockBloom;
+
+    fn db_name() -> &'static str {
+        "BlockBloom"
+    }
+}
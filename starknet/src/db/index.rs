@@ -0,0 +1,186 @@
+//! Event index data.
+//!
+//! The indices map a field element (event address or first key) to the
+//! blocks that contain at least one matching event. Block numbers are grouped
+//! in ranges of [EVENT_INDEX_RANGE] blocks and each range is stored as a bitmap.
+
+use std::io::Cursor;
+
+use apibara_core::starknet::v1alpha2;
+use apibara_node::db::{KeyDecodeError, Table, TableKey};
+use byteorder::{BigEndian, ReadBytesExt};
+use prost::Message;
+
+/// Number of blocks covered by a single index entry.
+pub const EVENT_INDEX_RANGE: u64 = 4_096;
+
+/// Key of an event index entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventIndexKey {
+    /// The indexed value.
+    pub value: v1alpha2::FieldElement,
+    /// First block number covered by the entry.
+    pub range_start: u64,
+}
+
+/// Bitmap of the blocks in a range that contain a value.
+#[derive(Clone, PartialEq, Message)]
+pub struct BlockBitmap {
+    #[prost(bytes, tag = "1")]
+    pub bits: prost::alloc::vec::Vec<u8>,
+}
+
+/// Track which blocks are covered by the event indices.
+#[derive(Clone, PartialEq, Message)]
+pub struct EventIndexState {
+    /// First block written together with its index entries.
+    #[prost(uint64, tag = "1")]
+    pub first_indexed_block: u64,
+}
+
+/// Index blocks by the address of the contracts emitting events.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EventAddressIndexTable {}
+
+/// Index blocks by the first key of the events.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EventKeyIndexTable {}
+
+/// Store the event index state.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EventIndexStateTable {}
+
+impl EventIndexKey {
+    /// Returns the key of the entry containing the given block.
+    pub fn new(value: v1alpha2::FieldElement, block_number: u64) -> Self {
+        let range_start = block_number - block_number % EVENT_INDEX_RANGE;
+        EventIndexKey { value, range_start }
+    }
+
+    /// Returns the position of the given block inside the entry.
+    ///
+    /// Blocks before the start of the range are mapped to the first position.
+    pub fn offset(&self, block_number: u64) -> u64 {
+        block_number.saturating_sub(self.range_start)
+    }
+}
+
+impl BlockBitmap {
+    /// Marks the block at the given offset.
+    pub fn set(&mut self, offset: u64) {
+        let byte = (offset / 8) as usize;
+        if self.bits.len() <= byte {
+            self.bits.resize(byte + 1, 0);
+        }
+        self.bits[byte] |= 1 << (offset % 8);
+    }
+
+    /// Returns true if the block at the given offset is marked.
+    pub fn is_set(&self, offset: u64) -> bool {
+        let byte = (offset / 8) as usize;
+        self.bits
+            .get(byte)
+            .map(|b| b & (1 << (offset % 8)) != 0)
+            .unwrap_or(false)
+    }
+
+    /// Returns the first marked offset greater than or equal to `offset`.
+    pub fn next_set(&self, offset: u64) -> Option<u64> {
+        let max_offset = self.bits.len() as u64 * 8;
+        (offset..max_offset).find(|o| self.is_set(*o))
+    }
+}
+
+// An index key is encoded as:
+// - 32 bytes field element
+// - 8 bytes big endian representation of the range start
+impl TableKey for EventIndexKey {
+    type Encoded = [u8; 40];
+
+    fn encode(&self) -> Self::Encoded {
+        let mut out = [0; 40];
+        out[..32].copy_from_slice(&self.value.to_bytes());
+        out[32..].copy_from_slice(&self.range_start.to_be_bytes());
+        out
+    }
+
+    fn decode(b: &[u8]) -> Result<Self, KeyDecodeError> {
+        if b.len() != 40 {
+            return Err(KeyDecodeError::InvalidByteSize {
+                expected: 40,
+                actual: b.len(),
+            });
+        }
+        let mut value = [0; 32];
+        value.copy_from_slice(&b[..32]);
+        let mut cursor = Cursor::new(&b[32..]);
+        let range_start = cursor
+            .read_u64::<BigEndian>()
+            .map_err(KeyDecodeError::ReadError)?;
+        Ok(EventIndexKey {
+            value: v1alpha2::FieldElement::from_bytes(&value),
+            range_start,
+        })
+    }
+}
+
+impl Table for EventAddressIndexTable {
+    type Key = EventIndexKey;
+    type Value = BlockBitmap;
+
+    fn db_name() -> &'static str {
+        "EventAddressIndex"
+    }
+}
+
+impl Table for EventKeyIndexTable {
+    type Key = EventIndexKey;
+    type Value = BlockBitmap;
+
+    fn db_name() -> &'static str {
+        "EventKeyIndex"
+    }
+}
+
+impl Table for EventIndexStateTable {
+    type Key = ();
+    type Value = EventIndexState;
+
+    fn db_name() -> &'static str {
+        "EventIndexState"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use apibara_core::starknet::v1alpha2::FieldElement;
+    use apibara_node::db::TableKey;
+
+    use super::{BlockBitmap, EventIndexKey, EVENT_INDEX_RANGE};
+
+    #[test]
+    fn test_bitmap_next_set() {
+        let mut bitmap = BlockBitmap::default();
+        assert_eq!(bitmap.next_set(0), None);
+
+        bitmap.set(3);
+        bitmap.set(100);
+        assert!(bitmap.is_set(3));
+        assert!(!bitmap.is_set(4));
+        assert_eq!(bitmap.next_set(0), Some(3));
+        assert_eq!(bitmap.next_set(3), Some(3));
+        assert_eq!(bitmap.next_set(4), Some(100));
+        assert_eq!(bitmap.next_set(101), None);
+    }
+
+    #[test]
+    fn test_key_range() {
+        let value = FieldElement::from_u64(42);
+        let key = EventIndexKey::new(value.clone(), EVENT_INDEX_RANGE + 7);
+        assert_eq!(key.range_start, EVENT_INDEX_RANGE);
+        assert_eq!(key.offset(EVENT_INDEX_RANGE + 7), 7);
+
+        let back = EventIndexKey::decode(&key.encode()).unwrap();
+        assert_eq!(back, key);
+    }
+}
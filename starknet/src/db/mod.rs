@@ -1,18 +1,26 @@
 mod block;
 mod chain;
+mod index;
+mod reorg;
 mod state;
 mod storage;
 mod transaction;
 
-pub use self::block::{BlockBody, BlockReceipts, BlockStatus};
-pub use self::storage::{DatabaseStorage, DatabaseStorageWriter, StorageReader, StorageWriter};
+pub use self::block::{BlockBloom, BlockBody, BlockReceipts, BlockStatus};
+pub use self::index::{BlockBitmap, EventIndexKey, EventIndexState, EVENT_INDEX_RANGE};
+pub use self::storage::{
+    new_bloom, BlockBlooms, Bloom, DatabaseStorage, DatabaseStorageWriter, StorageReader,
+    StorageWriter, StoredBlockData,
+};
 
 pub mod tables {
     use apibara_node::db::libmdbx::{EnvironmentKind, Error as MdbxError, Transaction, RW};
     use apibara_node::db::MdbxRWTransactionExt;
 
-    pub use super::block::{BlockHeaderTable, BlockStatusTable};
+    pub use super::block::{BlockBloomTable, BlockHeaderTable, BlockStatusTable};
     pub use super::chain::CanonicalChainTable;
+    pub use super::index::{EventAddressIndexTable, EventIndexStateTable, EventKeyIndexTable};
+    pub use super::reorg::ReorgLogTable;
     pub use super::state::StateUpdateTable;
     pub use super::transaction::{BlockBodyTable, BlockReceiptsTable};
 
@@ -24,6 +32,11 @@ pub mod tables {
         txn.ensure_table::<self::CanonicalChainTable>(None)?;
         txn.ensure_table::<self::BlockReceiptsTable>(None)?;
         txn.ensure_table::<self::StateUpdateTable>(None)?;
+        txn.ensure_table::<self::EventAddressIndexTable>(None)?;
+        txn.ensure_table::<self::EventKeyIndexTable>(None)?;
+        txn.ensure_table::<self::EventIndexStateTable>(None)?;
+        txn.ensure_table::<self::BlockBloomTable>(None)?;
+        txn.ensure_table::<self::ReorgLogTable>(None)?;
         Ok(())
     }
 }
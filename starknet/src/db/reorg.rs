@@ -0,0 +1,17 @@
+//! Chain reorganizations log.
+
+use apibara_core::node::v1alpha2;
+use apibara_node::db::Table;
+
+/// Store chain reorganizations, keyed by a sequence number.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReorgLogTable {}
+
+impl Table for ReorgLogTable {
+    type Key = u64;
+    type Value = v1alpha2::ChainReorganization;
+
+    fn db_name() -> &'static str {
+        "ReorgLog"
+    }
+}
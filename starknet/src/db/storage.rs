@@ -1,23 +1,52 @@
 //! Abstraction over raw db tables.
 
-use std::sync::Arc;
+use std::{collections::BTreeSet, sync::Arc};
 
-use apibara_core::starknet::v1alpha2;
+use apibara_core::{node::v1alpha2::ChainReorganization, starknet::v1alpha2};
 use apibara_node::db::{
-    libmdbx::{self, Environment, EnvironmentKind, Transaction, RW},
-    MdbxErrorExt, MdbxTransactionExt, TableCursor,
+    libmdbx::{self, Environment, EnvironmentKind, Transaction, TransactionKind, RW},
+    MdbxErrorExt, MdbxTransactionExt, Table, TableCursor,
 };
 
 use crate::core::GlobalBlockId;
 
 use super::{
-    block::{BlockBody, BlockReceipts, HasherKeys, RawBloom},
+    block::{BlockBloom, BlockBody, BlockReceipts, HasherKeys, RawBloom},
+    index::{BlockBitmap, EventIndexKey, EventIndexState},
     tables,
 };
 
 /// Bloom filter over field elements.
 pub type Bloom = bloomfilter::Bloom<v1alpha2::FieldElement>;
 
+/// The bloom filters of a block.
+///
+/// A missing bloom filter means the block data must be checked in full.
+#[derive(Default)]
+pub struct BlockBlooms {
+    pub transactions: Option<Bloom>,
+    pub events: Option<Bloom>,
+    pub messages: Option<Bloom>,
+    pub state_update: Option<Bloom>,
+}
+
+/// The block data rows that are present in storage.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StoredBlockData {
+    pub status: bool,
+    pub header: bool,
+    pub body: bool,
+    pub receipts: bool,
+    pub state_update: bool,
+}
+
+impl StoredBlockData {
+    /// Returns true if all block data is stored.
+    pub fn is_complete(&self) -> bool {
+        self.status && self.header && self.body && self.receipts && self.state_update
+    }
+}
+
 /// An object to read chain data from storage.
 pub trait StorageReader {
     type Error: std::error::Error + Send + Sync + 'static;
@@ -28,6 +57,9 @@ pub trait StorageReader {
     /// Returns the highest finalized block that was indexed.
     fn highest_finalized_block(&self) -> Result<Option<GlobalBlockId>, Self::Error>;
 
+    /// Returns the lowest block in the canonical chain.
+    fn lowest_canonical_block(&self) -> Result<Option<GlobalBlockId>, Self::Error>;
+
     /// Returns the block id for the block at the given height, or `None` if the
     /// canonical chain is shorter.
     fn canonical_block_id(&self, number: u64) -> Result<Option<GlobalBlockId>, Self::Error>;
@@ -43,17 +75,48 @@ pub trait StorageReader {
     /// Returns all transactions in the given block.
     fn read_body(&self, id: &GlobalBlockId) -> Result<Vec<v1alpha2::Transaction>, Self::Error>;
 
-    /// Returns all receipts in the given block together with its bloom filter.
+    /// Returns all receipts in the given block.
     fn read_receipts(
         &self,
         id: &GlobalBlockId,
-    ) -> Result<(Vec<v1alpha2::TransactionReceipt>, Option<Bloom>), Self::Error>;
+    ) -> Result<Vec<v1alpha2::TransactionReceipt>, Self::Error>;
+
+    /// Returns the bloom filters for the given block.
+    fn read_blooms(&self, id: &GlobalBlockId) -> Result<BlockBlooms, Self::Error>;
 
     /// Returns the state update for the given block.
     fn read_state_update(
         &self,
         id: &GlobalBlockId,
     ) -> Result<Option<v1alpha2::StateUpdate>, Self::Error>;
+
+    /// Returns which data rows are stored for the given block.
+    fn stored_block_data(&self, id: &GlobalBlockId) -> Result<StoredBlockData, Self::Error>;
+
+    /// Returns the first block number covered by the event indices.
+    ///
+    /// Blocks before this one were ingested before the indices existed and
+    /// must be scanned in full.
+    fn event_index_start(&self) -> Result<Option<u64>, Self::Error>;
+
+    /// Returns the lowest block number, greater than or equal to `from`, that
+    /// contains an event emitted by `address`.
+    fn next_block_with_event_address(
+        &self,
+        address: &v1alpha2::FieldElement,
+        from: u64,
+    ) -> Result<Option<u64>, Self::Error>;
+
+    /// Returns the lowest block number, greater than or equal to `from`, that
+    /// contains an event with `key` as its first key.
+    fn next_block_with_event_key(
+        &self,
+        key: &v1alpha2::FieldElement,
+        from: u64,
+    ) -> Result<Option<u64>, Self::Error>;
+
+    /// Returns up to `limit` chain reorganizations, most recent first.
+    fn read_reorganizations(&self, limit: usize) -> Result<Vec<ChainReorganization>, Self::Error>;
 }
 
 /// An object to write chain data to storage in a single transaction.
@@ -99,6 +162,9 @@ pub trait StorageWriter {
         id: &GlobalBlockId,
         state_update: v1alpha2::StateUpdate,
     ) -> Result<(), Self::Error>;
+
+    /// Appends a chain reorganization to the reorganizations log.
+    fn write_reorganization(&mut self, reorg: ChainReorganization) -> Result<(), Self::Error>;
 }
 
 #[derive(Debug, Clone)]
@@ -114,6 +180,11 @@ pub struct DatabaseStorageWriter<'env, 'txn, E: EnvironmentKind> {
     receipts_cursor: TableCursor<'txn, tables::BlockReceiptsTable, RW>,
     state_update_cursor: TableCursor<'txn, tables::StateUpdateTable, RW>,
     canonical_chain_cursor: TableCursor<'txn, tables::CanonicalChainTable, RW>,
+    event_address_index_cursor: TableCursor<'txn, tables::EventAddressIndexTable, RW>,
+    event_key_index_cursor: TableCursor<'txn, tables::EventKeyIndexTable, RW>,
+    event_index_state_cursor: TableCursor<'txn, tables::EventIndexStateTable, RW>,
+    bloom_cursor: TableCursor<'txn, tables::BlockBloomTable, RW>,
+    reorg_log_cursor: TableCursor<'txn, tables::ReorgLogTable, RW>,
 }
 
 impl<E: EnvironmentKind> DatabaseStorage<E> {
@@ -129,6 +200,11 @@ impl<E: EnvironmentKind> DatabaseStorage<E> {
         let receipts_cursor = txn.open_cursor::<tables::BlockReceiptsTable>()?;
         let state_update_cursor = txn.open_cursor::<tables::StateUpdateTable>()?;
         let canonical_chain_cursor = txn.open_cursor::<tables::CanonicalChainTable>()?;
+        let event_address_index_cursor = txn.open_cursor::<tables::EventAddressIndexTable>()?;
+        let event_key_index_cursor = txn.open_cursor::<tables::EventKeyIndexTable>()?;
+        let event_index_state_cursor = txn.open_cursor::<tables::EventIndexStateTable>()?;
+        let bloom_cursor = txn.open_cursor::<tables::BlockBloomTable>()?;
+        let reorg_log_cursor = txn.open_cursor::<tables::ReorgLogTable>()?;
         let writer = DatabaseStorageWriter {
             txn,
             status_cursor,
@@ -137,6 +213,11 @@ impl<E: EnvironmentKind> DatabaseStorage<E> {
             receipts_cursor,
             state_update_cursor,
             canonical_chain_cursor,
+            event_address_index_cursor,
+            event_key_index_cursor,
+            event_index_state_cursor,
+            bloom_cursor,
+            reorg_log_cursor,
         };
         Ok(writer)
     }
@@ -186,6 +267,21 @@ impl<E: EnvironmentKind> StorageReader for DatabaseStorage<E> {
         Ok(None)
     }
 
+    #[tracing::instrument(level = "trace", skip(self))]
+    fn lowest_canonical_block(&self) -> Result<Option<GlobalBlockId>, Self::Error> {
+        let txn = self.db.begin_ro_txn()?;
+        let mut cursor = txn.open_cursor::<tables::CanonicalChainTable>()?;
+        let block_id = match cursor.first()? {
+            None => None,
+            Some((number, hash)) => {
+                let hash = (&hash).try_into().map_err(libmdbx::Error::decode_error)?;
+                Some(GlobalBlockId::new(number, hash))
+            }
+        };
+        txn.commit()?;
+        Ok(block_id)
+    }
+
     #[tracing::instrument(level = "trace", skip(self))]
     fn canonical_block_id(&self, number: u64) -> Result<Option<GlobalBlockId>, Self::Error> {
         let txn = self.db.begin_ro_txn()?;
@@ -246,14 +342,39 @@ impl<E: EnvironmentKind> StorageReader for DatabaseStorage<E> {
     fn read_receipts(
         &self,
         id: &GlobalBlockId,
-    ) -> Result<(Vec<v1alpha2::TransactionReceipt>, Option<Bloom>), Self::Error> {
+    ) -> Result<Vec<v1alpha2::TransactionReceipt>, Self::Error> {
         let txn = self.db.begin_ro_txn()?;
         let mut cursor = txn.open_cursor::<tables::BlockReceiptsTable>()?;
         let block_receipts_data = cursor.seek_exact(id)?.map(|t| t.1).unwrap_or_default();
-        let receipts = block_receipts_data.receipts;
-        let bloom = block_receipts_data.bloom.and_then(|b| b.into());
         txn.commit()?;
-        Ok((receipts, bloom))
+        Ok(block_receipts_data.receipts)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    fn read_blooms(&self, id: &GlobalBlockId) -> Result<BlockBlooms, Self::Error> {
+        let txn = self.db.begin_ro_txn()?;
+        let mut cursor = txn.open_cursor::<tables::BlockBloomTable>()?;
+        let mut blooms = match cursor.seek_exact(id)?.map(|t| t.1) {
+            None => BlockBlooms::default(),
+            Some(bloom) => BlockBlooms {
+                transactions: bloom.transactions.and_then(|b| b.into()),
+                events: bloom.events.and_then(|b| b.into()),
+                messages: bloom.messages.and_then(|b| b.into()),
+                state_update: bloom.state_update.and_then(|b| b.into()),
+            },
+        };
+
+        // older blocks store the events bloom together with the receipts.
+        if blooms.events.is_none() {
+            let mut cursor = txn.open_cursor::<tables::BlockReceiptsTable>()?;
+            blooms.events = cursor
+                .seek_exact(id)?
+                .and_then(|t| t.1.bloom)
+                .and_then(|b| b.into());
+        }
+
+        txn.commit()?;
+        Ok(blooms)
     }
 
     #[tracing::instrument(level = "trace", skip(self))]
@@ -267,6 +388,91 @@ impl<E: EnvironmentKind> StorageReader for DatabaseStorage<E> {
         txn.commit()?;
         Ok(state_update)
     }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    fn stored_block_data(&self, id: &GlobalBlockId) -> Result<StoredBlockData, Self::Error> {
+        let txn = self.db.begin_ro_txn()?;
+        let status = txn
+            .open_cursor::<tables::BlockStatusTable>()?
+            .seek_exact(id)?
+            .is_some();
+        let header = txn
+            .open_cursor::<tables::BlockHeaderTable>()?
+            .seek_exact(id)?
+            .is_some();
+        let body = txn
+            .open_cursor::<tables::BlockBodyTable>()?
+            .seek_exact(id)?
+            .is_some();
+        let receipts = txn
+            .open_cursor::<tables::BlockReceiptsTable>()?
+            .seek_exact(id)?
+            .is_some();
+        let state_update = txn
+            .open_cursor::<tables::StateUpdateTable>()?
+            .seek_exact(id)?
+            .is_some();
+        txn.commit()?;
+        Ok(StoredBlockData {
+            status,
+            header,
+            body,
+            receipts,
+            state_update,
+        })
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    fn event_index_start(&self) -> Result<Option<u64>, Self::Error> {
+        let txn = self.db.begin_ro_txn()?;
+        let mut cursor = txn.open_cursor::<tables::EventIndexStateTable>()?;
+        let start = cursor.seek_exact(&())?.map(|t| t.1.first_indexed_block);
+        txn.commit()?;
+        Ok(start)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    fn next_block_with_event_address(
+        &self,
+        address: &v1alpha2::FieldElement,
+        from: u64,
+    ) -> Result<Option<u64>, Self::Error> {
+        let txn = self.db.begin_ro_txn()?;
+        let mut cursor = txn.open_cursor::<tables::EventAddressIndexTable>()?;
+        let block_number = next_indexed_block(&mut cursor, address, from)?;
+        txn.commit()?;
+        Ok(block_number)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    fn next_block_with_event_key(
+        &self,
+        key: &v1alpha2::FieldElement,
+        from: u64,
+    ) -> Result<Option<u64>, Self::Error> {
+        let txn = self.db.begin_ro_txn()?;
+        let mut cursor = txn.open_cursor::<tables::EventKeyIndexTable>()?;
+        let block_number = next_indexed_block(&mut cursor, key, from)?;
+        txn.commit()?;
+        Ok(block_number)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    fn read_reorganizations(&self, limit: usize) -> Result<Vec<ChainReorganization>, Self::Error> {
+        let txn = self.db.begin_ro_txn()?;
+        let mut cursor = txn.open_cursor::<tables::ReorgLogTable>()?;
+        let mut reorganizations = Vec::default();
+        let mut maybe_reorg = cursor.last()?;
+        while let Some((_, reorg)) = maybe_reorg {
+            if reorganizations.len() >= limit {
+                break;
+            }
+            reorganizations.push(reorg);
+            maybe_reorg = cursor.prev()?;
+        }
+        txn.commit()?;
+        Ok(reorganizations)
+    }
 }
 
 impl<'env, 'txn, E: EnvironmentKind> StorageWriter for DatabaseStorageWriter<'env, 'txn, E> {
@@ -327,6 +533,57 @@ impl<'env, 'txn, E: EnvironmentKind> StorageWriter for DatabaseStorageWriter<'en
 
     #[tracing::instrument(level = "trace", skip(self, body))]
     fn write_body(&mut self, id: &GlobalBlockId, body: BlockBody) -> Result<(), Self::Error> {
+        use v1alpha2::transaction::Transaction;
+
+        let mut values = Vec::default();
+        for tx in &body.transactions {
+            match tx.transaction.as_ref() {
+                None => {}
+                Some(Transaction::InvokeV0(tx)) => {
+                    values.extend(tx.contract_address.as_ref());
+                    values.extend(tx.entry_point_selector.as_ref());
+                }
+                Some(Transaction::InvokeV1(tx)) => {
+                    values.extend(tx.sender_address.as_ref());
+                }
+                Some(Transaction::Deploy(tx)) => {
+                    values.extend(tx.class_hash.as_ref());
+                    values.extend(tx.contract_address_salt.as_ref());
+                }
+                Some(Transaction::Declare(tx)) => {
+                    values.extend(tx.class_hash.as_ref());
+                    values.extend(tx.sender_address.as_ref());
+                }
+                Some(Transaction::L1Handler(tx)) => {
+                    values.extend(tx.contract_address.as_ref());
+                    values.extend(tx.entry_point_selector.as_ref());
+                }
+                Some(Transaction::DeployAccount(tx)) => {
+                    values.extend(tx.class_hash.as_ref());
+                    values.extend(tx.contract_address_salt.as_ref());
+                }
+                Some(Transaction::InvokeV3(tx)) => {
+                    values.extend(tx.sender_address.as_ref());
+                }
+                Some(Transaction::DeclareV2(tx)) => {
+                    values.extend(tx.class_hash.as_ref());
+                    values.extend(tx.sender_address.as_ref());
+                    values.extend(tx.compiled_class_hash.as_ref());
+                }
+                Some(Transaction::DeclareV3(tx)) => {
+                    values.extend(tx.class_hash.as_ref());
+                    values.extend(tx.sender_address.as_ref());
+                    values.extend(tx.compiled_class_hash.as_ref());
+                }
+                Some(Transaction::DeployAccountV3(tx)) => {
+                    values.extend(tx.class_hash.as_ref());
+                    values.extend(tx.contract_address_salt.as_ref());
+                }
+            }
+        }
+        let bloom = new_bloom(values);
+        self.update_bloom(id, |blooms| blooms.transactions = Some(bloom.into()))?;
+
         self.body_cursor.seek_exact(id)?;
         self.body_cursor.put(id, &body)?;
         Ok(())
@@ -338,26 +595,57 @@ impl<'env, 'txn, E: EnvironmentKind> StorageWriter for DatabaseStorageWriter<'en
         id: &GlobalBlockId,
         receipts: Vec<v1alpha2::TransactionReceipt>,
     ) -> Result<(), Self::Error> {
-        // compute bloom filter for receipts
-        // the bloomfilter crate expects a positive bitmapsize and items count.
-        // add 1 to the receipts count to avoid a panic.
-        let estimate_items = receipts.len() * 2 + 1;
-        let mut bloom = Bloom::new(256, estimate_items);
+        // compute bloom filters for receipts
+        let mut event_values = Vec::default();
+        let mut message_values = Vec::default();
+
+        // collect the unique values to add to the event indices.
+        let mut addresses = BTreeSet::new();
+        let mut first_keys = BTreeSet::new();
 
         for receipt in receipts.iter() {
             for event in &receipt.events {
                 if let Some(addr) = &event.from_address {
-                    bloom.set(addr);
+                    event_values.push(addr);
+                    addresses.insert(addr.to_bytes());
                 }
-                for key in event.keys.iter() {
-                    bloom.set(key);
+                event_values.extend(event.keys.iter());
+                if let Some(key) = event.keys.first() {
+                    first_keys.insert(key.to_bytes());
                 }
             }
+            for message in &receipt.l2_to_l1_messages {
+                message_values.extend(message.to_address.as_ref());
+            }
+        }
+
+        let events_bloom = new_bloom(event_values);
+        let messages_bloom = new_bloom(message_values);
+        self.update_bloom(id, |blooms| {
+            blooms.events = Some(events_bloom.into());
+            blooms.messages = Some(messages_bloom.into());
+        })?;
+
+        if self.event_index_state_cursor.seek_exact(&())?.is_none() {
+            let state = EventIndexState {
+                first_indexed_block: id.number(),
+            };
+            self.event_index_state_cursor.put(&(), &state)?;
+        }
+
+        for address in addresses {
+            let address = v1alpha2::FieldElement::from_bytes(&address);
+            update_event_index(&mut self.event_address_index_cursor, address, id.number())?;
+        }
+
+        for key in first_keys {
+            let key = v1alpha2::FieldElement::from_bytes(&key);
+            update_event_index(&mut self.event_key_index_cursor, key, id.number())?;
         }
 
         let body = BlockReceipts {
             receipts,
-            bloom: Some(bloom.into()),
+            bloom: None,
         };
         self.receipts_cursor.seek_exact(id)?;
         self.receipts_cursor.put(id, &body)?;
@@ -370,10 +658,109 @@ impl<'env, 'txn, E: EnvironmentKind> StorageWriter for DatabaseStorageWriter<'en
         id: &GlobalBlockId,
         state_update: v1alpha2::StateUpdate,
     ) -> Result<(), Self::Error> {
+        let mut values = Vec::default();
+        if let Some(state_diff) = &state_update.state_diff {
+            for diff in &state_diff.storage_diffs {
+                values.extend(diff.contract_address.as_ref());
+            }
+            for contract in &state_diff.declared_contracts {
+                values.extend(contract.class_hash.as_ref());
+            }
+            for contract in &state_diff.deployed_contracts {
+                values.extend(contract.contract_address.as_ref());
+                values.extend(contract.class_hash.as_ref());
+            }
+            for nonce in &state_diff.nonces {
+                values.extend(nonce.contract_address.as_ref());
+            }
+        }
+        let bloom = new_bloom(values);
+        self.update_bloom(id, |blooms| blooms.state_update = Some(bloom.into()))?;
+
         self.state_update_cursor.seek_exact(id)?;
         self.state_update_cursor.put(id, &state_update)?;
         Ok(())
     }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    fn write_reorganization(&mut self, reorg: ChainReorganization) -> Result<(), Self::Error> {
+        let sequence = self
+            .reorg_log_cursor
+            .last()?
+            .map(|(sequence, _)| sequence + 1)
+            .unwrap_or(0);
+        self.reorg_log_cursor.put(&sequence, &reorg)?;
+        Ok(())
+    }
+}
+
+impl<'env, 'txn, E: EnvironmentKind> DatabaseStorageWriter<'env, 'txn, E> {
+    /// Updates the bloom filters of the given block.
+    fn update_bloom<F>(&mut self, id: &GlobalBlockId, update: F) -> Result<(), libmdbx::Error>
+    where
+        F: FnOnce(&mut BlockBloom),
+    {
+        let mut blooms = self
+            .bloom_cursor
+            .seek_exact(id)?
+            .map(|t| t.1)
+            .unwrap_or_default();
+        update(&mut blooms);
+        self.bloom_cursor.put(id, &blooms)?;
+        Ok(())
+    }
+}
+
+/// Creates a new bloom filter containing the given values.
+pub fn new_bloom(values: Vec<&v1alpha2::FieldElement>) -> Bloom {
+    // the bloomfilter crate expects a positive bitmapsize and items count.
+    // add 1 to the items count to avoid a panic.
+    let mut bloom = Bloom::new(256, values.len() + 1);
+    for value in values {
+        bloom.set(value);
+    }
+    bloom
+}
+
+/// Marks the given block in the index entry for `value`.
+fn update_event_index<T>(
+    cursor: &mut TableCursor<'_, T, RW>,
+    value: v1alpha2::FieldElement,
+    block_number: u64,
+) -> Result<(), libmdbx::Error>
+where
+    T: Table<Key = EventIndexKey, Value = BlockBitmap>,
+{
+    let key = EventIndexKey::new(value, block_number);
+    let mut bitmap = cursor.seek_exact(&key)?.map(|t| t.1).unwrap_or_default();
+    bitmap.set(key.offset(block_number));
+    cursor.put(&key, &bitmap)?;
+    Ok(())
+}
+
+/// Returns the first block number, greater than or equal to `from`, marked in
+/// the index entries for `value`.
+fn next_indexed_block<T, K>(
+    cursor: &mut TableCursor<'_, T, K>,
+    value: &v1alpha2::FieldElement,
+    from: u64,
+) -> Result<Option<u64>, libmdbx::Error>
+where
+    T: Table<Key = EventIndexKey, Value = BlockBitmap>,
+    K: TransactionKind,
+{
+    let mut entry = cursor.seek_range(&EventIndexKey::new(value.clone(), from))?;
+    while let Some((key, bitmap)) = entry {
+        // entries are sorted by value first, so there are no more entries for `value`.
+        if key.value != *value {
+            return Ok(None);
+        }
+        if let Some(offset) = bitmap.next_set(key.offset(from)) {
+            return Ok(Some(key.range_start + offset));
+        }
+        entry = cursor.next()?;
+    }
+    Ok(None)
 }
 
 impl From<RawBloom> for Option<Bloom> {
@@ -1,15 +1,23 @@
-use std::sync::Arc;
+use std::{error::Error, sync::Arc, time::Duration};
 
 use apibara_core::starknet::v1alpha2;
-use apibara_node::db::libmdbx::{Environment, EnvironmentKind, Error as MdxError};
-use tokio::sync::mpsc::{self, Receiver, Sender};
+use apibara_node::{
+    db::libmdbx::{Environment, EnvironmentKind, Error as MdxError},
+    o11y::{self, Counter, KeyValue},
+};
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    time::MissedTickBehavior,
+};
 use tokio_util::sync::CancellationToken;
-use tracing::{info, warn};
+use tracing::{debug, error, info, warn};
 
 use crate::{
-    core::GlobalBlockId,
-    db::{DatabaseStorage, StorageWriter},
-    provider::Provider,
+    core::{BlockHash, GlobalBlockId, InvalidBlock},
+    db::{DatabaseStorage, StorageReader, StorageWriter},
+    ingestion::{BlockIngestionError, Downloader},
+    provider::{BlockId, Provider},
+    supervisor::Task,
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -18,19 +26,62 @@ pub enum HealerError {
     ChannelClosed,
     #[error("database error")]
     Database(#[from] MdxError),
+    #[error("failed to fetch provider data")]
+    Provider(#[source] Box<dyn Error + Send + Sync + 'static>),
+    #[error("failed to download block")]
+    Download(#[from] BlockIngestionError),
+    #[error(transparent)]
+    InvalidBlock(#[from] InvalidBlock),
+    #[error("finalized block {block_id} doesn't match the provider chain")]
+    FinalizedBlockMismatch { block_id: GlobalBlockId },
 }
 
 #[derive(Debug, Clone)]
 pub enum HealerMessage {
     /// The given block is expected to be finalized.
     StatusFinalizedExpected(GlobalBlockId),
+    /// Some data of the given block is missing from storage.
+    BlockDataMissing(GlobalBlockId),
+}
+
+/// Healer configuration.
+#[derive(Debug, Clone)]
+pub struct HealerConfig {
+    /// How often to verify the next range of canonical blocks.
+    pub scan_interval: Duration,
+    /// How many blocks to verify at each scan.
+    pub scan_size: u64,
+    /// How many of the most recently finalized blocks are compared with the
+    /// provider chain when the healer starts. After that, blocks are compared
+    /// once, after they're finalized.
+    pub finalized_check_depth: u64,
 }
 
-/// A service that receives broken blocks and heals them.
+/// A problem found while verifying a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockIssue {
+    /// Some of the block data is not stored.
+    MissingData,
+    /// The block parent is not the previous block in the canonical chain.
+    BrokenParentLink,
+    /// The block is not the one returned by the provider.
+    HashMismatch,
+}
+
+/// A service that verifies the canonical chain and heals broken blocks.
+///
+/// The healer periodically scans a range of the canonical chain, resuming
+/// from where the previous scan stopped, and also heals blocks reported by
+/// the streams.
 pub struct Healer<G: Provider + Send, E: EnvironmentKind> {
-    _provider: Arc<G>,
+    config: HealerConfig,
+    provider: Arc<G>,
+    downloader: Downloader<G>,
     storage: DatabaseStorage<E>,
     rx: Receiver<HealerMessage>,
+    next_scan_block: Option<u64>,
+    next_finalized_check: Option<u64>,
+    metrics: HealerMetrics,
 }
 
 #[derive(Clone)]
@@ -38,24 +89,44 @@ pub struct HealerClient {
     tx: Sender<HealerMessage>,
 }
 
+struct HealerMetrics {
+    scanned_blocks: Counter<u64>,
+    broken_blocks: Counter<u64>,
+    repaired_blocks: Counter<u64>,
+    failed_messages: Counter<u64>,
+}
+
 impl<G, E> Healer<G, E>
 where
     G: Provider + Send,
     E: EnvironmentKind,
 {
-    pub fn new(provider: Arc<G>, db: Arc<Environment<E>>) -> (HealerClient, Self) {
+    pub fn new(
+        provider: Arc<G>,
+        db: Arc<Environment<E>>,
+        config: HealerConfig,
+    ) -> (HealerClient, Self) {
         let storage = DatabaseStorage::new(db);
+        let downloader = Downloader::new(provider.clone());
         let (tx, rx) = mpsc::channel(64);
         let healer = Healer {
-            _provider: provider,
+            config,
+            provider,
+            downloader,
             storage,
             rx,
+            next_scan_block: None,
+            next_finalized_check: None,
+            metrics: HealerMetrics::new(),
         };
         let client = HealerClient { tx };
         (client, healer)
     }
 
-    pub async fn start(mut self, ct: CancellationToken) -> Result<(), HealerError> {
+    pub async fn start(&mut self, ct: CancellationToken) -> Result<(), HealerError> {
+        let mut scan_interval = tokio::time::interval(self.config.scan_interval);
+        scan_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
         loop {
             tokio::select! {
                 _ = ct.cancelled() => {
@@ -63,18 +134,29 @@ where
                 }
                 msg = self.rx.recv() => {
                     let msg = msg.ok_or(HealerError::ChannelClosed)?;
-                    self.handle_message(msg)?;
+                    // messages only concern the reported block, for example a
+                    // rejected block the provider doesn't serve anymore.
+                    if let Err(err) = self.handle_message(msg.clone()).await {
+                        warn!(message = ?msg, err = ?err, "failed to handle healer message");
+                        self.metrics
+                            .failed_messages
+                            .add(&o11y::Context::current(), 1, &[]);
+                    }
+                }
+                _ = scan_interval.tick() => {
+                    self.scan().await?;
                 }
             }
         }
     }
 
-    fn handle_message(&self, message: HealerMessage) -> Result<(), HealerError> {
+    async fn handle_message(&self, message: HealerMessage) -> Result<(), HealerError> {
         info!(message = ?message, "received healer message");
         match message {
             HealerMessage::StatusFinalizedExpected(cursor) => {
                 self.handle_status_finalized_expected(cursor)
             }
+            HealerMessage::BlockDataMissing(cursor) => self.handle_block_data_missing(cursor).await,
         }
     }
 
@@ -84,6 +166,258 @@ where
         txn.commit()?;
         Ok(())
     }
+
+    async fn handle_block_data_missing(&self, cursor: GlobalBlockId) -> Result<(), HealerError> {
+        // pending blocks cannot be downloaded by hash, and the block may
+        // have been healed since the message was sent.
+        if cursor.hash().is_zero() || self.storage.stored_block_data(&cursor)?.is_complete() {
+            return Ok(());
+        }
+
+        self.record_broken_block(BlockIssue::MissingData);
+        self.download_block(&cursor).await?;
+        self.metrics
+            .repaired_blocks
+            .add(&o11y::Context::current(), 1, &[]);
+        Ok(())
+    }
+
+    /// Verifies the next range of the canonical chain.
+    ///
+    /// When the scan reaches the chain head, the next scan restarts from the
+    /// lowest canonical block.
+    #[tracing::instrument(skip(self))]
+    async fn scan(&mut self) -> Result<(), HealerError> {
+        let lowest = match self.storage.lowest_canonical_block()? {
+            None => return Ok(()),
+            Some(block_id) => block_id.number(),
+        };
+        let finalized = self.storage.highest_finalized_block()?;
+
+        if let Some(finalized) = finalized {
+            self.check_finalized(lowest, finalized).await?;
+        }
+
+        let start = self.next_scan_block.unwrap_or(lowest).max(lowest);
+        let end = start + self.config.scan_size;
+        debug!(start = %start, end = %end, "scan canonical chain");
+
+        for number in start..end {
+            let block_id = match self.storage.canonical_block_id(number)? {
+                None => {
+                    info!(lowest = %lowest, head = %number, "canonical chain verified");
+                    self.next_scan_block = None;
+                    return Ok(());
+                }
+                Some(block_id) => block_id,
+            };
+
+            let is_finalized = finalized
+                .map(|f| block_id.number() <= f.number())
+                .unwrap_or(false);
+            self.verify_block(&block_id, is_finalized).await?;
+        }
+
+        self.next_scan_block = Some(end);
+        Ok(())
+    }
+
+    /// Verifies the given canonical block, repairing it if it's broken.
+    async fn verify_block(
+        &self,
+        block_id: &GlobalBlockId,
+        is_finalized: bool,
+    ) -> Result<(), HealerError> {
+        let cx = o11y::Context::current();
+        self.metrics.scanned_blocks.add(&cx, 1, &[]);
+
+        let issue = match self.find_issue(block_id)? {
+            None => return Ok(()),
+            Some(issue) => issue,
+        };
+
+        warn!(block_id = %block_id, issue = ?issue, "found broken block");
+        self.record_broken_block(issue);
+
+        match issue {
+            BlockIssue::MissingData => {
+                // the block hash is correct, download the same block again.
+                self.download_block(block_id).await?;
+            }
+            BlockIssue::BrokenParentLink | BlockIssue::HashMismatch => {
+                // accepted blocks can still change, ingestion takes care of them.
+                if !is_finalized {
+                    return Ok(());
+                }
+                return Err(self.finalized_block_mismatch(block_id, issue));
+            }
+        }
+
+        self.metrics.repaired_blocks.add(&cx, 1, &[]);
+        info!(block_id = %block_id, issue = ?issue, "repaired block");
+        Ok(())
+    }
+
+    fn find_issue(&self, block_id: &GlobalBlockId) -> Result<Option<BlockIssue>, HealerError> {
+        let header = match self.storage.read_header(block_id)? {
+            Some(header) if self.storage.stored_block_data(block_id)?.is_complete() => header,
+            _ => return Ok(Some(BlockIssue::MissingData)),
+        };
+
+        if block_id.number() > 0 {
+            if let Some(parent_id) = self.storage.canonical_block_id(block_id.number() - 1)? {
+                let parent_hash: Option<BlockHash> =
+                    header.parent_block_hash.as_ref().map(Into::into);
+                if parent_hash.as_ref() != Some(parent_id.hash()) {
+                    return Ok(Some(BlockIssue::BrokenParentLink));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Compares the next range of finalized blocks with the provider chain.
+    ///
+    /// Only the blocks finalized since the previous check are compared, starting
+    /// from the most recently finalized blocks when the healer starts.
+    async fn check_finalized(
+        &mut self,
+        lowest: u64,
+        finalized: GlobalBlockId,
+    ) -> Result<(), HealerError> {
+        let head = finalized.number() + 1;
+        let start = self
+            .next_finalized_check
+            .unwrap_or_else(|| head.saturating_sub(self.config.finalized_check_depth))
+            .max(lowest);
+        let end = (start + self.config.scan_size).min(head);
+        debug!(start = %start, end = %end, "compare finalized blocks");
+
+        for number in start..end {
+            let block_id = match self.storage.canonical_block_id(number)? {
+                None => continue,
+                Some(block_id) => block_id,
+            };
+
+            let (_status, header, _body) = self
+                .provider
+                .get_block(&BlockId::Number(number))
+                .await
+                .map_err(HealerError::provider)?;
+            if GlobalBlockId::from_block_header(&header)? != block_id {
+                self.record_broken_block(BlockIssue::HashMismatch);
+                return Err(self.finalized_block_mismatch(&block_id, BlockIssue::HashMismatch));
+            }
+        }
+
+        self.next_finalized_check = Some(end.max(start));
+        Ok(())
+    }
+
+    /// Returns the error for a finalized block that doesn't match the provider chain.
+    ///
+    /// Streams already sent the block as finalized, replacing it silently would
+    /// leave clients with the wrong data.
+    fn finalized_block_mismatch(&self, block_id: &GlobalBlockId, issue: BlockIssue) -> HealerError {
+        error!(
+            block_id = %block_id,
+            issue = ?issue,
+            "finalized block doesn't match the provider chain"
+        );
+        HealerError::FinalizedBlockMismatch {
+            block_id: *block_id,
+        }
+    }
+
+    /// Downloads the given block and stores it, overwriting any existing data.
+    async fn download_block(&self, block_id: &GlobalBlockId) -> Result<(), HealerError> {
+        let (status, header, body, receipts) = self
+            .provider
+            .get_block_with_receipts(&BlockId::Hash(*block_id.hash()))
+            .await
+            .map_err(HealerError::provider)?;
+
+        let mut txn = self.storage.begin_txn()?;
+        self.downloader
+            .finish_ingesting_block(block_id, status, header, body, receipts, &mut txn)
+            .await?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn record_broken_block(&self, issue: BlockIssue) {
+        let cx = o11y::Context::current();
+        self.metrics
+            .broken_blocks
+            .add(&cx, 1, &[KeyValue::new("issue", issue.as_str())]);
+    }
+}
+
+#[apibara_node::async_trait]
+impl<G, E> Task for Healer<G, E>
+where
+    G: Provider + Send + Sync + 'static,
+    E: EnvironmentKind,
+{
+    type Error = HealerError;
+
+    async fn run(&mut self, ct: CancellationToken) -> Result<(), Self::Error> {
+        self.start(ct).await
+    }
+
+    fn is_transient(&self, err: &Self::Error) -> bool {
+        err.is_transient()
+    }
+}
+
+impl HealerError {
+    /// Returns true if the healer can recover from the error by restarting.
+    ///
+    /// Finalized blocks that don't match the provider chain need an operator
+    /// to intervene.
+    pub fn is_transient(&self) -> bool {
+        !matches!(self, HealerError::FinalizedBlockMismatch { .. })
+    }
+
+    fn provider<E>(err: E) -> Self
+    where
+        E: Error + Send + Sync + 'static,
+    {
+        HealerError::Provider(Box::new(err))
+    }
+}
+
+impl Default for HealerConfig {
+    fn default() -> Self {
+        HealerConfig {
+            scan_interval: Duration::from_secs(30),
+            scan_size: 100,
+            finalized_check_depth: 100,
+        }
+    }
+}
+
+impl BlockIssue {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BlockIssue::MissingData => "missing_data",
+            BlockIssue::BrokenParentLink => "broken_parent_link",
+            BlockIssue::HashMismatch => "hash_mismatch",
+        }
+    }
+}
+
+impl HealerMetrics {
+    fn new() -> Self {
+        let meter = o11y::meter("healer");
+        HealerMetrics {
+            scanned_blocks: meter.u64_counter("scanned_blocks").init(),
+            broken_blocks: meter.u64_counter("broken_blocks").init(),
+            repaired_blocks: meter.u64_counter("repaired_blocks").init(),
+            failed_messages: meter.u64_counter("failed_messages").init(),
+        }
+    }
 }
 
 impl HealerClient {
@@ -91,6 +425,10 @@ impl HealerClient {
         self.send_message(HealerMessage::StatusFinalizedExpected(cursor))
     }
 
+    pub fn block_data_missing(&self, cursor: GlobalBlockId) {
+        self.send_message(HealerMessage::BlockDataMissing(cursor))
+    }
+
     fn send_message(&self, message: HealerMessage) {
         // healer is not critical so don't fail if it cannot send
         if let Err(err) = self.tx.try_send(message) {
@@ -98,3 +436,284 @@ impl HealerClient {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use apibara_core::starknet::v1alpha2;
+    use apibara_node::db::{
+        libmdbx::{Environment, NoWriteMap},
+        MdbxEnvironmentExt, MdbxTransactionExt, Table,
+    };
+    use tempfile::TempDir;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        core::GlobalBlockId,
+        db::{tables, DatabaseStorage, StorageReader, StorageWriter},
+        ingestion::Downloader,
+        provider::{BlockId, MockChainProvider, Provider},
+    };
+
+    use super::{Healer, HealerClient, HealerConfig, HealerError, HealerMessage};
+
+    struct TestHealer {
+        provider: Arc<MockChainProvider>,
+        db: Arc<Environment<NoWriteMap>>,
+        storage: DatabaseStorage<NoWriteMap>,
+        healer: Healer<MockChainProvider, NoWriteMap>,
+        client: HealerClient,
+        _datadir: TempDir,
+    }
+
+    impl TestHealer {
+        fn new(provider: MockChainProvider) -> TestHealer {
+            let config = HealerConfig {
+                scan_size: 10,
+                ..HealerConfig::default()
+            };
+            TestHealer::with_config(provider, config)
+        }
+
+        fn with_config(provider: MockChainProvider, config: HealerConfig) -> TestHealer {
+            let datadir = tempfile::tempdir().unwrap();
+            let db = Environment::<NoWriteMap>::builder()
+                .with_size_gib(1, 10)
+                .open(datadir.path())
+                .unwrap();
+            let txn = db.begin_rw_txn().unwrap();
+            tables::ensure(&txn).unwrap();
+            txn.commit().unwrap();
+
+            let db = Arc::new(db);
+            let provider = Arc::new(provider);
+            let (client, healer) = Healer::new(provider.clone(), db.clone(), config);
+            TestHealer {
+                provider,
+                storage: DatabaseStorage::new(db.clone()),
+                db,
+                healer,
+                client,
+                _datadir: datadir,
+            }
+        }
+
+        /// Stores the canonical chain of the provider, up to `number` (inclusive).
+        async fn ingest(&self, number: u64) -> Vec<GlobalBlockId> {
+            let downloader = Downloader::new(self.provider.clone());
+            let mut blocks = Vec::default();
+            for n in 0..=number {
+                let (status, header, body, receipts) = self
+                    .provider
+                    .get_block_with_receipts(&BlockId::Number(n))
+                    .await
+                    .unwrap();
+                let block_id = GlobalBlockId::from_block_header(&header).unwrap();
+                let mut txn = self.storage.begin_txn().unwrap();
+                downloader
+                    .finish_ingesting_block(&block_id, status, header, body, receipts, &mut txn)
+                    .await
+                    .unwrap();
+                txn.extend_canonical_chain(&block_id).unwrap();
+                txn.commit().unwrap();
+                blocks.push(block_id);
+            }
+            blocks
+        }
+
+        /// Deletes the block data stored in table `T`.
+        fn delete<T: Table<Key = GlobalBlockId>>(&self, block_id: &GlobalBlockId) {
+            let txn = self.db.begin_rw_txn().unwrap();
+            let mut cursor = txn.open_cursor::<T>().unwrap();
+            cursor.seek_exact(block_id).unwrap().unwrap();
+            cursor.del().unwrap();
+            drop(cursor);
+            txn.commit().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repair_missing_block_data() {
+        let provider = MockChainProvider::new();
+        provider.produce_blocks(3);
+        let mut test = TestHealer::new(provider);
+        let blocks = test.ingest(3).await;
+
+        test.delete::<tables::BlockBodyTable>(&blocks[2]);
+        assert!(!test.storage.stored_block_data(&blocks[2]).unwrap().body);
+
+        test.healer.scan().await.unwrap();
+        assert!(test
+            .storage
+            .stored_block_data(&blocks[2])
+            .unwrap()
+            .is_complete());
+        assert_eq!(test.storage.read_body(&blocks[2]).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_repair_missing_block_status() {
+        let provider = MockChainProvider::new();
+        provider.produce_blocks(3);
+        let mut test = TestHealer::new(provider);
+        let blocks = test.ingest(3).await;
+
+        test.delete::<tables::BlockStatusTable>(&blocks[2]);
+        assert!(!test.storage.stored_block_data(&blocks[2]).unwrap().status);
+
+        test.healer.scan().await.unwrap();
+        assert_eq!(
+            test.storage.read_status(&blocks[2]).unwrap(),
+            Some(v1alpha2::BlockStatus::AcceptedOnL2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_halt_on_mismatched_finalized_blocks() {
+        let provider = MockChainProvider::new();
+        provider.produce_blocks(3);
+        provider.finalize(3);
+        let mut test = TestHealer::new(provider);
+        let old_blocks = test.ingest(3).await;
+
+        // the node stored blocks that are not part of the chain.
+        test.provider.reorg(2);
+        test.provider.produce_blocks(2);
+        test.provider.finalize(3);
+
+        let err = test.healer.scan().await.unwrap_err();
+        assert!(matches!(
+            err,
+            HealerError::FinalizedBlockMismatch { block_id } if block_id == old_blocks[2]
+        ));
+        assert!(!err.is_transient());
+
+        // the stored chain is left untouched.
+        for (number, block_id) in old_blocks.iter().enumerate() {
+            assert_eq!(
+                test.storage.canonical_block_id(number as u64).unwrap(),
+                Some(*block_id)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compare_finalized_blocks_once() {
+        let provider = MockChainProvider::new();
+        provider.produce_blocks(14);
+        provider.finalize(14);
+        let config = HealerConfig {
+            scan_size: 10,
+            finalized_check_depth: 5,
+            ..HealerConfig::default()
+        };
+        let mut test = TestHealer::with_config(provider, config);
+        test.ingest(14).await;
+
+        // only the most recently finalized blocks are compared at start.
+        let requests = test.provider.request_count();
+        test.healer.scan().await.unwrap();
+        assert_eq!(test.provider.request_count(), requests + 5);
+        test.healer.scan().await.unwrap();
+        assert_eq!(test.provider.request_count(), requests + 5);
+
+        // then each newly finalized block is compared once.
+        test.provider.produce_blocks(2);
+        test.provider.finalize(16);
+        test.ingest(16).await;
+        let requests = test.provider.request_count();
+        test.healer.scan().await.unwrap();
+        assert_eq!(test.provider.request_count(), requests + 2);
+    }
+
+    #[tokio::test]
+    async fn test_keep_accepted_blocks() {
+        let provider = MockChainProvider::new();
+        provider.produce_blocks(3);
+        let mut test = TestHealer::new(provider);
+        let blocks = test.ingest(3).await;
+
+        // accepted blocks are left to block ingestion.
+        test.provider.reorg(2);
+        test.provider.produce_blocks(2);
+
+        test.healer.scan().await.unwrap();
+        for (number, block_id) in blocks.iter().enumerate() {
+            assert_eq!(
+                test.storage.canonical_block_id(number as u64).unwrap(),
+                Some(*block_id)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_resumes_from_previous_range() {
+        let provider = MockChainProvider::new();
+        provider.produce_blocks(14);
+        let mut test = TestHealer::new(provider);
+        let blocks = test.ingest(14).await;
+
+        test.healer.scan().await.unwrap();
+        assert_eq!(test.healer.next_scan_block, Some(10));
+
+        test.delete::<tables::BlockBodyTable>(&blocks[12]);
+        test.healer.scan().await.unwrap();
+        assert_eq!(test.healer.next_scan_block, None);
+        assert!(test
+            .storage
+            .stored_block_data(&blocks[12])
+            .unwrap()
+            .is_complete());
+    }
+
+    #[tokio::test]
+    async fn test_heal_block_reported_by_stream() {
+        let provider = MockChainProvider::new();
+        provider.produce_blocks(3);
+        let test = TestHealer::new(provider);
+        let blocks = test.ingest(3).await;
+
+        test.delete::<tables::BlockBodyTable>(&blocks[1]);
+        test.healer
+            .handle_message(HealerMessage::BlockDataMissing(blocks[1]))
+            .await
+            .unwrap();
+        assert!(test
+            .storage
+            .stored_block_data(&blocks[1])
+            .unwrap()
+            .is_complete());
+    }
+
+    #[tokio::test]
+    async fn test_keep_running_after_failed_message() {
+        let provider = MockChainProvider::new();
+        provider.produce_blocks(3);
+        let mut test = TestHealer::new(provider);
+        let blocks = test.ingest(3).await;
+
+        // a block the provider doesn't serve cannot be repaired.
+        let unknown_block =
+            GlobalBlockId::new(2, v1alpha2::FieldElement::from_u64(u64::MAX).into());
+        test.client.block_data_missing(unknown_block);
+        test.client.status_finalized_expected(blocks[1]);
+
+        let ct = CancellationToken::new();
+        let storage = &test.storage;
+        let (result, _) = tokio::join!(test.healer.start(ct.clone()), async {
+            // messages are handled in order, so the healer is still running.
+            tokio::time::timeout(Duration::from_secs(10), async {
+                while storage.read_status(&blocks[1]).unwrap()
+                    != Some(v1alpha2::BlockStatus::AcceptedOnL1)
+                {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("timeout waiting for healer");
+            ct.cancel();
+        });
+        result.unwrap();
+    }
+}
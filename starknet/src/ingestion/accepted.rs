@@ -1,9 +1,16 @@
 //! Ingest accepted block data.
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use apibara_node::db::libmdbx::EnvironmentKind;
+use apibara_core::node::v1alpha2::ChainReorganization;
+use apibara_node::{
+    db::libmdbx::EnvironmentKind,
+    o11y::{self, Counter, KeyValue},
+};
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, info, warn};
+use tracing::{debug, error, info, warn};
 
 use crate::{
     core::GlobalBlockId,
@@ -34,6 +41,7 @@ struct AcceptedBlockIngestionImpl<G: Provider + Send, E: EnvironmentKind> {
     downloader: Downloader<G>,
     storage: DatabaseStorage<E>,
     publisher: IngestionStreamPublisher,
+    reorg_counter: Counter<u64>,
 }
 
 enum TickResult {
@@ -57,7 +65,7 @@ where
         config: BlockIngestionConfig,
         publisher: IngestionStreamPublisher,
     ) -> Self {
-        let downloader = Downloader::new(provider.clone(), config.rpc_concurrency);
+        let downloader = Downloader::new(provider.clone());
         AcceptedBlockIngestion {
             config,
             provider,
@@ -95,6 +103,7 @@ where
             storage: self.storage,
             downloader: self.downloader,
             publisher: self.publisher,
+            reorg_counter: new_reorg_counter(),
         };
         ingestion.start(ct).await
     }
@@ -249,13 +258,17 @@ where
         // some node configurations don't support pending data.
         // in that case, simply ignore any error.
 
-        match self.provider.get_block(&BlockId::Pending).await {
+        match self
+            .provider
+            .get_block_with_receipts(&BlockId::Pending)
+            .await
+        {
             Err(_) => {
                 // cannot set pending ingested here because pathfinder returns
                 // an error if the pending block is not prepared yet.
                 Ok(())
             }
-            Ok((status, mut header, body)) => {
+            Ok((status, mut header, body, receipts)) => {
                 // pending block is not what was expected. do nothing.
                 let is_next_pending_block = if let Some(hash) = header.parent_block_hash.as_ref() {
                     *self.current_head.hash() == hash.into()
@@ -274,7 +287,7 @@ where
                 let new_block_id = GlobalBlockId::from_block_header(&header)?;
                 let mut txn = self.storage.begin_txn()?;
                 self.downloader
-                    .finish_ingesting_block(&new_block_id, status, header, body, &mut txn)
+                    .finish_ingesting_block(&new_block_id, status, header, body, receipts, &mut txn)
                     .await?;
                 txn.commit()?;
 
@@ -291,10 +304,11 @@ where
         &self,
         number: u64,
     ) -> Result<Option<GlobalBlockId>, BlockIngestionError> {
-        let global_id = self
-            .storage
-            .canonical_block_id(number)?
-            .ok_or(BlockIngestionError::InconsistentDatabase)?;
+        // blocks that were not ingested yet cannot be marked as finalized.
+        let global_id = match self.storage.canonical_block_id(number)? {
+            None => return Ok(None),
+            Some(global_id) => global_id,
+        };
         let block_id = BlockId::Hash(*global_id.hash());
         let (status, _header, _body) = self
             .provider
@@ -322,11 +336,11 @@ where
             "ingest block by number"
         );
         let block_id = BlockId::Number(number);
-        let (status, header, body) = {
+        let (status, header, body, receipts) = {
             loop {
                 // if the node is not fully synced it will fail to fetch the block
                 // in that case, simply wait a bit and retry.
-                match self.provider.get_block(&block_id).await {
+                match self.provider.get_block_with_receipts(&block_id).await {
                     Ok(result) => break result,
                     Err(err) if err.is_block_not_found() => {
                         warn!("node is not fully synced");
@@ -350,7 +364,7 @@ where
         // write block data to storage
         let mut txn = self.storage.begin_txn()?;
         self.downloader
-            .finish_ingesting_block(&new_block_id, status, header, body, &mut txn)
+            .finish_ingesting_block(&new_block_id, status, header, body, receipts, &mut txn)
             .await?;
         txn.commit()?;
 
@@ -361,6 +375,10 @@ where
     }
 
     /// Shrink the old canonical chain until it joins with the new canonical chain.
+    ///
+    /// If more than `max_reorg_depth` blocks need to be removed, the chain is
+    /// left untouched, the reorganization is logged as rejected and ingestion
+    /// fails with [BlockIngestionError::ReorgTooDeep].
     #[tracing::instrument(skip(self))]
     async fn shrink_diverging_chain(&mut self) -> Result<TickResult, BlockIngestionError> {
         info!(
@@ -371,6 +389,7 @@ where
 
         let mut txn = self.storage.begin_txn()?;
         let mut ingested_tip = self.previous;
+        let mut depth = 0;
 
         loop {
             let belongs_to_new_canonical_chain =
@@ -399,7 +418,25 @@ where
                 break;
             }
 
+            if depth >= self.config.max_reorg_depth {
+                // drop the transaction so that no block is removed.
+                drop(txn);
+                error!(
+                    previous = %self.previous,
+                    current = %self.current_head,
+                    max_depth = %self.config.max_reorg_depth,
+                    "chain reorganization is too deep"
+                );
+                self.record_reorg("too_deep");
+                // the current tip doesn't belong to the new chain either.
+                self.write_rejected_reorganization(depth + 1)?;
+                return Err(BlockIngestionError::ReorgTooDeep {
+                    max_depth: self.config.max_reorg_depth,
+                });
+            }
+
             txn.reject_block_from_canonical_chain(&ingested_tip)?;
+            depth += 1;
 
             // header must exist in the database
             let header = self
@@ -416,8 +453,30 @@ where
             ingested_tip = GlobalBlockId::new(header.block_number - 1, parent_hash);
         }
 
+        if depth > 0 {
+            txn.write_reorganization(ChainReorganization {
+                old_tip: Some(self.previous.to_cursor()),
+                common_ancestor: Some(ingested_tip.to_cursor()),
+                depth,
+                timestamp: unix_timestamp(),
+                new_tip: Some(self.current_head.to_cursor()),
+                rejected: false,
+            })?;
+        }
+
         txn.commit()?;
 
+        if depth > 0 {
+            warn!(
+                old_tip = %self.previous,
+                common_ancestor = %ingested_tip,
+                new_tip = %self.current_head,
+                depth = %depth,
+                "chain reorganization"
+            );
+            self.record_reorg("applied");
+        }
+
         // `ingested_tip` is the new chain root, that is the highest common block
         // between the old canonical chain and the new canonical chain.
         // restart ingestion from the new canonical chain head
@@ -426,4 +485,52 @@ where
 
         Ok(TickResult::MoreToSync)
     }
+
+    /// Logs a reorganization that was not applied, so that operators can find it.
+    ///
+    /// Ingestion halts on the same reorganization after restarting, so it's
+    /// only logged once.
+    fn write_rejected_reorganization(&self, depth: u64) -> Result<(), BlockIngestionError> {
+        let old_tip = Some(self.previous.to_cursor());
+        let already_logged = self
+            .storage
+            .read_reorganizations(1)?
+            .first()
+            .map(|reorg| reorg.rejected && reorg.old_tip == old_tip)
+            .unwrap_or(false);
+        if already_logged {
+            return Ok(());
+        }
+
+        let mut txn = self.storage.begin_txn()?;
+        txn.write_reorganization(ChainReorganization {
+            old_tip,
+            common_ancestor: None,
+            depth,
+            timestamp: unix_timestamp(),
+            new_tip: Some(self.current_head.to_cursor()),
+            rejected: true,
+        })?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn record_reorg(&self, outcome: &'static str) {
+        let cx = o11y::Context::current();
+        self.reorg_counter
+            .add(&cx, 1, &[KeyValue::new("outcome", outcome)]);
+    }
+}
+
+/// Returns the current time, in seconds since the unix epoch.
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn new_reorg_counter() -> Counter<u64> {
+    let meter = o11y::meter("ingestion");
+    meter.u64_counter("chain_reorganization").init()
 }
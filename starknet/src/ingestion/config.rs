@@ -1,20 +1,25 @@
 //! Block ingestion configuration.
 use std::time::Duration;
 
+/// Default maximum number of blocks removed by a single chain reorganization.
+pub const DEFAULT_MAX_REORG_DEPTH: u64 = 100;
+
 /// Block ingestion configuration.
 #[derive(Debug, Clone)]
 pub struct BlockIngestionConfig {
-    /// Concurrency for RPC requests.
-    pub rpc_concurrency: usize,
     /// How often to refresh head block.
     pub head_refresh_interval: Duration,
+    /// Maximum number of blocks a chain reorganization can remove.
+    ///
+    /// Deeper reorganizations halt ingestion instead of being applied.
+    pub max_reorg_depth: u64,
 }
 
 impl Default for BlockIngestionConfig {
     fn default() -> Self {
         BlockIngestionConfig {
-            rpc_concurrency: 16,
             head_refresh_interval: Duration::from_secs(3),
+            max_reorg_depth: DEFAULT_MAX_REORG_DEPTH,
         }
     }
 }
@@ -3,7 +3,6 @@
 use std::sync::Arc;
 
 use apibara_core::starknet::v1alpha2;
-use futures::{stream, StreamExt};
 
 use crate::{
     core::GlobalBlockId,
@@ -15,18 +14,14 @@ use super::BlockIngestionError;
 
 pub struct Downloader<G: Provider + Send> {
     provider: Arc<G>,
-    receipt_concurrency: usize,
 }
 
 impl<G> Downloader<G>
 where
     G: Provider + Send,
 {
-    pub fn new(provider: Arc<G>, receipt_concurrency: usize) -> Self {
-        Downloader {
-            provider,
-            receipt_concurrency,
-        }
+    pub fn new(provider: Arc<G>) -> Self {
+        Downloader { provider }
     }
 
     pub async fn finish_ingesting_block<W: StorageWriter>(
@@ -35,51 +30,22 @@ where
         status: v1alpha2::BlockStatus,
         header: v1alpha2::BlockHeader,
         body: BlockBody,
+        mut receipts: Vec<v1alpha2::TransactionReceipt>,
         writer: &mut W,
     ) -> Result<(), BlockIngestionError>
     where
         BlockIngestionError: From<W::Error>,
     {
-        // download state update, receipts
-        let hashes = body
-            .transactions
-            .iter()
-            .map(|tx| {
-                let tx_hash = tx
-                    .meta
-                    .as_ref()
-                    .ok_or(BlockIngestionError::MalformedTransaction)?
-                    .hash
-                    .clone();
-                Ok(tx_hash)
-            })
-            .collect::<Result<Vec<_>, BlockIngestionError>>()?;
-
-        let receipts = stream::iter(hashes)
-            .enumerate()
-            .map(|(tx_idx, tx_hash)| {
-                let provider = &self.provider;
-                async move {
-                    let tx_hash = tx_hash.ok_or(BlockIngestionError::MalformedTransaction)?;
-                    provider
-                        .get_transaction_receipt(&tx_hash)
-                        .await
-                        .map(|mut r| {
-                            // update transaction index inside a map or the type checker
-                            // will complain about the closure return type.
-                            r.transaction_index = tx_idx as u64;
-                            r
-                        })
-                        .map_err(BlockIngestionError::provider)
-                }
-            })
-            .buffer_unordered(self.receipt_concurrency);
+        if receipts.len() != body.transactions.len() {
+            return Err(BlockIngestionError::ReceiptCountMismatch {
+                transactions: body.transactions.len(),
+                receipts: receipts.len(),
+            });
+        }
 
-        let receipts = receipts
-            .collect::<Vec<_>>()
-            .await
-            .into_iter()
-            .collect::<Result<Vec<_>, BlockIngestionError>>()?;
+        for (tx_idx, receipt) in receipts.iter_mut().enumerate() {
+            receipt.transaction_index = tx_idx as u64;
+        }
 
         // pathfinder doesn't support state update for pending data.
         let state_update = if !global_id.hash().is_zero() {
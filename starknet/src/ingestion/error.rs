@@ -16,6 +16,11 @@ pub enum BlockIngestionError {
     MissingBlockHash,
     #[error("transaction is missing data")]
     MalformedTransaction,
+    #[error("block has {transactions} transactions but {receipts} receipts")]
+    ReceiptCountMismatch {
+        transactions: usize,
+        receipts: usize,
+    },
     #[error("database is in an inconsistent state")]
     InconsistentDatabase,
     #[error("tried to access a block as canonical, but it's not")]
@@ -26,9 +31,19 @@ pub enum BlockIngestionError {
     InvalidBlock(#[from] InvalidBlock),
     #[error("failed to publish an ingestion stream message")]
     IngestionStreamPublish,
+    #[error("chain reorganization deeper than {max_depth} blocks")]
+    ReorgTooDeep { max_depth: u64 },
 }
 
 impl BlockIngestionError {
+    /// Returns true if ingestion can recover from the error by restarting.
+    ///
+    /// Chain reorganizations that are too deep need an operator to intervene,
+    /// restarting ingestion only finds the same reorganization again.
+    pub fn is_transient(&self) -> bool {
+        !matches!(self, BlockIngestionError::ReorgTooDeep { .. })
+    }
+
     pub(crate) fn provider<E>(err: E) -> Self
     where
         E: Error + Send + Sync + 'static,
@@ -44,7 +44,7 @@ where
         config: BlockIngestionConfig,
         publisher: IngestionStreamPublisher,
     ) -> Self {
-        let downloader = Downloader::new(provider.clone(), config.rpc_concurrency);
+        let downloader = Downloader::new(provider.clone());
         FinalizedBlockIngestion {
             config,
             provider,
@@ -112,13 +112,14 @@ where
             "ingest block by number"
         );
         let block_id = BlockId::Number(number);
-        let (status, header, body) = match self.provider.get_block(&block_id).await {
-            Ok(result) => result,
-            Err(err) if err.is_block_not_found() => {
-                return Ok(IngestResult::RetryWithDelay(Duration::from_secs(60)))
-            }
-            Err(err) => return Err(BlockIngestionError::provider(err)),
-        };
+        let (status, header, body, receipts) =
+            match self.provider.get_block_with_receipts(&block_id).await {
+                Ok(result) => result,
+                Err(err) if err.is_block_not_found() => {
+                    return Ok(IngestResult::RetryWithDelay(Duration::from_secs(60)))
+                }
+                Err(err) => return Err(BlockIngestionError::provider(err)),
+            };
 
         let global_id = GlobalBlockId::from_block_header(&header)?;
 
@@ -128,7 +129,7 @@ where
 
         let mut txn = self.storage.begin_txn()?;
         self.downloader
-            .finish_ingesting_block(&global_id, status, header, body, &mut txn)
+            .finish_ingesting_block(&global_id, status, header, body, receipts, &mut txn)
             .await?;
         txn.extend_canonical_chain(&global_id)?;
         txn.commit()?;
@@ -9,14 +9,15 @@ mod subscription;
 use std::sync::Arc;
 
 use apibara_node::db::libmdbx::{Environment, EnvironmentKind};
-use tokio::time::Duration;
 use tokio_util::sync::CancellationToken;
-use tracing::error;
+use tracing::warn;
 
-use crate::{db::DatabaseStorage, provider::Provider};
+use crate::{db::DatabaseStorage, provider::Provider, supervisor::Task};
 
 use self::{started::StartedBlockIngestion, subscription::IngestionStreamPublisher};
 
+pub(crate) use self::downloader::Downloader;
+
 pub use self::{
     config::BlockIngestionConfig,
     error::BlockIngestionError,
@@ -53,32 +54,42 @@ where
     }
 
     /// Start ingesting blocks.
-    pub async fn start(self, ct: CancellationToken) -> Result<(), BlockIngestionError> {
-        loop {
-            let storage = DatabaseStorage::new(self.db.clone());
-            let result = StartedBlockIngestion::new(
-                self.provider.clone(),
-                storage,
-                self.config.clone(),
-                self.publisher.clone(),
-            )
-            .start(ct.clone())
-            .await;
-
-            match result {
-                Ok(_) => {
-                    if !ct.is_cancelled() {
-                        error!("block ingestion stopped without error");
-                    }
-                    return Ok(());
-                }
-                Err(err) => {
-                    error!(error = ?err, "block ingestion terminated with error");
-                }
-            }
+    ///
+    /// Returns when cancelled or when ingestion fails.
+    pub async fn start(&self, ct: CancellationToken) -> Result<(), BlockIngestionError> {
+        let storage = DatabaseStorage::new(self.db.clone());
+        self.publisher.set_running(true);
+        let result = StartedBlockIngestion::new(
+            self.provider.clone(),
+            storage,
+            self.config.clone(),
+            self.publisher.clone(),
+        )
+        .start(ct.clone())
+        .await;
+        self.publisher.set_running(false);
 
-            // TODO: would be better if we exponentially backed off.
-            tokio::time::sleep(Duration::from_secs(10)).await;
+        if result.is_ok() && !ct.is_cancelled() {
+            warn!("block ingestion stopped without error");
         }
+
+        result
+    }
+}
+
+#[apibara_node::async_trait]
+impl<G, E> Task for BlockIngestion<G, E>
+where
+    G: Provider + Send + Sync + 'static,
+    E: EnvironmentKind,
+{
+    type Error = BlockIngestionError;
+
+    async fn run(&mut self, ct: CancellationToken) -> Result<(), Self::Error> {
+        self.start(ct).await
+    }
+
+    fn is_transient(&self, err: &Self::Error) -> bool {
+        err.is_transient()
     }
 }
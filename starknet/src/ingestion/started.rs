@@ -37,7 +37,7 @@ where
         config: BlockIngestionConfig,
         publisher: IngestionStreamPublisher,
     ) -> Self {
-        let downloader = Downloader::new(provider.clone(), config.rpc_concurrency);
+        let downloader = Downloader::new(provider.clone());
         StartedBlockIngestion {
             config,
             provider,
@@ -133,9 +133,9 @@ where
     async fn ingest_genesis_block(&self) -> Result<GlobalBlockId, BlockIngestionError> {
         info!("ingest genesis block");
         let block_id = BlockId::Number(0);
-        let (status, header, body) = self
+        let (status, header, body, receipts) = self
             .provider
-            .get_block(&block_id)
+            .get_block_with_receipts(&block_id)
             .await
             .map_err(BlockIngestionError::provider)?;
 
@@ -144,7 +144,7 @@ where
 
         let mut txn = self.storage.begin_txn()?;
         self.downloader
-            .finish_ingesting_block(&global_id, status, header, body, &mut txn)
+            .finish_ingesting_block(&global_id, status, header, body, receipts, &mut txn)
             .await?;
         txn.extend_canonical_chain(&global_id)?;
         txn.commit()?;
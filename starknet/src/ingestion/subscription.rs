@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
 use tokio::sync::broadcast;
 use tokio_stream::wrappers::BroadcastStream;
@@ -14,10 +17,12 @@ pub type IngestionStream = BroadcastStream<IngestionMessage>;
 pub struct IngestionStreamPublisher {
     tx: Arc<broadcast::Sender<IngestionMessage>>,
     _rx: Arc<broadcast::Receiver<IngestionMessage>>,
+    running: Arc<AtomicBool>,
 }
 
 pub struct IngestionStreamClient {
     tx: Arc<broadcast::Sender<IngestionMessage>>,
+    running: Arc<AtomicBool>,
 }
 
 impl IngestionStreamPublisher {
@@ -25,15 +30,22 @@ impl IngestionStreamPublisher {
         let (tx, rx) = broadcast::channel(128);
         let tx = Arc::new(tx);
         let rx = Arc::new(rx);
+        let running = Arc::new(AtomicBool::new(false));
 
         let manager = IngestionStreamPublisher {
             tx: tx.clone(),
             _rx: rx,
+            running: running.clone(),
         };
-        let client = IngestionStreamClient { tx };
+        let client = IngestionStreamClient { tx, running };
         (client, manager)
     }
 
+    /// Signals whether block ingestion is running.
+    pub fn set_running(&self, running: bool) {
+        self.running.store(running, Ordering::SeqCst);
+    }
+
     pub fn publish_finalized(&self, id: GlobalBlockId) -> Result<(), BlockIngestionError> {
         self.publish(IngestionMessage::Finalized(id))
     }
@@ -63,4 +75,9 @@ impl IngestionStreamClient {
         debug!("subscribing to ingestion stream");
         BroadcastStream::new(self.tx.subscribe())
     }
+
+    /// Returns true if block ingestion is running.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
 }
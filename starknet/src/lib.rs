@@ -6,8 +6,9 @@ pub mod node;
 pub mod provider;
 pub mod server;
 pub mod stream;
+pub mod supervisor;
 
 pub use crate::node::StarkNetNode;
-pub use crate::provider::HttpProvider;
+pub use crate::provider::{FailoverProvider, FeederGatewayProvider, HttpProvider};
 
 pub use apibara_node::db::libmdbx::NoWriteMap;
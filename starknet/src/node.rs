@@ -1,26 +1,24 @@
-use std::{
-    fs,
-    marker::PhantomData,
-    net::{AddrParseError, SocketAddr},
-    path::PathBuf,
-    sync::Arc,
-    time::Duration,
-};
+use std::{fs, marker::PhantomData, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 
 use apibara_node::db::{
     default_data_dir,
     libmdbx::{self, Environment, EnvironmentKind},
     MdbxEnvironmentExt,
 };
+use futures::future;
+use tokio::task::JoinError;
 use tokio_util::sync::CancellationToken;
+use tonic::transport::ServerTlsConfig;
 use tracing::{info, warn};
 
 use crate::{
     db::tables,
-    healer::{Healer, HealerError},
+    healer::{Healer, HealerConfig, HealerError},
     ingestion::{BlockIngestion, BlockIngestionConfig, BlockIngestionError},
     provider::{HttpProviderError, Provider},
-    server::{RequestObserver, Server, ServerError, SimpleRequestObserver},
+    server::{RequestObserver, Server, ServerError, SimpleRequestObserver, DEFAULT_MAX_HEAD_LAG},
+    stream::DEFAULT_PROGRESS_INTERVAL,
+    supervisor::{Supervisor, SupervisorConfig},
     HttpProvider,
 };
 
@@ -33,6 +31,13 @@ where
     db: Arc<Environment<E>>,
     sequencer_provider: Arc<G>,
     request_span: O,
+    block_ingestion_config: BlockIngestionConfig,
+    healer_config: HealerConfig,
+    server_addr: SocketAddr,
+    server_tls: Option<ServerTlsConfig>,
+    stream_progress_interval: Duration,
+    max_head_lag: u64,
+    supervisor_config: SupervisorConfig,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -45,8 +50,8 @@ pub enum StarkNetNodeError {
     Server(#[from] ServerError),
     #[error("healer error")]
     Healer(#[from] HealerError),
-    #[error("error parsing server address")]
-    AddressParseError(#[from] AddrParseError),
+    #[error("error awaiting task")]
+    Task(#[from] JoinError),
 }
 
 impl<G, O, E> StarkNetNode<G, O, E>
@@ -58,17 +63,41 @@ where
     /// Creates a new builder, used to configure the node.
     pub fn builder(
         url: &str,
-    ) -> Result<StarkNetNodeBuilder<SimpleRequestObserver, E>, StarkNetNodeBuilderError> {
-        StarkNetNodeBuilder::<SimpleRequestObserver, E>::new(url)
+    ) -> Result<StarkNetNodeBuilder<HttpProvider, SimpleRequestObserver, E>, StarkNetNodeBuilderError>
+    {
+        StarkNetNodeBuilder::<HttpProvider, SimpleRequestObserver, E>::new(url)
+    }
+
+    /// Creates a new builder that fetches data using the given provider.
+    pub fn builder_with_provider(provider: G) -> StarkNetNodeBuilder<G, SimpleRequestObserver, E> {
+        StarkNetNodeBuilder::<G, SimpleRequestObserver, E>::from_provider(provider)
     }
 
-    pub(crate) fn new(db: Environment<E>, sequencer_provider: G, request_span: O) -> Self {
+    pub(crate) fn new(
+        db: Environment<E>,
+        sequencer_provider: G,
+        request_span: O,
+        block_ingestion_config: BlockIngestionConfig,
+        healer_config: HealerConfig,
+        server_addr: SocketAddr,
+        server_tls: Option<ServerTlsConfig>,
+        stream_progress_interval: Duration,
+        max_head_lag: u64,
+        supervisor_config: SupervisorConfig,
+    ) -> Self {
         let db = Arc::new(db);
         let sequencer_provider = Arc::new(sequencer_provider);
         StarkNetNode {
             db,
             sequencer_provider,
             request_span,
+            block_ingestion_config,
+            healer_config,
+            server_addr,
+            server_tls,
+            stream_progress_interval,
+            max_head_lag,
+            supervisor_config,
         }
     }
 
@@ -85,35 +114,63 @@ where
             self.wait_for_rpc(ct.clone()).await?;
         }
 
-        // TODO: config from command line
-        let (block_ingestion_client, block_ingestion) = BlockIngestion::new(
+        // cancel the remaining tasks when one of them terminates.
+        let ct = ct.child_token();
+
+        let (block_ingestion_client, mut block_ingestion) = BlockIngestion::new(
             self.sequencer_provider.clone(),
             self.db.clone(),
-            BlockIngestionConfig::default(),
+            self.block_ingestion_config,
         );
 
-        let mut block_ingestion_handle = tokio::spawn({
+        let block_ingestion_handle = tokio::spawn({
             let ct = ct.clone();
+            let supervisor = Supervisor::new("block_ingestion", self.supervisor_config.clone());
             async move {
-                block_ingestion
-                    .start(ct)
-                    .await
-                    .map_err(StarkNetNodeError::BlockIngestion)
+                match supervisor.run(&mut block_ingestion, ct.clone()).await {
+                    // keep the server running so that clients can still read the stored
+                    // chain and the reorganization log. the stream is reported as not serving.
+                    Err(err) if !err.is_transient() => {
+                        warn!("block ingestion halted, server keeps running");
+                        ct.cancelled().await;
+                        Err(StarkNetNodeError::BlockIngestion(err))
+                    }
+                    result => result.map_err(StarkNetNodeError::BlockIngestion),
+                }
             }
         });
 
-        let (healer_client, healer) = Healer::new(self.sequencer_provider.clone(), self.db.clone());
+        let (healer_client, mut healer) = Healer::new(
+            self.sequencer_provider.clone(),
+            self.db.clone(),
+            self.healer_config,
+        );
 
-        let mut healer_handle = tokio::spawn({
+        let healer_handle = tokio::spawn({
             let ct = ct.clone();
-            async move { healer.start(ct).await.map_err(StarkNetNodeError::Healer) }
+            let supervisor = Supervisor::new("healer", self.supervisor_config);
+            async move {
+                supervisor
+                    .run(&mut healer, ct)
+                    .await
+                    .map_err(StarkNetNodeError::Healer)
+            }
         });
 
-        // TODO: configure from command line
-        let server_addr: SocketAddr = "0.0.0.0:7171".parse()?;
-        let server = Server::<E, O>::new(self.db.clone(), block_ingestion_client, healer_client)
-            .with_request_observer(self.request_span);
-        let mut server_handle = tokio::spawn({
+        let server_addr = self.server_addr;
+        let mut server = Server::<G, E, O>::new(
+            self.db.clone(),
+            self.sequencer_provider.clone(),
+            block_ingestion_client,
+            healer_client,
+        )
+        .with_request_observer(self.request_span)
+        .with_progress_interval(self.stream_progress_interval)
+        .with_max_head_lag(self.max_head_lag);
+        if let Some(tls_config) = self.server_tls {
+            server = server.with_tls_config(tls_config);
+        }
+        let server_handle = tokio::spawn({
             let ct = ct.clone();
             async move {
                 server
@@ -123,22 +180,23 @@ where
             }
         });
 
-        // TODO: based on which handles terminates first, it needs to wait
-        // for the other handle to terminate too.
-        tokio::select! {
-            ret = &mut block_ingestion_handle => {
-                warn!(result = ?ret, "block ingestion terminated");
-            }
-            ret = &mut server_handle => {
-                warn!(result = ?ret, "server terminated");
-            }
-            ret = &mut healer_handle => {
-                warn!(result = ?ret, "healer terminated");
+        let task_names = ["block ingestion", "healer", "server"];
+        let handles = vec![block_ingestion_handle, healer_handle, server_handle];
+        let (result, index, remaining) = future::select_all(handles).await;
+        warn!(task = task_names[index], result = ?result, "task terminated");
+
+        // orderly shutdown of the other tasks.
+        ct.cancel();
+        for other_result in future::join_all(remaining).await {
+            match other_result {
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => warn!(err = ?err, "task terminated with error"),
+                Err(err) => warn!(err = ?err, "failed to join task"),
             }
         }
 
         info!("terminated. bye");
-        Ok(())
+        result?
     }
 
     fn ensure_tables(&self) -> Result<(), StarkNetNodeError> {
@@ -171,14 +229,31 @@ where
     }
 }
 
-pub struct StarkNetNodeBuilder<O: RequestObserver, E: EnvironmentKind> {
+pub struct StarkNetNodeBuilder<G, O, E>
+where
+    G: Provider + Send + Sync + 'static,
+    O: RequestObserver,
+    E: EnvironmentKind,
+{
     datadir: PathBuf,
-    provider: HttpProvider,
-    poll_interval: Duration,
+    provider: G,
+    block_ingestion_config: BlockIngestionConfig,
+    healer_config: HealerConfig,
+    server_addr: SocketAddr,
+    server_tls: Option<ServerTlsConfig>,
+    stream_progress_interval: Duration,
+    max_head_lag: u64,
+    supervisor_config: SupervisorConfig,
+    db_min_size_gib: usize,
+    db_max_size_gib: usize,
+    db_growth_step_gib: isize,
     request_observer: O,
     _phantom: PhantomData<E>,
 }
 
+/// Default address the gRPC server listens on.
+pub const DEFAULT_SERVER_ADDRESS: &str = "0.0.0.0:7171";
+
 #[derive(Debug, thiserror::Error)]
 pub enum StarkNetNodeBuilderError {
     #[error("failed to create datadir")]
@@ -189,63 +264,192 @@ pub enum StarkNetNodeBuilderError {
     ProviderUrl(#[from] url::ParseError),
     #[error("failed to create sequencer")]
     Provider(#[from] HttpProviderError),
+    #[error("invalid database size: min {min} GiB, max {max} GiB")]
+    InvalidDatabaseSize { min: usize, max: usize },
 }
 
-impl<O, E> StarkNetNodeBuilder<O, E>
+impl<G, O, E> StarkNetNodeBuilder<G, O, E>
 where
+    G: Provider + Send + Sync + 'static,
     O: RequestObserver,
     E: EnvironmentKind,
 {
     pub(crate) fn new(
         url: &str,
-    ) -> Result<StarkNetNodeBuilder<SimpleRequestObserver, E>, StarkNetNodeBuilderError> {
+    ) -> Result<StarkNetNodeBuilder<HttpProvider, SimpleRequestObserver, E>, StarkNetNodeBuilderError>
+    {
+        let url = url.parse()?;
+        let sequencer = HttpProvider::new(url);
+        Ok(StarkNetNodeBuilder::<HttpProvider, SimpleRequestObserver, E>::from_provider(sequencer))
+    }
+
+    pub(crate) fn from_provider(provider: G) -> StarkNetNodeBuilder<G, SimpleRequestObserver, E> {
         let datadir = default_data_dir()
             .map(|d| d.join("starknet"))
             .expect("no datadir");
-        let url = url.parse()?;
-        let sequencer = HttpProvider::new(url);
-        let poll_interval = Duration::from_millis(5_000);
+        let server_addr = DEFAULT_SERVER_ADDRESS
+            .parse()
+            .expect("default server address is valid");
         let request_observer = SimpleRequestObserver::default();
-        let builder = StarkNetNodeBuilder {
+        StarkNetNodeBuilder {
             datadir,
-            provider: sequencer,
-            poll_interval,
+            provider,
+            block_ingestion_config: BlockIngestionConfig::default(),
+            healer_config: HealerConfig::default(),
+            server_addr,
+            server_tls: None,
+            stream_progress_interval: DEFAULT_PROGRESS_INTERVAL,
+            max_head_lag: DEFAULT_MAX_HEAD_LAG,
+            supervisor_config: SupervisorConfig::default(),
+            db_min_size_gib: 10,
+            db_max_size_gib: 100,
+            db_growth_step_gib: 2,
             request_observer,
             _phantom: Default::default(),
-        };
-        Ok(builder)
+        }
     }
 
     pub fn with_datadir(&mut self, datadir: PathBuf) {
         self.datadir = datadir;
     }
 
+    /// Change how often the node polls the RPC for a new head.
     pub fn with_poll_interval(&mut self, poll_interval: Duration) {
-        self.poll_interval = poll_interval;
+        self.block_ingestion_config.head_refresh_interval = poll_interval;
+    }
+
+    /// Change the maximum number of blocks a chain reorganization can remove
+    /// before ingestion halts.
+    pub fn with_max_reorg_depth(&mut self, max_reorg_depth: u64) {
+        self.block_ingestion_config.max_reorg_depth = max_reorg_depth;
+    }
+
+    /// Change how often and how many blocks the healer verifies.
+    pub fn with_healer_config(&mut self, healer_config: HealerConfig) {
+        self.healer_config = healer_config;
+    }
+
+    /// Change the address the gRPC server listens on.
+    pub fn with_server_address(&mut self, server_addr: SocketAddr) {
+        self.server_addr = server_addr;
+    }
+
+    /// Serve the gRPC server over TLS with the given configuration.
+    pub fn with_server_tls(&mut self, tls_config: ServerTlsConfig) {
+        self.server_tls = Some(tls_config);
+    }
+
+    /// Change how often streams send finalized batches without any data, so that
+    /// clients can track progress on sparse filters.
+    pub fn with_stream_progress_interval(&mut self, progress_interval: Duration) {
+        self.stream_progress_interval = progress_interval;
+    }
+
+    /// Change how many blocks the node can lag behind the RPC head before it's
+    /// reported as not serving by the health service.
+    pub fn with_max_head_lag(&mut self, max_head_lag: u64) {
+        self.max_head_lag = max_head_lag;
+    }
+
+    /// Change how the block ingestion and healer tasks are restarted after they fail.
+    pub fn with_supervisor_config(&mut self, supervisor_config: SupervisorConfig) {
+        self.supervisor_config = supervisor_config;
+    }
+
+    /// Change the database size limits, in GiB.
+    pub fn with_db_size_gib(&mut self, min_size: usize, max_size: usize) {
+        self.db_min_size_gib = min_size;
+        self.db_max_size_gib = max_size;
+    }
+
+    /// Change the database growth step, in GiB.
+    pub fn with_db_growth_step_gib(&mut self, step: isize) {
+        self.db_growth_step_gib = step;
+    }
+
+    /// Use a different provider to fetch data from the chain.
+    pub fn with_provider<P: Provider + Send + Sync + 'static>(
+        self,
+        provider: P,
+    ) -> StarkNetNodeBuilder<P, O, E> {
+        StarkNetNodeBuilder {
+            datadir: self.datadir,
+            provider,
+            block_ingestion_config: self.block_ingestion_config,
+            healer_config: self.healer_config,
+            server_addr: self.server_addr,
+            server_tls: self.server_tls,
+            stream_progress_interval: self.stream_progress_interval,
+            max_head_lag: self.max_head_lag,
+            supervisor_config: self.supervisor_config,
+            db_min_size_gib: self.db_min_size_gib,
+            db_max_size_gib: self.db_max_size_gib,
+            db_growth_step_gib: self.db_growth_step_gib,
+            request_observer: self.request_observer,
+            _phantom: self._phantom,
+        }
     }
 
     pub fn with_request_observer<N: RequestObserver>(
         self,
         request_observer: N,
-    ) -> StarkNetNodeBuilder<N, E> {
+    ) -> StarkNetNodeBuilder<G, N, E> {
         StarkNetNodeBuilder {
             datadir: self.datadir,
             provider: self.provider,
-            poll_interval: self.poll_interval,
+            block_ingestion_config: self.block_ingestion_config,
+            healer_config: self.healer_config,
+            server_addr: self.server_addr,
+            server_tls: self.server_tls,
+            stream_progress_interval: self.stream_progress_interval,
+            max_head_lag: self.max_head_lag,
+            supervisor_config: self.supervisor_config,
+            db_min_size_gib: self.db_min_size_gib,
+            db_max_size_gib: self.db_max_size_gib,
+            db_growth_step_gib: self.db_growth_step_gib,
             request_observer,
             _phantom: self._phantom,
         }
     }
 
-    pub fn build(self) -> Result<StarkNetNode<HttpProvider, O, E>, StarkNetNodeBuilderError> {
+    pub fn build(self) -> Result<StarkNetNode<G, O, E>, StarkNetNodeBuilderError> {
+        if self.db_min_size_gib > self.db_max_size_gib {
+            return Err(StarkNetNodeBuilderError::InvalidDatabaseSize {
+                min: self.db_min_size_gib,
+                max: self.db_max_size_gib,
+            });
+        }
+
         fs::create_dir_all(&self.datadir).map_err(StarkNetNodeBuilderError::CreateDatadir)?;
 
         let db = Environment::<E>::builder()
-            .with_size_gib(10, 100)
-            .with_growth_step_gib(2)
+            .with_size_gib(self.db_min_size_gib, self.db_max_size_gib)
+            .with_growth_step_gib(self.db_growth_step_gib)
             .open(&self.datadir)
             .map_err(StarkNetNodeBuilderError::DatabaseOpen)?;
 
-        Ok(StarkNetNode::new(db, self.provider, self.request_observer))
+        Ok(StarkNetNode::new(
+            db,
+            self.provider,
+            self.request_observer,
+            self.block_ingestion_config,
+            self.healer_config,
+            self.server_addr,
+            self.server_tls,
+            self.stream_progress_interval,
+            self.max_head_lag,
+            self.supervisor_config,
+        ))
+    }
+}
+
+impl<O, E> StarkNetNodeBuilder<HttpProvider, O, E>
+where
+    O: RequestObserver,
+    E: EnvironmentKind,
+{
+    /// Change the number of concurrent RPC requests used while ingesting a block.
+    pub fn with_rpc_concurrency(&mut self, rpc_concurrency: usize) {
+        self.provider.with_rpc_concurrency(rpc_concurrency);
     }
 }
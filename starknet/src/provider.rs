@@ -1,9 +1,22 @@
 //! Connect to the sequencer gateway.
+mod failover;
+mod feeder;
+#[cfg(test)]
+mod fixture_server;
+#[cfg(test)]
+mod mock;
+mod models;
+mod rpc;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use apibara_core::starknet::v1alpha2;
+use futures::{stream, StreamExt, TryStreamExt};
 use starknet::{
     core::types::{FieldElement, FromByteArrayError},
     providers::jsonrpc::{self, models::ErrorCode, JsonRpcClientError, RpcError},
 };
+use tracing::warn;
 use url::Url;
 
 use crate::{
@@ -11,6 +24,21 @@ use crate::{
     db::BlockBody,
 };
 
+pub use self::failover::{
+    FailoverConfig, FailoverConfigError, FailoverProvider, FailoverProviderError,
+};
+pub use self::feeder::{FeederGatewayProvider, FeederGatewayProviderError};
+pub use self::rpc::RpcClientError;
+
+#[cfg(test)]
+pub use self::mock::{MockChainProvider, MockChainProviderError};
+
+/// Maximum number of receipts requested in a single batch.
+const RECEIPT_BATCH_SIZE: usize = 100;
+
+/// Default number of receipt batches requested concurrently.
+pub const DEFAULT_RPC_CONCURRENCY: usize = 16;
+
 #[derive(Debug, Clone)]
 pub enum BlockId {
     Latest,
@@ -36,6 +64,22 @@ pub trait Provider {
         id: &BlockId,
     ) -> Result<(v1alpha2::BlockStatus, v1alpha2::BlockHeader, BlockBody), Self::Error>;
 
+    /// Get a specific block together with the receipts of its transactions.
+    ///
+    /// Receipts are in the same order as the transactions in the block body.
+    async fn get_block_with_receipts(
+        &self,
+        id: &BlockId,
+    ) -> Result<
+        (
+            v1alpha2::BlockStatus,
+            v1alpha2::BlockHeader,
+            BlockBody,
+            Vec<v1alpha2::TransactionReceipt>,
+        ),
+        Self::Error,
+    >;
+
     /// Get state update for a specific block.
     async fn get_state_update(&self, id: &BlockId) -> Result<v1alpha2::StateUpdate, Self::Error>;
 
@@ -49,6 +93,11 @@ pub trait Provider {
 /// StarkNet RPC provider over HTTP.
 pub struct HttpProvider {
     provider: jsonrpc::JsonRpcClient<jsonrpc::HttpTransport>,
+    client: rpc::RpcClient,
+    /// Number of receipt batches requested concurrently.
+    rpc_concurrency: usize,
+    /// Set to false once the node reports it doesn't support `starknet_getBlockWithReceipts`.
+    supports_block_with_receipts: AtomicBool,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -69,19 +118,75 @@ pub enum HttpProviderError {
     InvalidBlockId(#[from] FromByteArrayError),
     #[error("failed to parse block hash")]
     InvalidBlockHash(#[from] InvalidBlockHashSize),
+    #[error("rpc request failed")]
+    Rpc(#[from] RpcClientError),
+    #[error("malformed rpc response: {0}")]
+    MalformedResponse(&'static str),
 }
 
 impl HttpProvider {
     pub fn new(rpc_url: Url) -> Self {
+        let client = rpc::RpcClient::new(rpc_url.clone());
         let http = jsonrpc::HttpTransport::new(rpc_url);
         let provider = jsonrpc::JsonRpcClient::new(http);
-        HttpProvider { provider }
+        HttpProvider {
+            provider,
+            client,
+            rpc_concurrency: DEFAULT_RPC_CONCURRENCY,
+            supports_block_with_receipts: AtomicBool::new(true),
+        }
+    }
+
+    /// Change the number of concurrent requests used to fetch the receipts of a
+    /// block, when the node doesn't support `starknet_getBlockWithReceipts`.
+    pub fn with_rpc_concurrency(&mut self, rpc_concurrency: usize) {
+        self.rpc_concurrency = rpc_concurrency.max(1);
+    }
+
+    /// Fetch the receipts of all transactions in the block, using batch requests.
+    async fn get_block_receipts(
+        &self,
+        body: &BlockBody,
+    ) -> Result<Vec<v1alpha2::TransactionReceipt>, HttpProviderError> {
+        let hashes = body
+            .transactions
+            .iter()
+            .map(|tx| {
+                tx.meta.as_ref().and_then(|meta| meta.hash.clone()).ok_or(
+                    HttpProviderError::MalformedResponse("missing transaction hash"),
+                )
+            })
+            .collect::<Result<Vec<_>, HttpProviderError>>()?;
+
+        // batches are returned in order, so receipts are in the same order as hashes.
+        let chunks: Vec<Vec<_>> = stream::iter(hashes.chunks(RECEIPT_BATCH_SIZE))
+            .map(|chunk| self.client.get_transaction_receipts(chunk))
+            .buffered(self.rpc_concurrency)
+            .try_collect()
+            .await?;
+
+        let receipts = chunks
+            .iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, receipt)| {
+                let mut receipt: v1alpha2::TransactionReceipt = receipt.to_proto();
+                receipt.transaction_index = index as u64;
+                receipt
+            })
+            .collect();
+
+        Ok(receipts)
     }
 }
 
 impl ProviderError for HttpProviderError {
     fn is_block_not_found(&self) -> bool {
-        matches!(self, HttpProviderError::BlockNotFound)
+        match self {
+            HttpProviderError::BlockNotFound => true,
+            HttpProviderError::Rpc(err) => err.is_block_not_found(),
+            _ => false,
+        }
     }
 }
 
@@ -134,33 +239,59 @@ impl Provider for HttpProvider {
         &self,
         id: &BlockId,
     ) -> Result<(v1alpha2::BlockStatus, v1alpha2::BlockHeader, BlockBody), Self::Error> {
-        let block_id = id.try_into()?;
-        let block = self
-            .provider
-            .get_block_with_txs(&block_id)
-            .await
-            .map_err(HttpProviderError::from_provider_error)?;
+        let block = self.client.get_block_with_txs(id).await?;
+        if id.is_pending() && !block.header.is_pending() {
+            return Err(HttpProviderError::ExpectedPendingBlock);
+        }
+        if !id.is_pending() && block.header.is_pending() {
+            return Err(HttpProviderError::UnexpectedPendingBlock);
+        }
+        let status = block.header.to_proto();
+        let header = block.header.try_to_proto()?;
+        let body = block.to_proto();
+        Ok((status, header, body))
+    }
 
-        match block {
-            jsonrpc::models::MaybePendingBlockWithTxs::Block(ref block) => {
-                if id.is_pending() {
-                    return Err(HttpProviderError::UnexpectedPendingBlock);
+    #[tracing::instrument(skip(self), err(Debug))]
+    async fn get_block_with_receipts(
+        &self,
+        id: &BlockId,
+    ) -> Result<
+        (
+            v1alpha2::BlockStatus,
+            v1alpha2::BlockHeader,
+            BlockBody,
+            Vec<v1alpha2::TransactionReceipt>,
+        ),
+        Self::Error,
+    > {
+        if self.supports_block_with_receipts.load(Ordering::Relaxed) {
+            match self.client.get_block_with_receipts(id).await {
+                Ok(block) => {
+                    if id.is_pending() && !block.header.is_pending() {
+                        return Err(HttpProviderError::ExpectedPendingBlock);
+                    }
+                    if !id.is_pending() && block.header.is_pending() {
+                        return Err(HttpProviderError::UnexpectedPendingBlock);
+                    }
+                    let status = block.header.to_proto();
+                    let header = block.header.try_to_proto()?;
+                    let body = block.to_proto();
+                    let receipts = block.to_proto();
+                    return Ok((status, header, body, receipts));
                 }
-                let status = block.to_proto();
-                let header = block.to_proto();
-                let body = block.to_proto();
-                Ok((status, header, body))
-            }
-            jsonrpc::models::MaybePendingBlockWithTxs::PendingBlock(ref block) => {
-                if !id.is_pending() {
-                    return Err(HttpProviderError::ExpectedPendingBlock);
+                Err(err) if err.is_method_not_found() => {
+                    warn!("rpc doesn't support getBlockWithReceipts, using batch requests");
+                    self.supports_block_with_receipts
+                        .store(false, Ordering::Relaxed);
                 }
-                let status = block.to_proto();
-                let header = block.to_proto();
-                let body = block.to_proto();
-                Ok((status, header, body))
+                Err(err) => return Err(err.into()),
             }
         }
+
+        let (status, header, body) = self.get_block(id).await?;
+        let receipts = self.get_block_receipts(&body).await?;
+        Ok((status, header, body, receipts))
     }
 
     #[tracing::instrument(skip(self), err(Debug))]
@@ -180,15 +311,7 @@ impl Provider for HttpProvider {
         &self,
         hash: &v1alpha2::FieldElement,
     ) -> Result<v1alpha2::TransactionReceipt, Self::Error> {
-        let hash: FieldElement = hash
-            .try_into()
-            .map_err(|err| HttpProviderError::Provider(Box::new(err)))?;
-        let receipt = self
-            .provider
-            .get_transaction_receipt(hash)
-            .await
-            .map_err(HttpProviderError::from_provider_error)?
-            .to_proto();
+        let receipt = self.client.get_transaction_receipt(hash).await?.to_proto();
         Ok(receipt)
     }
 }
@@ -217,604 +340,6 @@ impl TryFrom<&BlockId> for jsonrpc::models::BlockId {
     }
 }
 
-impl ToProto<v1alpha2::BlockStatus> for jsonrpc::models::BlockWithTxs {
-    fn to_proto(&self) -> v1alpha2::BlockStatus {
-        self.status.to_proto()
-    }
-}
-
-impl ToProto<v1alpha2::BlockStatus> for jsonrpc::models::PendingBlockWithTxs {
-    fn to_proto(&self) -> v1alpha2::BlockStatus {
-        v1alpha2::BlockStatus::Pending
-    }
-}
-
-impl ToProto<v1alpha2::BlockHeader> for jsonrpc::models::BlockWithTxs {
-    fn to_proto(&self) -> v1alpha2::BlockHeader {
-        let block_hash = self.block_hash.into();
-        let parent_block_hash = self.parent_hash.into();
-        let block_number = self.block_number;
-        let sequencer_address = self.sequencer_address.into();
-        let new_root = self.new_root.into();
-        let timestamp = pbjson_types::Timestamp {
-            nanos: 0,
-            seconds: self.timestamp as i64,
-        };
-
-        v1alpha2::BlockHeader {
-            block_hash: Some(block_hash),
-            parent_block_hash: Some(parent_block_hash),
-            block_number,
-            sequencer_address: Some(sequencer_address),
-            new_root: Some(new_root),
-            timestamp: Some(timestamp),
-        }
-    }
-}
-
-impl ToProto<v1alpha2::BlockHeader> for jsonrpc::models::PendingBlockWithTxs {
-    fn to_proto(&self) -> v1alpha2::BlockHeader {
-        let block_hash = FieldElement::ZERO.into();
-        let parent_block_hash = self.parent_hash.into();
-        let sequencer_address = self.sequencer_address.into();
-        let timestamp = pbjson_types::Timestamp {
-            nanos: 0,
-            seconds: self.timestamp as i64,
-        };
-
-        v1alpha2::BlockHeader {
-            block_hash: Some(block_hash),
-            parent_block_hash: Some(parent_block_hash),
-            block_number: u64::MAX,
-            sequencer_address: Some(sequencer_address),
-            new_root: None,
-            timestamp: Some(timestamp),
-        }
-    }
-}
-
-impl ToProto<BlockBody> for jsonrpc::models::BlockWithTxs {
-    fn to_proto(&self) -> BlockBody {
-        let transactions = self.transactions.iter().map(|tx| tx.to_proto()).collect();
-        BlockBody { transactions }
-    }
-}
-
-impl ToProto<BlockBody> for jsonrpc::models::PendingBlockWithTxs {
-    fn to_proto(&self) -> BlockBody {
-        let transactions = self.transactions.iter().map(|tx| tx.to_proto()).collect();
-        BlockBody { transactions }
-    }
-}
-
-impl ToProto<v1alpha2::BlockStatus> for jsonrpc::models::BlockStatus {
-    fn to_proto(&self) -> v1alpha2::BlockStatus {
-        use jsonrpc::models::BlockStatus;
-
-        match self {
-            BlockStatus::Pending => v1alpha2::BlockStatus::Pending,
-            BlockStatus::AcceptedOnL2 => v1alpha2::BlockStatus::AcceptedOnL2,
-            BlockStatus::AcceptedOnL1 => v1alpha2::BlockStatus::AcceptedOnL1,
-            BlockStatus::Rejected => v1alpha2::BlockStatus::Rejected,
-        }
-    }
-}
-
-impl ToProto<v1alpha2::Transaction> for jsonrpc::models::Transaction {
-    fn to_proto(&self) -> v1alpha2::Transaction {
-        use jsonrpc::models::Transaction;
-
-        match self {
-            Transaction::Invoke(invoke) => invoke.to_proto(),
-            Transaction::Deploy(deploy) => deploy.to_proto(),
-            Transaction::Declare(declare) => declare.to_proto(),
-            Transaction::L1Handler(l1_handler) => l1_handler.to_proto(),
-            Transaction::DeployAccount(deploy_account) => deploy_account.to_proto(),
-        }
-    }
-}
-
-impl ToProto<v1alpha2::Transaction> for jsonrpc::models::InvokeTransaction {
-    fn to_proto(&self) -> v1alpha2::Transaction {
-        use jsonrpc::models::InvokeTransaction;
-
-        match self {
-            InvokeTransaction::V0(v0) => v0.to_proto(),
-            InvokeTransaction::V1(v1) => v1.to_proto(),
-        }
-    }
-}
-
-impl ToProto<v1alpha2::Transaction> for jsonrpc::models::InvokeTransactionV0 {
-    fn to_proto(&self) -> v1alpha2::Transaction {
-        use v1alpha2::transaction::Transaction;
-
-        let hash = self.transaction_hash.into();
-        let max_fee = self.max_fee.into();
-        let signature = self.signature.iter().map(|fe| fe.into()).collect();
-        let nonce = self.nonce.into();
-
-        let meta = v1alpha2::TransactionMeta {
-            hash: Some(hash),
-            max_fee: Some(max_fee),
-            signature,
-            nonce: Some(nonce),
-            version: 0,
-        };
-
-        let contract_address = self.contract_address.into();
-        let entry_point_selector = self.entry_point_selector.into();
-        let calldata = self.calldata.iter().map(|fe| fe.into()).collect();
-
-        let invoke_v0 = v1alpha2::InvokeTransactionV0 {
-            contract_address: Some(contract_address),
-            entry_point_selector: Some(entry_point_selector),
-            calldata,
-        };
-
-        v1alpha2::Transaction {
-            meta: Some(meta),
-            transaction: Some(Transaction::InvokeV0(invoke_v0)),
-        }
-    }
-}
-
-impl ToProto<v1alpha2::Transaction> for jsonrpc::models::InvokeTransactionV1 {
-    fn to_proto(&self) -> v1alpha2::Transaction {
-        use v1alpha2::transaction::Transaction;
-
-        let hash = self.transaction_hash.into();
-        let max_fee = self.max_fee.into();
-        let signature = self.signature.iter().map(|fe| fe.into()).collect();
-        let nonce = self.nonce.into();
-
-        let meta = v1alpha2::TransactionMeta {
-            hash: Some(hash),
-            max_fee: Some(max_fee),
-            signature,
-            nonce: Some(nonce),
-            version: 0,
-        };
-
-        let sender_address = self.sender_address.into();
-        let calldata = self.calldata.iter().map(|fe| fe.into()).collect();
-        let invoke_v1 = v1alpha2::InvokeTransactionV1 {
-            sender_address: Some(sender_address),
-            calldata,
-        };
-
-        v1alpha2::Transaction {
-            meta: Some(meta),
-            transaction: Some(Transaction::InvokeV1(invoke_v1)),
-        }
-    }
-}
-
-impl ToProto<v1alpha2::Transaction> for jsonrpc::models::DeployTransaction {
-    fn to_proto(&self) -> v1alpha2::Transaction {
-        use v1alpha2::transaction::Transaction;
-
-        let hash = self.transaction_hash.into();
-
-        let meta = v1alpha2::TransactionMeta {
-            hash: Some(hash),
-            version: self.version,
-            ..v1alpha2::TransactionMeta::default()
-        };
-
-        let class_hash = self.class_hash.into();
-        let contract_address_salt = self.contract_address_salt.into();
-        let constructor_calldata = self
-            .constructor_calldata
-            .iter()
-            .map(|fe| fe.into())
-            .collect();
-
-        let deploy = v1alpha2::DeployTransaction {
-            class_hash: Some(class_hash),
-            contract_address_salt: Some(contract_address_salt),
-            constructor_calldata,
-        };
-
-        v1alpha2::Transaction {
-            meta: Some(meta),
-            transaction: Some(Transaction::Deploy(deploy)),
-        }
-    }
-}
-
-impl ToProto<v1alpha2::Transaction> for jsonrpc::models::DeclareTransaction {
-    fn to_proto(&self) -> v1alpha2::Transaction {
-        use v1alpha2::transaction::Transaction;
-
-        let hash = self.transaction_hash.into();
-        let max_fee = self.max_fee.into();
-        let signature = self.signature.iter().map(|fe| fe.into()).collect();
-        let nonce = self.nonce.into();
-        let version = self.version;
-
-        let meta = v1alpha2::TransactionMeta {
-            hash: Some(hash),
-            max_fee: Some(max_fee),
-            signature,
-            nonce: Some(nonce),
-            version,
-        };
-
-        let class_hash = self.class_hash.into();
-        let sender_address = self.sender_address.into();
-
-        let declare = v1alpha2::DeclareTransaction {
-            class_hash: Some(class_hash),
-            sender_address: Some(sender_address),
-        };
-
-        v1alpha2::Transaction {
-            meta: Some(meta),
-            transaction: Some(Transaction::Declare(declare)),
-        }
-    }
-}
-
-impl ToProto<v1alpha2::Transaction> for jsonrpc::models::L1HandlerTransaction {
-    fn to_proto(&self) -> v1alpha2::Transaction {
-        use v1alpha2::transaction::Transaction;
-
-        let hash = self.transaction_hash.into();
-        let version = self.version;
-
-        let meta = v1alpha2::TransactionMeta {
-            hash: Some(hash),
-            version,
-            ..v1alpha2::TransactionMeta::default()
-        };
-
-        let contract_address = self.contract_address.into();
-        let entry_point_selector = self.entry_point_selector.into();
-        let calldata = self.calldata.iter().map(|fe| fe.into()).collect();
-
-        let l1_handler = v1alpha2::L1HandlerTransaction {
-            contract_address: Some(contract_address),
-            entry_point_selector: Some(entry_point_selector),
-            calldata,
-        };
-
-        v1alpha2::Transaction {
-            meta: Some(meta),
-            transaction: Some(Transaction::L1Handler(l1_handler)),
-        }
-    }
-}
-
-impl ToProto<v1alpha2::Transaction> for jsonrpc::models::DeployAccountTransaction {
-    fn to_proto(&self) -> v1alpha2::Transaction {
-        use v1alpha2::transaction::Transaction;
-
-        let hash = self.transaction_hash.into();
-        let max_fee = self.max_fee.into();
-        let signature = self.signature.iter().map(|fe| fe.into()).collect();
-        let nonce = self.nonce.into();
-        let version = self.version;
-
-        let meta = v1alpha2::TransactionMeta {
-            hash: Some(hash),
-            max_fee: Some(max_fee),
-            signature,
-            nonce: Some(nonce),
-            version,
-        };
-
-        let contract_address_salt = self.contract_address_salt.into();
-        let class_hash = self.class_hash.into();
-        let constructor_calldata = self
-            .constructor_calldata
-            .iter()
-            .map(|fe| fe.into())
-            .collect();
-
-        let deploy_account = v1alpha2::DeployAccountTransaction {
-            contract_address_salt: Some(contract_address_salt),
-            class_hash: Some(class_hash),
-            constructor_calldata,
-        };
-
-        v1alpha2::Transaction {
-            meta: Some(meta),
-            transaction: Some(Transaction::DeployAccount(deploy_account)),
-        }
-    }
-}
-
-impl ToProto<v1alpha2::TransactionReceipt> for jsonrpc::models::MaybePendingTransactionReceipt {
-    fn to_proto(&self) -> v1alpha2::TransactionReceipt {
-        use jsonrpc::models::MaybePendingTransactionReceipt;
-
-        match self {
-            MaybePendingTransactionReceipt::PendingReceipt(receipt) => receipt.to_proto(),
-            MaybePendingTransactionReceipt::Receipt(receipt) => receipt.to_proto(),
-        }
-    }
-}
-
-impl ToProto<v1alpha2::TransactionReceipt> for jsonrpc::models::PendingTransactionReceipt {
-    fn to_proto(&self) -> v1alpha2::TransactionReceipt {
-        use jsonrpc::models::PendingTransactionReceipt;
-
-        match self {
-            PendingTransactionReceipt::Invoke(invoke) => invoke.to_proto(),
-            PendingTransactionReceipt::L1Handler(l1_handler) => l1_handler.to_proto(),
-            PendingTransactionReceipt::Declare(declare) => declare.to_proto(),
-            PendingTransactionReceipt::Deploy(deploy) => deploy.to_proto(),
-            PendingTransactionReceipt::DeployAccount(deploy) => deploy.to_proto(),
-        }
-    }
-}
-
-impl ToProto<v1alpha2::TransactionReceipt> for jsonrpc::models::PendingInvokeTransactionReceipt {
-    fn to_proto(&self) -> v1alpha2::TransactionReceipt {
-        let transaction_hash = self.transaction_hash.into();
-        let actual_fee = self.actual_fee.into();
-        let l2_to_l1_messages = self
-            .messages_sent
-            .iter()
-            .map(|msg| msg.to_proto())
-            .collect();
-        let events = self.events.iter().map(|ev| ev.to_proto()).collect();
-
-        v1alpha2::TransactionReceipt {
-            transaction_index: 0,
-            transaction_hash: Some(transaction_hash),
-            actual_fee: Some(actual_fee),
-            l2_to_l1_messages,
-            events,
-            contract_address: None,
-        }
-    }
-}
-
-impl ToProto<v1alpha2::TransactionReceipt> for jsonrpc::models::PendingL1HandlerTransactionReceipt {
-    fn to_proto(&self) -> v1alpha2::TransactionReceipt {
-        let transaction_hash = self.transaction_hash.into();
-        let actual_fee = self.actual_fee.into();
-        let l2_to_l1_messages = self
-            .messages_sent
-            .iter()
-            .map(|msg| msg.to_proto())
-            .collect();
-        let events = self.events.iter().map(|ev| ev.to_proto()).collect();
-
-        v1alpha2::TransactionReceipt {
-            transaction_index: 0,
-            transaction_hash: Some(transaction_hash),
-            actual_fee: Some(actual_fee),
-            l2_to_l1_messages,
-            events,
-            contract_address: None,
-        }
-    }
-}
-
-impl ToProto<v1alpha2::TransactionReceipt> for jsonrpc::models::PendingDeclareTransactionReceipt {
-    fn to_proto(&self) -> v1alpha2::TransactionReceipt {
-        let transaction_hash = self.transaction_hash.into();
-        let actual_fee = self.actual_fee.into();
-        let l2_to_l1_messages = self
-            .messages_sent
-            .iter()
-            .map(|msg| msg.to_proto())
-            .collect();
-        let events = self.events.iter().map(|ev| ev.to_proto()).collect();
-
-        v1alpha2::TransactionReceipt {
-            transaction_index: 0,
-            transaction_hash: Some(transaction_hash),
-            actual_fee: Some(actual_fee),
-            l2_to_l1_messages,
-            events,
-            contract_address: None,
-        }
-    }
-}
-
-impl ToProto<v1alpha2::TransactionReceipt> for jsonrpc::models::PendingDeployTransactionReceipt {
-    fn to_proto(&self) -> v1alpha2::TransactionReceipt {
-        let transaction_hash = self.transaction_hash.into();
-        let actual_fee = self.actual_fee.into();
-        let l2_to_l1_messages = self
-            .messages_sent
-            .iter()
-            .map(|msg| msg.to_proto())
-            .collect();
-        let events = self.events.iter().map(|ev| ev.to_proto()).collect();
-        let contract_address = self.contract_address.into();
-
-        v1alpha2::TransactionReceipt {
-            transaction_index: 0,
-            transaction_hash: Some(transaction_hash),
-            actual_fee: Some(actual_fee),
-            l2_to_l1_messages,
-            events,
-            contract_address: Some(contract_address),
-        }
-    }
-}
-
-impl ToProto<v1alpha2::TransactionReceipt>
-    for jsonrpc::models::PendingDeployAccountTransactionReceipt
-{
-    fn to_proto(&self) -> v1alpha2::TransactionReceipt {
-        let transaction_hash = self.transaction_hash.into();
-        let actual_fee = self.actual_fee.into();
-        let l2_to_l1_messages = self
-            .messages_sent
-            .iter()
-            .map(|msg| msg.to_proto())
-            .collect();
-        let events = self.events.iter().map(|ev| ev.to_proto()).collect();
-
-        v1alpha2::TransactionReceipt {
-            transaction_index: 0,
-            transaction_hash: Some(transaction_hash),
-            actual_fee: Some(actual_fee),
-            l2_to_l1_messages,
-            events,
-            contract_address: None,
-        }
-    }
-}
-
-impl ToProto<v1alpha2::TransactionReceipt> for jsonrpc::models::TransactionReceipt {
-    fn to_proto(&self) -> v1alpha2::TransactionReceipt {
-        use jsonrpc::models::TransactionReceipt;
-
-        match self {
-            TransactionReceipt::Invoke(invoke) => invoke.to_proto(),
-            TransactionReceipt::L1Handler(l1_handler) => l1_handler.to_proto(),
-            TransactionReceipt::Declare(declare) => declare.to_proto(),
-            TransactionReceipt::Deploy(deploy) => deploy.to_proto(),
-            TransactionReceipt::DeployAccount(deploy) => deploy.to_proto(),
-        }
-    }
-}
-
-impl ToProto<v1alpha2::TransactionReceipt> for jsonrpc::models::InvokeTransactionReceipt {
-    fn to_proto(&self) -> v1alpha2::TransactionReceipt {
-        let transaction_hash = self.transaction_hash.into();
-        let actual_fee = self.actual_fee.into();
-        let l2_to_l1_messages = self
-            .messages_sent
-            .iter()
-            .map(|msg| msg.to_proto())
-            .collect();
-        let events = self.events.iter().map(|ev| ev.to_proto()).collect();
-
-        v1alpha2::TransactionReceipt {
-            transaction_index: 0,
-            transaction_hash: Some(transaction_hash),
-            actual_fee: Some(actual_fee),
-            l2_to_l1_messages,
-            events,
-            contract_address: None,
-        }
-    }
-}
-
-impl ToProto<v1alpha2::TransactionReceipt> for jsonrpc::models::L1HandlerTransactionReceipt {
-    fn to_proto(&self) -> v1alpha2::TransactionReceipt {
-        let transaction_hash = self.transaction_hash.into();
-        let actual_fee = self.actual_fee.into();
-        let l2_to_l1_messages = self
-            .messages_sent
-            .iter()
-            .map(|msg| msg.to_proto())
-            .collect();
-        let events = self.events.iter().map(|ev| ev.to_proto()).collect();
-
-        v1alpha2::TransactionReceipt {
-            transaction_index: 0,
-            transaction_hash: Some(transaction_hash),
-            actual_fee: Some(actual_fee),
-            l2_to_l1_messages,
-            events,
-            contract_address: None,
-        }
-    }
-}
-
-impl ToProto<v1alpha2::TransactionReceipt> for jsonrpc::models::DeclareTransactionReceipt {
-    fn to_proto(&self) -> v1alpha2::TransactionReceipt {
-        let transaction_hash = self.transaction_hash.into();
-        let actual_fee = self.actual_fee.into();
-        let l2_to_l1_messages = self
-            .messages_sent
-            .iter()
-            .map(|msg| msg.to_proto())
-            .collect();
-        let events = self.events.iter().map(|ev| ev.to_proto()).collect();
-
-        v1alpha2::TransactionReceipt {
-            transaction_index: 0,
-            transaction_hash: Some(transaction_hash),
-            actual_fee: Some(actual_fee),
-            l2_to_l1_messages,
-            events,
-            contract_address: None,
-        }
-    }
-}
-
-impl ToProto<v1alpha2::TransactionReceipt> for jsonrpc::models::DeployTransactionReceipt {
-    fn to_proto(&self) -> v1alpha2::TransactionReceipt {
-        let transaction_hash = self.transaction_hash.into();
-        let actual_fee = self.actual_fee.into();
-        let l2_to_l1_messages = self
-            .messages_sent
-            .iter()
-            .map(|msg| msg.to_proto())
-            .collect();
-        let events = self.events.iter().map(|ev| ev.to_proto()).collect();
-        let contract_address = self.contract_address.into();
-
-        v1alpha2::TransactionReceipt {
-            transaction_index: 0,
-            transaction_hash: Some(transaction_hash),
-            actual_fee: Some(actual_fee),
-            l2_to_l1_messages,
-            events,
-            contract_address: Some(contract_address),
-        }
-    }
-}
-
-impl ToProto<v1alpha2::TransactionReceipt> for jsonrpc::models::DeployAccountTransactionReceipt {
-    fn to_proto(&self) -> v1alpha2::TransactionReceipt {
-        let transaction_hash = self.transaction_hash.into();
-        let actual_fee = self.actual_fee.into();
-        let l2_to_l1_messages = self
-            .messages_sent
-            .iter()
-            .map(|msg| msg.to_proto())
-            .collect();
-        let events = self.events.iter().map(|ev| ev.to_proto()).collect();
-        let contract_address = self.contract_address.into();
-
-        v1alpha2::TransactionReceipt {
-            transaction_index: 0,
-            transaction_hash: Some(transaction_hash),
-            actual_fee: Some(actual_fee),
-            l2_to_l1_messages,
-            events,
-            contract_address: Some(contract_address),
-        }
-    }
-}
-
-impl ToProto<v1alpha2::L2ToL1Message> for jsonrpc::models::MsgToL1 {
-    fn to_proto(&self) -> v1alpha2::L2ToL1Message {
-        let to_address = self.to_address.into();
-        let payload = self.payload.iter().map(|p| p.into()).collect();
-
-        v1alpha2::L2ToL1Message {
-            to_address: Some(to_address),
-            payload,
-        }
-    }
-}
-
-impl ToProto<v1alpha2::Event> for jsonrpc::models::Event {
-    fn to_proto(&self) -> v1alpha2::Event {
-        let from_address = self.from_address.into();
-        let keys = self.keys.iter().map(|k| k.into()).collect();
-        let data = self.data.iter().map(|d| d.into()).collect();
-
-        v1alpha2::Event {
-            from_address: Some(from_address),
-            keys,
-            data,
-        }
-    }
-}
-
 impl<'a> TryFrom<TransactionHash<'a>> for FieldElement {
     type Error = FromByteArrayError;
 
@@ -0,0 +1,617 @@
+//! Fetch data from multiple providers.
+//!
+//! Requests are sent to the first healthy provider, moving on to the next one
+//! when a provider fails or doesn't have the requested block yet. Providers
+//! that keep failing are only used as a last resort for a while.
+//!
+//! Optionally, blocks fetched by number are only returned after a quorum of
+//! providers agree on their hash. Block ingestion only extends the canonical
+//! chain with blocks fetched by number, so bad data from a single provider
+//! never makes it into the canonical chain. With a quorum, state updates are
+//! also only returned after enough providers return the same update.
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use apibara_core::starknet::v1alpha2;
+use futures::future::{self, BoxFuture};
+use tracing::{info, warn};
+
+use crate::{core::GlobalBlockId, db::BlockBody};
+
+use super::{BlockId, Provider, ProviderError};
+
+/// Default number of consecutive failures before a provider is unhealthy.
+pub const DEFAULT_MAX_FAILURES: usize = 3;
+
+/// Default time an unhealthy provider is used only as a last resort.
+pub const DEFAULT_UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Configure how a [FailoverProvider] picks providers.
+#[derive(Debug, Clone)]
+pub struct FailoverConfig {
+    /// Number of providers that must agree on the hash of a block fetched by
+    /// number and on state updates. A quorum of 1 disables cross-checking.
+    pub quorum: usize,
+    /// Number of consecutive failures before a provider is unhealthy.
+    pub max_failures: usize,
+    /// How long an unhealthy provider is used only as a last resort.
+    pub unhealthy_cooldown: Duration,
+}
+
+/// A [Provider] that sends requests to multiple providers.
+pub struct FailoverProvider<G: Provider> {
+    providers: Vec<G>,
+    health: Mutex<Vec<ProviderHealth>>,
+    config: FailoverConfig,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FailoverProviderError<E: ProviderError> {
+    #[error(transparent)]
+    Provider(E),
+    #[error("only {agreeing} of {quorum} providers returned block {number}")]
+    QuorumNotReached {
+        number: u64,
+        agreeing: usize,
+        quorum: usize,
+    },
+    #[error("providers disagree on the hash of block {number}")]
+    HashMismatch { number: u64 },
+    #[error(
+        "only {agreeing} of {quorum} providers returned the state update of block {block_id:?}"
+    )]
+    StateUpdateQuorumNotReached {
+        block_id: BlockId,
+        agreeing: usize,
+        quorum: usize,
+    },
+    #[error("providers disagree on the state update of block {block_id:?}")]
+    StateUpdateMismatch { block_id: BlockId },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FailoverConfigError {
+    #[error("at least one provider is required")]
+    NoProviders,
+    #[error("quorum must be between 1 and {providers}, got {quorum}")]
+    InvalidQuorum { quorum: usize, providers: usize },
+}
+
+#[derive(Debug, Default, Clone)]
+struct ProviderHealth {
+    failures: usize,
+    unhealthy_until: Option<Instant>,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        FailoverConfig {
+            quorum: 1,
+            max_failures: DEFAULT_MAX_FAILURES,
+            unhealthy_cooldown: DEFAULT_UNHEALTHY_COOLDOWN,
+        }
+    }
+}
+
+impl ProviderHealth {
+    fn is_healthy(&self, now: Instant) -> bool {
+        self.unhealthy_until
+            .map(|until| now >= until)
+            .unwrap_or(true)
+    }
+}
+
+impl<G> FailoverProvider<G>
+where
+    G: Provider + Send + Sync,
+{
+    /// Creates a new provider that uses `providers` in order of preference.
+    pub fn new(providers: Vec<G>, config: FailoverConfig) -> Result<Self, FailoverConfigError> {
+        if providers.is_empty() {
+            return Err(FailoverConfigError::NoProviders);
+        }
+
+        if config.quorum == 0 || config.quorum > providers.len() {
+            return Err(FailoverConfigError::InvalidQuorum {
+                quorum: config.quorum,
+                providers: providers.len(),
+            });
+        }
+
+        let health = vec![ProviderHealth::default(); providers.len()];
+        Ok(FailoverProvider {
+            providers,
+            health: Mutex::new(health),
+            config,
+        })
+    }
+
+    /// Returns the indices of all providers, healthy providers first.
+    fn provider_order(&self) -> Vec<usize> {
+        let now = Instant::now();
+        let health = self.health.lock().unwrap();
+        let (healthy, unhealthy): (Vec<_>, Vec<_>) =
+            (0..self.providers.len()).partition(|idx| health[*idx].is_healthy(now));
+        healthy.into_iter().chain(unhealthy).collect()
+    }
+
+    /// Returns the indices of the healthy providers, or of all providers if
+    /// none is healthy.
+    fn healthy_providers(&self) -> Vec<usize> {
+        let now = Instant::now();
+        let health = self.health.lock().unwrap();
+        let healthy: Vec<_> = (0..self.providers.len())
+            .filter(|idx| health[*idx].is_healthy(now))
+            .collect();
+        if healthy.is_empty() {
+            (0..self.providers.len()).collect()
+        } else {
+            healthy
+        }
+    }
+
+    fn record_success(&self, idx: usize) {
+        let mut health = self.health.lock().unwrap();
+        if health[idx].unhealthy_until.is_some() {
+            info!(provider = idx, "provider is healthy again");
+        }
+        health[idx] = ProviderHealth::default();
+    }
+
+    fn record_failure(&self, idx: usize, err: &G::Error) {
+        // a provider that doesn't have a block yet is lagging, not failing.
+        if err.is_block_not_found() {
+            return;
+        }
+
+        let mut health = self.health.lock().unwrap();
+        let provider_health = &mut health[idx];
+        provider_health.failures += 1;
+        if provider_health.failures >= self.config.max_failures {
+            warn!(provider = idx, error = ?err, "provider is unhealthy");
+            provider_health.unhealthy_until = Some(Instant::now() + self.config.unhealthy_cooldown);
+        } else {
+            warn!(provider = idx, error = ?err, "provider request failed");
+        }
+    }
+
+    /// Sends the request to one provider after the other, until one succeeds.
+    ///
+    /// Returns the index of the provider that answered together with its response.
+    async fn request<'a, T, F>(
+        &'a self,
+        f: F,
+    ) -> Result<(usize, T), FailoverProviderError<G::Error>>
+    where
+        F: Fn(&'a G) -> BoxFuture<'a, Result<T, G::Error>>,
+    {
+        let mut last_error = None;
+        for idx in self.provider_order() {
+            match f(&self.providers[idx]).await {
+                Ok(value) => {
+                    self.record_success(idx);
+                    return Ok((idx, value));
+                }
+                Err(err) => {
+                    self.record_failure(idx, &err);
+                    last_error = Some(err);
+                }
+            }
+        }
+        let err = last_error.expect("failover provider has at least one provider");
+        Err(FailoverProviderError::Provider(err))
+    }
+
+    /// Checks that enough providers agree on the hash of the given block.
+    ///
+    /// The block was returned by the provider at index `source`, so that provider
+    /// is not asked again.
+    async fn check_quorum(
+        &self,
+        source: usize,
+        header: &v1alpha2::BlockHeader,
+    ) -> Result<(), FailoverProviderError<G::Error>> {
+        let quorum = self.config.quorum;
+        let number = header.block_number;
+        let agreeing = self
+            .agreeing_providers(source, &header.block_hash, |provider| {
+                Box::pin(async move {
+                    provider
+                        .get_block(&BlockId::Number(number))
+                        .await
+                        .map(|(_status, other, _body)| other.block_hash)
+                })
+            })
+            .await
+            .map_err(|idx| {
+                warn!(
+                    block_number = number,
+                    source = source,
+                    provider = idx,
+                    "providers disagree on block hash"
+                );
+                FailoverProviderError::HashMismatch { number }
+            })?;
+
+        if agreeing < quorum {
+            return Err(FailoverProviderError::QuorumNotReached {
+                number,
+                agreeing,
+                quorum,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks that enough providers return the same state update for the given block.
+    ///
+    /// The state update was returned by the provider at index `source`, so that
+    /// provider is not asked again.
+    async fn check_state_update_quorum(
+        &self,
+        source: usize,
+        id: &BlockId,
+        state_update: &v1alpha2::StateUpdate,
+    ) -> Result<(), FailoverProviderError<G::Error>> {
+        let quorum = self.config.quorum;
+        let agreeing = self
+            .agreeing_providers(source, state_update, |provider| {
+                provider.get_state_update(id)
+            })
+            .await
+            .map_err(|idx| {
+                warn!(
+                    block_id = ?id,
+                    source = source,
+                    provider = idx,
+                    "providers disagree on state update"
+                );
+                FailoverProviderError::StateUpdateMismatch {
+                    block_id: id.clone(),
+                }
+            })?;
+
+        if agreeing < quorum {
+            return Err(FailoverProviderError::StateUpdateQuorumNotReached {
+                block_id: id.clone(),
+                agreeing,
+                quorum,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Asks the other providers for the same data, until a quorum of providers
+    /// returned `value`.
+    ///
+    /// Returns the number of providers that agree with `value`, including
+    /// `source`, or the index of the first provider that returned different data.
+    async fn agreeing_providers<'a, T, F>(
+        &'a self,
+        source: usize,
+        value: &T,
+        f: F,
+    ) -> Result<usize, usize>
+    where
+        T: PartialEq,
+        F: Fn(&'a G) -> BoxFuture<'a, Result<T, G::Error>>,
+    {
+        let mut agreeing = 1;
+
+        for idx in self.provider_order() {
+            if agreeing >= self.config.quorum {
+                break;
+            }
+
+            if idx == source {
+                continue;
+            }
+
+            match f(&self.providers[idx]).await {
+                Ok(other) => {
+                    self.record_success(idx);
+                    if other != *value {
+                        return Err(idx);
+                    }
+                    agreeing += 1;
+                }
+                Err(err) => self.record_failure(idx, &err),
+            }
+        }
+
+        Ok(agreeing)
+    }
+}
+
+impl<E> ProviderError for FailoverProviderError<E>
+where
+    E: ProviderError,
+{
+    fn is_block_not_found(&self) -> bool {
+        match self {
+            FailoverProviderError::Provider(err) => err.is_block_not_found(),
+            // not enough providers have the block yet.
+            FailoverProviderError::QuorumNotReached { .. }
+            | FailoverProviderError::StateUpdateQuorumNotReached { .. } => true,
+            FailoverProviderError::HashMismatch { .. }
+            | FailoverProviderError::StateUpdateMismatch { .. } => false,
+        }
+    }
+}
+
+#[apibara_node::async_trait]
+impl<G> Provider for FailoverProvider<G>
+where
+    G: Provider + Send + Sync,
+{
+    type Error = FailoverProviderError<G::Error>;
+
+    async fn get_head(&self) -> Result<GlobalBlockId, Self::Error> {
+        // ask all providers so that a lagging provider doesn't hold back ingestion.
+        let providers = self.healthy_providers();
+        let responses =
+            future::join_all(providers.iter().map(|idx| self.providers[*idx].get_head())).await;
+
+        let mut head: Option<GlobalBlockId> = None;
+        let mut last_error = None;
+        for (idx, response) in providers.into_iter().zip(responses) {
+            match response {
+                Ok(new_head) => {
+                    self.record_success(idx);
+                    head = match head {
+                        Some(head) if head.number() >= new_head.number() => Some(head),
+                        _ => Some(new_head),
+                    };
+                }
+                Err(err) => {
+                    self.record_failure(idx, &err);
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        match (head, last_error) {
+            (Some(head), _) => Ok(head),
+            (None, Some(err)) => Err(FailoverProviderError::Provider(err)),
+            (None, None) => unreachable!("failover provider has at least one provider"),
+        }
+    }
+
+    async fn get_block(
+        &self,
+        id: &BlockId,
+    ) -> Result<(v1alpha2::BlockStatus, v1alpha2::BlockHeader, BlockBody), Self::Error> {
+        let (source, block) = self.request(|provider| provider.get_block(id)).await?;
+        if let BlockId::Number(_) = id {
+            self.check_quorum(source, &block.1).await?;
+        }
+        Ok(block)
+    }
+
+    async fn get_block_with_receipts(
+        &self,
+        id: &BlockId,
+    ) -> Result<
+        (
+            v1alpha2::BlockStatus,
+            v1alpha2::BlockHeader,
+            BlockBody,
+            Vec<v1alpha2::TransactionReceipt>,
+        ),
+        Self::Error,
+    > {
+        let (source, block) = self
+            .request(|provider| provider.get_block_with_receipts(id))
+            .await?;
+        if let BlockId::Number(_) = id {
+            self.check_quorum(source, &block.1).await?;
+        }
+        Ok(block)
+    }
+
+    async fn get_state_update(&self, id: &BlockId) -> Result<v1alpha2::StateUpdate, Self::Error> {
+        let (source, state_update) = self
+            .request(|provider| provider.get_state_update(id))
+            .await?;
+        self.check_state_update_quorum(source, id, &state_update)
+            .await?;
+        Ok(state_update)
+    }
+
+    async fn get_transaction_receipt(
+        &self,
+        hash: &v1alpha2::FieldElement,
+    ) -> Result<v1alpha2::TransactionReceipt, Self::Error> {
+        let (_, receipt) = self
+            .request(|provider| provider.get_transaction_receipt(hash))
+            .await?;
+        Ok(receipt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::provider::{BlockId, MockChainProvider, Provider, ProviderError};
+
+    use super::{FailoverConfig, FailoverConfigError, FailoverProvider, FailoverProviderError};
+
+    fn config(quorum: usize) -> FailoverConfig {
+        FailoverConfig {
+            quorum,
+            max_failures: 2,
+            unhealthy_cooldown: Duration::from_secs(60),
+        }
+    }
+
+    /// Creates `count` providers that share the same chain with `blocks` blocks.
+    fn providers(count: usize, blocks: usize) -> Vec<MockChainProvider> {
+        (0..count)
+            .map(|_| {
+                let provider = MockChainProvider::new();
+                provider.produce_blocks(blocks);
+                provider
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let result = FailoverProvider::<MockChainProvider>::new(Vec::default(), config(1));
+        assert!(matches!(result, Err(FailoverConfigError::NoProviders)));
+
+        let result = FailoverProvider::new(providers(2, 0), config(3));
+        assert!(matches!(
+            result,
+            Err(FailoverConfigError::InvalidQuorum { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_failover_to_next_provider() {
+        let providers = providers(2, 3);
+        providers[0].fail_next_requests(usize::MAX);
+        let provider = FailoverProvider::new(providers, config(1)).unwrap();
+
+        for _ in 0..4 {
+            let (_, header, _, _) = provider
+                .get_block_with_receipts(&BlockId::Number(3))
+                .await
+                .unwrap();
+            assert_eq!(header.block_number, 3);
+        }
+
+        // the failing provider is skipped once it's unhealthy.
+        assert_eq!(provider.providers[0].request_count(), 2);
+        assert_eq!(provider.providers[1].request_count(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_lagging_provider() {
+        let providers = providers(2, 3);
+        providers[1].produce_blocks(2);
+        let provider = FailoverProvider::new(providers, config(1)).unwrap();
+
+        let head = provider.get_head().await.unwrap();
+        assert_eq!(head.number(), 5);
+
+        let (_, header, _) = provider.get_block(&BlockId::Number(5)).await.unwrap();
+        assert_eq!(header.block_number, 5);
+
+        // lagging is not a failure, the first provider is still preferred.
+        provider.get_block(&BlockId::Number(1)).await.unwrap();
+        assert_eq!(provider.providers[0].request_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_quorum() {
+        let providers = providers(3, 3);
+        providers[0].fail_next_requests(1);
+        let provider = FailoverProvider::new(providers, config(2)).unwrap();
+
+        let (_, header, _, _) = provider
+            .get_block_with_receipts(&BlockId::Number(3))
+            .await
+            .unwrap();
+        assert_eq!(header.block_number, 3);
+
+        // the first provider recovered in time to confirm the block.
+        assert_eq!(provider.providers[0].request_count(), 2);
+        assert_eq!(provider.providers[2].request_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_quorum_not_reached() {
+        let providers = providers(2, 3);
+        providers[0].produce_blocks(1);
+        let provider = FailoverProvider::new(providers, config(2)).unwrap();
+
+        let err = provider
+            .get_block_with_receipts(&BlockId::Number(4))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            FailoverProviderError::QuorumNotReached {
+                number: 4,
+                agreeing: 1,
+                quorum: 2
+            }
+        ));
+        assert!(err.is_block_not_found());
+    }
+
+    #[tokio::test]
+    async fn test_quorum_hash_mismatch() {
+        let providers = providers(2, 3);
+        providers[1].reorg(1);
+        providers[1].produce_block();
+        let provider = FailoverProvider::new(providers, config(2)).unwrap();
+
+        let err = provider
+            .get_block_with_receipts(&BlockId::Number(3))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            FailoverProviderError::HashMismatch { number: 3 }
+        ));
+        assert!(!err.is_block_not_found());
+
+        // blocks before the fork are fine.
+        provider
+            .get_block_with_receipts(&BlockId::Number(2))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_state_update_quorum() {
+        let providers = providers(3, 3);
+        let block_id = BlockId::Hash(*providers[0].block_id(2).unwrap().hash());
+        providers[1].corrupt_state_update(3);
+        let provider = FailoverProvider::new(providers, config(2)).unwrap();
+
+        provider.get_state_update(&block_id).await.unwrap();
+        assert_eq!(provider.providers[1].request_count(), 1);
+        assert_eq!(provider.providers[2].request_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_state_update_mismatch() {
+        let providers = providers(2, 3);
+        let block_id = BlockId::Hash(*providers[0].block_id(2).unwrap().hash());
+        providers[1].corrupt_state_update(2);
+        let provider = FailoverProvider::new(providers, config(2)).unwrap();
+
+        let err = provider.get_state_update(&block_id).await.unwrap_err();
+        assert!(matches!(
+            err,
+            FailoverProviderError::StateUpdateMismatch { .. }
+        ));
+        assert!(!err.is_block_not_found());
+    }
+
+    #[tokio::test]
+    async fn test_state_update_quorum_not_reached() {
+        let providers = providers(2, 3);
+        let block_id = BlockId::Hash(*providers[0].produce_block().hash());
+        let provider = FailoverProvider::new(providers, config(2)).unwrap();
+
+        let err = provider.get_state_update(&block_id).await.unwrap_err();
+        assert!(matches!(
+            err,
+            FailoverProviderError::StateUpdateQuorumNotReached {
+                agreeing: 1,
+                quorum: 2,
+                ..
+            }
+        ));
+        assert!(err.is_block_not_found());
+    }
+}
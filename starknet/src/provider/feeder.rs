@@ -0,0 +1,643 @@
+//! Connect to the StarkNet feeder gateway.
+//!
+//! The feeder gateway returns the transaction receipts together with the
+//! block, so a block and its receipts are fetched with a single request.
+use apibara_core::starknet::v1alpha2;
+use serde::de::DeserializeOwned;
+use url::Url;
+
+use crate::{core::GlobalBlockId, db::BlockBody};
+
+use super::{BlockId, Provider, ProviderError, ToProto, TryToProto};
+
+const BLOCK_NOT_FOUND_CODE: &str = "StarknetErrorCode.BLOCK_NOT_FOUND";
+
+/// StarkNet feeder gateway provider over HTTP.
+pub struct FeederGatewayProvider {
+    client: reqwest::Client,
+    feeder_gateway_url: Url,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FeederGatewayProviderError {
+    #[error("the given block was not found")]
+    BlockNotFound,
+    #[error("the given transaction was not found")]
+    TransactionNotFound,
+    #[error("failed to parse feeder gateway url")]
+    Url(#[from] url::ParseError),
+    #[error("feeder gateway request failed")]
+    Request(#[from] reqwest::Error),
+    #[error("feeder gateway error {code}: {message}")]
+    Gateway { code: String, message: String },
+    #[error("received unexpected pending block")]
+    UnexpectedPendingBlock,
+    #[error("expected pending block, but received non pending block")]
+    ExpectedPendingBlock,
+    #[error("malformed feeder gateway response: {0}")]
+    MalformedResponse(&'static str),
+}
+
+impl FeederGatewayProvider {
+    /// Creates a new provider using the feeder gateway at the given url.
+    ///
+    /// The url is the base of the feeder gateway endpoints, for example
+    /// `https://alpha-mainnet.starknet.io/feeder_gateway`.
+    pub fn new(mut feeder_gateway_url: Url) -> Self {
+        // endpoints are joined to the url, so it must end with a slash.
+        if !feeder_gateway_url.path().ends_with('/') {
+            let path = format!("{}/", feeder_gateway_url.path());
+            feeder_gateway_url.set_path(&path);
+        }
+        FeederGatewayProvider {
+            client: reqwest::Client::new(),
+            feeder_gateway_url,
+        }
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, FeederGatewayProviderError> {
+        let url = self.feeder_gateway_url.join(endpoint)?;
+        let response = self.client.get(url).query(query).send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response.json().await?);
+        }
+
+        let body = response.text().await?;
+        match serde_json::from_str::<models::GatewayError>(&body) {
+            Ok(err) if err.code == BLOCK_NOT_FOUND_CODE => {
+                Err(FeederGatewayProviderError::BlockNotFound)
+            }
+            Ok(err) => Err(FeederGatewayProviderError::Gateway {
+                code: err.code,
+                message: err.message,
+            }),
+            Err(_) => Err(FeederGatewayProviderError::Gateway {
+                code: status.to_string(),
+                message: body,
+            }),
+        }
+    }
+
+    async fn request_block(
+        &self,
+        id: &BlockId,
+    ) -> Result<models::Block, FeederGatewayProviderError> {
+        let block: models::Block = self.request("get_block", &[id.to_query()]).await?;
+
+        let is_pending = block.status == models::BlockStatus::Pending;
+        if id.is_pending() && !is_pending {
+            return Err(FeederGatewayProviderError::ExpectedPendingBlock);
+        }
+        if !id.is_pending() && is_pending {
+            return Err(FeederGatewayProviderError::UnexpectedPendingBlock);
+        }
+
+        Ok(block)
+    }
+}
+
+impl ProviderError for FeederGatewayProviderError {
+    fn is_block_not_found(&self) -> bool {
+        matches!(self, FeederGatewayProviderError::BlockNotFound)
+    }
+}
+
+#[apibara_node::async_trait]
+impl Provider for FeederGatewayProvider {
+    type Error = FeederGatewayProviderError;
+
+    #[tracing::instrument(skip(self), err(Debug))]
+    async fn get_head(&self) -> Result<GlobalBlockId, Self::Error> {
+        let query = [
+            BlockId::Latest.to_query(),
+            ("headerOnly", "true".to_string()),
+        ];
+        let head: models::BlockHashAndNumber = self.request("get_block", &query).await?;
+        Ok(GlobalBlockId::new(
+            head.block_number,
+            head.block_hash.into(),
+        ))
+    }
+
+    #[tracing::instrument(skip(self), err(Debug))]
+    async fn get_block(
+        &self,
+        id: &BlockId,
+    ) -> Result<(v1alpha2::BlockStatus, v1alpha2::BlockHeader, BlockBody), Self::Error> {
+        let block = self.request_block(id).await?;
+        let status = block.status.to_proto();
+        let header = block.try_to_proto()?;
+        let body = block.to_proto();
+        Ok((status, header, body))
+    }
+
+    #[tracing::instrument(skip(self), err(Debug))]
+    async fn get_block_with_receipts(
+        &self,
+        id: &BlockId,
+    ) -> Result<
+        (
+            v1alpha2::BlockStatus,
+            v1alpha2::BlockHeader,
+            BlockBody,
+            Vec<v1alpha2::TransactionReceipt>,
+        ),
+        Self::Error,
+    > {
+        let block = self.request_block(id).await?;
+        let status = block.status.to_proto();
+        let header = block.try_to_proto()?;
+        let body = block.to_proto();
+        let receipts = block.to_proto();
+        Ok((status, header, body, receipts))
+    }
+
+    #[tracing::instrument(skip(self), err(Debug))]
+    async fn get_state_update(&self, id: &BlockId) -> Result<v1alpha2::StateUpdate, Self::Error> {
+        let state_update: models::StateUpdate =
+            self.request("get_state_update", &[id.to_query()]).await?;
+        state_update.try_to_proto()
+    }
+
+    #[tracing::instrument(skip(self), fields(hash = %hash), err(Debug))]
+    async fn get_transaction_receipt(
+        &self,
+        hash: &v1alpha2::FieldElement,
+    ) -> Result<v1alpha2::TransactionReceipt, Self::Error> {
+        let query = [("transactionHash", hash.to_hex())];
+        let receipt: models::TransactionReceipt =
+            self.request("get_transaction_receipt", &query).await?;
+        if receipt.status.as_deref() == Some("NOT_RECEIVED") {
+            return Err(FeederGatewayProviderError::TransactionNotFound);
+        }
+        Ok(receipt.to_proto())
+    }
+}
+
+impl BlockId {
+    fn to_query(&self) -> (&'static str, String) {
+        match self {
+            BlockId::Latest => ("blockNumber", "latest".to_string()),
+            BlockId::Pending => ("blockNumber", "pending".to_string()),
+            BlockId::Number(number) => ("blockNumber", number.to_string()),
+            BlockId::Hash(hash) => ("blockHash", v1alpha2::FieldElement::from(hash).to_hex()),
+        }
+    }
+}
+
+impl TryToProto<v1alpha2::BlockHeader> for models::Block {
+    type Error = FeederGatewayProviderError;
+
+    fn try_to_proto(&self) -> Result<v1alpha2::BlockHeader, Self::Error> {
+        // pending blocks don't have a hash or number yet.
+        let (block_hash, block_number) =
+            if self.status == models::BlockStatus::Pending {
+                (v1alpha2::FieldElement::from_u64(0), u64::MAX)
+            } else {
+                let block_hash = self.block_hash.clone().ok_or(
+                    FeederGatewayProviderError::MalformedResponse("missing block hash"),
+                )?;
+                let block_number =
+                    self.block_number
+                        .ok_or(FeederGatewayProviderError::MalformedResponse(
+                            "missing block number",
+                        ))?;
+                (block_hash, block_number)
+            };
+
+        let timestamp = pbjson_types::Timestamp {
+            nanos: 0,
+            seconds: self.timestamp as i64,
+        };
+
+        // blocks produced before v0.13.1 only have a gas price in wei.
+        let l1_gas_price = match (&self.l1_gas_price, &self.gas_price) {
+            (Some(price), _) => Some(price.to_proto()),
+            (None, Some(price_in_wei)) => Some(v1alpha2::ResourcePrice {
+                price_in_fri: None,
+                price_in_wei: Some(price_in_wei.clone()),
+            }),
+            (None, None) => None,
+        };
+        let l1_data_gas_price = self.l1_data_gas_price.as_ref().map(|p| p.to_proto());
+        let l1_da_mode = self
+            .l1_da_mode
+            .as_ref()
+            .map(|m| m.to_proto())
+            .unwrap_or(v1alpha2::L1DataAvailabilityMode::Unspecified);
+
+        Ok(v1alpha2::BlockHeader {
+            block_hash: Some(block_hash),
+            parent_block_hash: Some(self.parent_block_hash.clone()),
+            block_number,
+            sequencer_address: self.sequencer_address.clone(),
+            new_root: self.state_root.clone(),
+            timestamp: Some(timestamp),
+            l1_gas_price,
+            l1_data_gas_price,
+            starknet_version: self.starknet_version.clone().unwrap_or_default(),
+            l1_da_mode: l1_da_mode as i32,
+        })
+    }
+}
+
+impl ToProto<BlockBody> for models::Block {
+    fn to_proto(&self) -> BlockBody {
+        let transactions = self.transactions.iter().map(|tx| tx.to_proto()).collect();
+        BlockBody { transactions }
+    }
+}
+
+impl ToProto<Vec<v1alpha2::TransactionReceipt>> for models::Block {
+    fn to_proto(&self) -> Vec<v1alpha2::TransactionReceipt> {
+        self.transaction_receipts
+            .iter()
+            .map(|receipt| {
+                let mut receipt = receipt.to_proto();
+                // the address of deployed contracts is only in the transaction.
+                let transaction = self.transactions.get(receipt.transaction_index as usize);
+                receipt.contract_address = transaction.and_then(|tx| tx.contract_address());
+                receipt
+            })
+            .collect()
+    }
+}
+
+impl ToProto<v1alpha2::TransactionReceipt> for models::TransactionReceipt {
+    fn to_proto(&self) -> v1alpha2::TransactionReceipt {
+        let l2_to_l1_messages = self
+            .l2_to_l1_messages
+            .iter()
+            .map(|msg| msg.to_proto())
+            .collect();
+        let events = self.events.iter().map(|ev| ev.to_proto()).collect();
+
+        // blocks produced before v0.12.1 only contain successful transactions.
+        let execution_status = self
+            .execution_status
+            .map(|status| status.to_proto())
+            .unwrap_or(v1alpha2::ExecutionStatus::Succeeded);
+
+        v1alpha2::TransactionReceipt {
+            transaction_hash: Some(self.transaction_hash.clone()),
+            transaction_index: self.transaction_index,
+            actual_fee: self.actual_fee.clone(),
+            l2_to_l1_messages,
+            events,
+            contract_address: None,
+            execution_status: execution_status as i32,
+            revert_reason: self.revert_error.clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl TryToProto<v1alpha2::StateUpdate> for models::StateUpdate {
+    type Error = FeederGatewayProviderError;
+
+    fn try_to_proto(&self) -> Result<v1alpha2::StateUpdate, Self::Error> {
+        let state_diff = self.state_diff.try_to_proto()?;
+        Ok(v1alpha2::StateUpdate {
+            new_root: self.new_root.clone(),
+            old_root: Some(self.old_root.clone()),
+            state_diff: Some(state_diff),
+        })
+    }
+}
+
+impl TryToProto<v1alpha2::StateDiff> for models::StateDiff {
+    type Error = FeederGatewayProviderError;
+
+    fn try_to_proto(&self) -> Result<v1alpha2::StateDiff, Self::Error> {
+        let storage_diffs = self
+            .storage_diffs
+            .iter()
+            .map(|(address, entries)| {
+                let storage_entries = entries
+                    .iter()
+                    .map(|entry| v1alpha2::StorageEntry {
+                        key: Some(entry.key.clone()),
+                        value: Some(entry.value.clone()),
+                    })
+                    .collect();
+                Ok(v1alpha2::StorageDiff {
+                    contract_address: Some(parse_address(address)?),
+                    storage_entries,
+                })
+            })
+            .collect::<Result<Vec<_>, Self::Error>>()?;
+
+        let declared_contracts = self
+            .old_declared_contracts
+            .iter()
+            .chain(self.declared_classes.iter().map(|c| &c.class_hash))
+            .map(|class_hash| v1alpha2::DeclaredContract {
+                class_hash: Some(class_hash.clone()),
+            })
+            .collect();
+
+        let deployed_contracts = self
+            .deployed_contracts
+            .iter()
+            .map(|c| v1alpha2::DeployedContract {
+                contract_address: Some(c.address.clone()),
+                class_hash: Some(c.class_hash.clone()),
+            })
+            .collect();
+
+        let nonces = self
+            .nonces
+            .iter()
+            .map(|(address, nonce)| {
+                Ok(v1alpha2::NonceUpdate {
+                    contract_address: Some(parse_address(address)?),
+                    nonce: Some(nonce.clone()),
+                })
+            })
+            .collect::<Result<Vec<_>, Self::Error>>()?;
+
+        Ok(v1alpha2::StateDiff {
+            storage_diffs,
+            declared_contracts,
+            deployed_contracts,
+            nonces,
+        })
+    }
+}
+
+fn parse_address(address: &str) -> Result<v1alpha2::FieldElement, FeederGatewayProviderError> {
+    v1alpha2::FieldElement::from_hex(address)
+        .map_err(|_| FeederGatewayProviderError::MalformedResponse("invalid contract address"))
+}
+
+/// Feeder gateway response types.
+mod models {
+    use std::collections::BTreeMap;
+
+    use apibara_core::starknet::v1alpha2::FieldElement;
+    use serde::Deserialize;
+
+    pub use crate::provider::models::{
+        BlockStatus, Event, ExecutionStatus, L1DataAvailabilityMode, L2ToL1Message, ResourcePrice,
+        Transaction,
+    };
+
+    #[derive(Debug, Deserialize)]
+    pub struct GatewayError {
+        pub code: String,
+        pub message: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct BlockHashAndNumber {
+        pub block_hash: FieldElement,
+        pub block_number: u64,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Block {
+        pub block_hash: Option<FieldElement>,
+        pub block_number: Option<u64>,
+        pub parent_block_hash: FieldElement,
+        pub state_root: Option<FieldElement>,
+        pub status: BlockStatus,
+        pub timestamp: u64,
+        pub sequencer_address: Option<FieldElement>,
+        pub gas_price: Option<FieldElement>,
+        pub l1_gas_price: Option<ResourcePrice>,
+        pub l1_data_gas_price: Option<ResourcePrice>,
+        pub l1_da_mode: Option<L1DataAvailabilityMode>,
+        pub starknet_version: Option<String>,
+        #[serde(default)]
+        pub transactions: Vec<Transaction>,
+        #[serde(default)]
+        pub transaction_receipts: Vec<TransactionReceipt>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct TransactionReceipt {
+        pub status: Option<String>,
+        pub transaction_hash: FieldElement,
+        #[serde(default)]
+        pub transaction_index: u64,
+        pub actual_fee: Option<FieldElement>,
+        #[serde(default)]
+        pub l2_to_l1_messages: Vec<L2ToL1Message>,
+        #[serde(default)]
+        pub events: Vec<Event>,
+        pub execution_status: Option<ExecutionStatus>,
+        pub revert_error: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct StateUpdate {
+        pub new_root: Option<FieldElement>,
+        pub old_root: FieldElement,
+        pub state_diff: StateDiff,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct StateDiff {
+        #[serde(default)]
+        pub storage_diffs: BTreeMap<String, Vec<StorageEntry>>,
+        #[serde(default)]
+        pub nonces: BTreeMap<String, FieldElement>,
+        #[serde(default)]
+        pub deployed_contracts: Vec<DeployedContract>,
+        #[serde(default)]
+        pub old_declared_contracts: Vec<FieldElement>,
+        #[serde(default)]
+        pub declared_classes: Vec<DeclaredClass>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct StorageEntry {
+        pub key: FieldElement,
+        pub value: FieldElement,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct DeployedContract {
+        pub address: FieldElement,
+        pub class_hash: FieldElement,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct DeclaredClass {
+        pub class_hash: FieldElement,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use apibara_core::starknet::v1alpha2::{self, transaction::Transaction, FieldElement};
+    use assert_matches::assert_matches;
+    use hyper::{Body, Request, Response, StatusCode};
+    use url::Url;
+
+    use crate::provider::{fixture_server::start_fixture_server, BlockId, Provider, ProviderError};
+
+    use super::FeederGatewayProvider;
+
+    const GET_BLOCK_100: &str = include_str!("../../fixtures/feeder_gateway/get_block_100.json");
+    const GET_BLOCK_LATEST_HEADER: &str =
+        include_str!("../../fixtures/feeder_gateway/get_block_latest_header.json");
+    const GET_STATE_UPDATE_100: &str =
+        include_str!("../../fixtures/feeder_gateway/get_state_update_100.json");
+    const BLOCK_NOT_FOUND: &str =
+        include_str!("../../fixtures/feeder_gateway/block_not_found.json");
+
+    /// Returns the recorded response for the given request.
+    fn fixture_response(req: &Request<Body>) -> Response<Body> {
+        let query = req.uri().query().unwrap_or_default();
+        let (status, body) = match (req.uri().path(), query) {
+            ("/feeder_gateway/get_block", "blockNumber=100") => (StatusCode::OK, GET_BLOCK_100),
+            ("/feeder_gateway/get_block", "blockNumber=latest&headerOnly=true") => {
+                (StatusCode::OK, GET_BLOCK_LATEST_HEADER)
+            }
+            ("/feeder_gateway/get_state_update", query) if query.starts_with("blockHash=") => {
+                (StatusCode::OK, GET_STATE_UPDATE_100)
+            }
+            _ => (StatusCode::BAD_REQUEST, BLOCK_NOT_FOUND),
+        };
+        Response::builder()
+            .status(status)
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .expect("valid response")
+    }
+
+    /// Starts a local feeder gateway serving the recorded responses.
+    ///
+    /// Returns the feeder gateway url and the number of requests served.
+    fn start_feeder_gateway() -> (Url, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let url = start_fixture_server("/feeder_gateway", {
+            let requests = requests.clone();
+            move |req| {
+                requests.fetch_add(1, Ordering::SeqCst);
+                let response = fixture_response(&req);
+                async move { response }
+            }
+        });
+        (url, requests)
+    }
+
+    fn felt(s: &str) -> FieldElement {
+        FieldElement::from_hex(s).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_head() {
+        let (url, _) = start_feeder_gateway();
+        let provider = FeederGatewayProvider::new(url);
+        let head = provider.get_head().await.unwrap();
+        assert_eq!(head.number(), 100);
+    }
+
+    #[tokio::test]
+    async fn test_get_block() {
+        let (url, _) = start_feeder_gateway();
+        let provider = FeederGatewayProvider::new(url);
+        let (status, header, body) = provider.get_block(&BlockId::Number(100)).await.unwrap();
+
+        assert_eq!(status, v1alpha2::BlockStatus::AcceptedOnL2);
+        assert_eq!(header.block_number, 100);
+        assert_eq!(header.starknet_version, "0.13.1");
+        assert_eq!(header.l1_da_mode(), v1alpha2::L1DataAvailabilityMode::Blob);
+        let l1_gas_price = header.l1_gas_price.unwrap();
+        assert_eq!(l1_gas_price.price_in_wei, Some(felt("0x3b9aca00")));
+        assert_eq!(l1_gas_price.price_in_fri, Some(felt("0x5af3107a4000")));
+
+        let transactions = body
+            .transactions
+            .iter()
+            .map(|tx| tx.transaction.clone().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(transactions.len(), 5);
+        assert_matches!(transactions[0], Transaction::InvokeV1(_));
+        assert_matches!(transactions[1], Transaction::InvokeV3(_));
+        assert_matches!(transactions[2], Transaction::DeclareV2(_));
+        assert_matches!(transactions[3], Transaction::DeployAccount(_));
+        assert_matches!(transactions[4], Transaction::L1Handler(_));
+
+        let v3_meta = body.transactions[1].meta.as_ref().unwrap();
+        assert_eq!(v3_meta.version, 3);
+        assert_eq!(
+            v3_meta.nonce_data_availability_mode(),
+            v1alpha2::DataAvailabilityMode::L1
+        );
+        let l1_gas = v3_meta
+            .resource_bounds
+            .as_ref()
+            .and_then(|b| b.l1_gas.as_ref())
+            .unwrap();
+        assert_eq!(l1_gas.max_amount, 100_000);
+        assert_eq!(
+            l1_gas.max_price_per_unit.as_ref().unwrap().low,
+            0x5af3107a4000
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_block_with_receipts() {
+        let (url, requests) = start_feeder_gateway();
+        let provider = FeederGatewayProvider::new(url);
+        let (_, _, body, receipts) = provider
+            .get_block_with_receipts(&BlockId::Number(100))
+            .await
+            .unwrap();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert_eq!(receipts.len(), body.transactions.len());
+        for (tx, receipt) in body.transactions.iter().zip(&receipts) {
+            assert_eq!(tx.meta.as_ref().unwrap().hash, receipt.transaction_hash);
+        }
+
+        assert!(!receipts[0].is_reverted());
+        assert_eq!(receipts[0].events.len(), 1);
+        assert_eq!(receipts[0].l2_to_l1_messages.len(), 1);
+        assert!(receipts[1].is_reverted());
+        assert_eq!(receipts[1].revert_reason, "Error in the called contract.");
+        assert_eq!(receipts[3].contract_address, Some(felt("0x44")));
+        assert_eq!(receipts[4].transaction_index, 4);
+    }
+
+    #[tokio::test]
+    async fn test_get_state_update() {
+        let (url, _) = start_feeder_gateway();
+        let provider = FeederGatewayProvider::new(url);
+        let head = provider.get_head().await.unwrap();
+        let state_update = provider
+            .get_state_update(&BlockId::Hash(*head.hash()))
+            .await
+            .unwrap();
+
+        let state_diff = state_update.state_diff.unwrap();
+        assert_eq!(state_diff.storage_diffs.len(), 1);
+        assert_eq!(
+            state_diff.storage_diffs[0].contract_address,
+            Some(felt("0x41"))
+        );
+        assert_eq!(state_diff.nonces.len(), 2);
+        assert_eq!(state_diff.deployed_contracts.len(), 1);
+        assert_eq!(state_diff.declared_contracts.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_block_not_found() {
+        let (url, _) = start_feeder_gateway();
+        let provider = FeederGatewayProvider::new(url);
+        let err = provider.get_block(&BlockId::Number(101)).await.unwrap_err();
+        assert!(err.is_block_not_found());
+    }
+}
@@ -0,0 +1,35 @@
+//! Local HTTP server used to test providers against recorded responses.
+use std::{convert::Infallible, future::Future, net::SocketAddr};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use url::Url;
+
+/// Starts a local HTTP server that answers every request with `handler`.
+///
+/// Returns the url of the server, ending with the given path.
+pub fn start_fixture_server<F, Fut>(path: &str, handler: F) -> Url
+where
+    F: Fn(Request<Body>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Response<Body>> + Send + 'static,
+{
+    let make_service = make_service_fn(move |_conn| {
+        let handler = handler.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let response = handler(req);
+                async move { Ok::<_, Infallible>(response.await) }
+            }))
+        }
+    });
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+    let server = Server::bind(&addr).serve(make_service);
+    let url = format!("http://{}{}", server.local_addr(), path)
+        .parse()
+        .expect("valid url");
+    tokio::spawn(server);
+    url
+}
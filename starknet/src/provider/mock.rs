@@ -0,0 +1,359 @@
+//! In-memory provider used to simulate a chain in tests.
+//!
+//! The chain is fully controlled by the test: blocks are only produced,
+//! finalized or reorganized when the test asks for it. Block and transaction
+//! hashes are derived from counters so that the same script always produces
+//! the same chain.
+use std::sync::Mutex;
+
+use apibara_core::starknet::v1alpha2;
+
+use crate::{
+    core::{BlockHash, GlobalBlockId},
+    db::BlockBody,
+};
+
+use super::{BlockId, Provider, ProviderError};
+
+/// A [Provider] backed by a scripted, in-memory chain.
+pub struct MockChainProvider {
+    chain: Mutex<MockChain>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MockChainProviderError {
+    #[error("the given block was not found")]
+    BlockNotFound,
+    #[error("the given transaction was not found")]
+    TransactionNotFound,
+    #[error("provider is temporarily unavailable")]
+    Unavailable,
+}
+
+#[derive(Debug, Clone)]
+struct MockBlock {
+    status: v1alpha2::BlockStatus,
+    header: v1alpha2::BlockHeader,
+    body: BlockBody,
+    receipts: Vec<v1alpha2::TransactionReceipt>,
+    /// Old state root returned with the block state update.
+    old_root: v1alpha2::FieldElement,
+}
+
+#[derive(Default)]
+struct MockChain {
+    /// Blocks in the canonical chain, indexed by block number.
+    canonical: Vec<MockBlock>,
+    /// Blocks removed from the canonical chain by a reorg.
+    rejected: Vec<MockBlock>,
+    /// The pending block, if any.
+    pending: Option<MockBlock>,
+    /// Incremented on every reorg, so that new blocks get new hashes.
+    fork: u64,
+    /// Number of transactions produced so far.
+    transaction_count: u64,
+    /// Number of requests that will fail before the provider recovers.
+    failures: usize,
+    /// Number of requests received, including failed ones.
+    requests: usize,
+}
+
+impl MockChainProvider {
+    /// Creates a new chain that contains only the genesis block.
+    pub fn new() -> Self {
+        let mut chain = MockChain::default();
+        chain.push_block();
+        MockChainProvider {
+            chain: Mutex::new(chain),
+        }
+    }
+
+    /// Returns the id of the canonical chain head.
+    pub fn head(&self) -> GlobalBlockId {
+        self.chain.lock().unwrap().head().id()
+    }
+
+    /// Returns the id of the canonical block at the given height.
+    pub fn block_id(&self, number: u64) -> Option<GlobalBlockId> {
+        let chain = self.chain.lock().unwrap();
+        chain.canonical.get(number as usize).map(MockBlock::id)
+    }
+
+    /// Appends a new block to the canonical chain.
+    ///
+    /// The pending block, if any, is discarded.
+    pub fn produce_block(&self) -> GlobalBlockId {
+        let mut chain = self.chain.lock().unwrap();
+        chain.pending = None;
+        chain.push_block()
+    }
+
+    /// Appends `count` new blocks to the canonical chain.
+    pub fn produce_blocks(&self, count: usize) -> Vec<GlobalBlockId> {
+        (0..count).map(|_| self.produce_block()).collect()
+    }
+
+    /// Creates a new pending block on top of the canonical chain head.
+    ///
+    /// Like real nodes, the pending block has no hash and its number is
+    /// not known.
+    pub fn produce_pending(&self) {
+        let mut chain = self.chain.lock().unwrap();
+        let mut pending = chain.new_block();
+        pending.status = v1alpha2::BlockStatus::Pending;
+        pending.header.block_hash = Some(v1alpha2::FieldElement::from_u64(0));
+        pending.header.block_number = u64::MAX;
+        chain.pending = Some(pending);
+    }
+
+    /// Marks all canonical blocks up to `number` (inclusive) as accepted on L1.
+    pub fn finalize(&self, number: u64) {
+        let mut chain = self.chain.lock().unwrap();
+        for block in chain.canonical.iter_mut().take(number as usize + 1) {
+            block.status = v1alpha2::BlockStatus::AcceptedOnL1;
+        }
+    }
+
+    /// Changes the state update of the canonical block at `number`, like a
+    /// faulty node would.
+    pub fn corrupt_state_update(&self, number: u64) {
+        let mut chain = self.chain.lock().unwrap();
+        let block = &mut chain.canonical[number as usize];
+        block.old_root = v1alpha2::FieldElement::from_u64(u64::MAX);
+    }
+
+    /// Removes the `depth` most recent blocks from the canonical chain.
+    ///
+    /// Removed blocks can still be fetched by hash and are reported as
+    /// rejected. Blocks produced after the reorg have different hashes
+    /// than the blocks they replace.
+    pub fn reorg(&self, depth: usize) -> Vec<GlobalBlockId> {
+        let mut chain = self.chain.lock().unwrap();
+        assert!(
+            depth < chain.canonical.len(),
+            "cannot reorg the genesis block"
+        );
+        let new_len = chain.canonical.len() - depth;
+        let mut removed = chain.canonical.split_off(new_len);
+        let removed_ids = removed.iter().map(MockBlock::id).collect();
+        for block in &mut removed {
+            block.status = v1alpha2::BlockStatus::Rejected;
+        }
+        chain.rejected.extend(removed);
+        chain.pending = None;
+        chain.fork += 1;
+        removed_ids
+    }
+
+    /// Makes the next `count` requests fail with a transient error.
+    pub fn fail_next_requests(&self, count: usize) {
+        self.chain.lock().unwrap().failures = count;
+    }
+
+    /// Returns the number of requests received so far.
+    pub fn request_count(&self) -> usize {
+        self.chain.lock().unwrap().requests
+    }
+
+    fn lock_chain(&self) -> Result<std::sync::MutexGuard<'_, MockChain>, MockChainProviderError> {
+        let mut chain = self.chain.lock().unwrap();
+        chain.requests += 1;
+        if chain.failures > 0 {
+            chain.failures -= 1;
+            return Err(MockChainProviderError::Unavailable);
+        }
+        Ok(chain)
+    }
+}
+
+impl Default for MockChainProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockChain {
+    fn head(&self) -> &MockBlock {
+        self.canonical
+            .last()
+            .expect("chain always contains the genesis block")
+    }
+
+    /// Creates the block that follows the current head.
+    fn new_block(&mut self) -> MockBlock {
+        let (number, parent_block_hash) = match self.canonical.last() {
+            None => (0, v1alpha2::FieldElement::from_u64(0)),
+            Some(parent) => (
+                parent.header.block_number + 1,
+                parent.header.block_hash.clone().unwrap_or_default(),
+            ),
+        };
+
+        // start hashes from 1 so that no block hash is zero.
+        let block_hash = v1alpha2::FieldElement::from_u64(((self.fork + 1) << 32) | number);
+
+        self.transaction_count += 1;
+        let transaction_hash = v1alpha2::FieldElement::from_u64(self.transaction_count);
+
+        let header = v1alpha2::BlockHeader {
+            block_hash: Some(block_hash.clone()),
+            parent_block_hash: Some(parent_block_hash),
+            block_number: number,
+            new_root: Some(block_hash),
+            timestamp: Some(pbjson_types::Timestamp {
+                seconds: number as i64,
+                nanos: 0,
+            }),
+            ..Default::default()
+        };
+
+        let transaction = v1alpha2::Transaction {
+            meta: Some(v1alpha2::TransactionMeta {
+                hash: Some(transaction_hash.clone()),
+                ..Default::default()
+            }),
+            transaction: Some(v1alpha2::transaction::Transaction::InvokeV1(
+                v1alpha2::InvokeTransactionV1 {
+                    sender_address: Some(v1alpha2::FieldElement::from_u64(0x1)),
+                    calldata: vec![v1alpha2::FieldElement::from_u64(number)],
+                },
+            )),
+        };
+
+        let receipt = v1alpha2::TransactionReceipt {
+            transaction_hash: Some(transaction_hash),
+            actual_fee: Some(v1alpha2::FieldElement::from_u64(1)),
+            events: vec![v1alpha2::Event {
+                from_address: Some(v1alpha2::FieldElement::from_u64(0x1)),
+                keys: vec![v1alpha2::FieldElement::from_u64(number)],
+                data: Vec::default(),
+            }],
+            ..Default::default()
+        };
+
+        MockBlock {
+            status: v1alpha2::BlockStatus::AcceptedOnL2,
+            header,
+            body: BlockBody {
+                transactions: vec![transaction],
+            },
+            receipts: vec![receipt],
+            old_root: v1alpha2::FieldElement::from_u64(0),
+        }
+    }
+
+    fn push_block(&mut self) -> GlobalBlockId {
+        let block = self.new_block();
+        let id = block.id();
+        self.canonical.push(block);
+        id
+    }
+
+    fn find_block(&self, id: &BlockId) -> Option<&MockBlock> {
+        match id {
+            BlockId::Latest => self.canonical.last(),
+            BlockId::Pending => self.pending.as_ref(),
+            BlockId::Number(number) => self.canonical.get(*number as usize),
+            BlockId::Hash(hash) => self
+                .canonical
+                .iter()
+                .chain(self.rejected.iter())
+                .find(|block| block.id().hash() == hash),
+        }
+    }
+
+    fn find_receipt(&self, hash: &v1alpha2::FieldElement) -> Option<&v1alpha2::TransactionReceipt> {
+        self.canonical
+            .iter()
+            .chain(self.rejected.iter())
+            .chain(self.pending.iter())
+            .flat_map(|block| block.receipts.iter())
+            .find(|receipt| receipt.transaction_hash.as_ref() == Some(hash))
+    }
+}
+
+impl MockBlock {
+    fn id(&self) -> GlobalBlockId {
+        let hash: BlockHash = self
+            .header
+            .block_hash
+            .as_ref()
+            .map(Into::into)
+            .unwrap_or_else(BlockHash::zero);
+        GlobalBlockId::new(self.header.block_number, hash)
+    }
+}
+
+impl ProviderError for MockChainProviderError {
+    fn is_block_not_found(&self) -> bool {
+        matches!(self, MockChainProviderError::BlockNotFound)
+    }
+}
+
+#[apibara_node::async_trait]
+impl Provider for MockChainProvider {
+    type Error = MockChainProviderError;
+
+    async fn get_head(&self) -> Result<GlobalBlockId, Self::Error> {
+        let chain = self.lock_chain()?;
+        Ok(chain.head().id())
+    }
+
+    async fn get_block(
+        &self,
+        id: &BlockId,
+    ) -> Result<(v1alpha2::BlockStatus, v1alpha2::BlockHeader, BlockBody), Self::Error> {
+        let chain = self.lock_chain()?;
+        let block = chain
+            .find_block(id)
+            .ok_or(MockChainProviderError::BlockNotFound)?;
+        Ok((block.status, block.header.clone(), block.body.clone()))
+    }
+
+    async fn get_block_with_receipts(
+        &self,
+        id: &BlockId,
+    ) -> Result<
+        (
+            v1alpha2::BlockStatus,
+            v1alpha2::BlockHeader,
+            BlockBody,
+            Vec<v1alpha2::TransactionReceipt>,
+        ),
+        Self::Error,
+    > {
+        let chain = self.lock_chain()?;
+        let block = chain
+            .find_block(id)
+            .ok_or(MockChainProviderError::BlockNotFound)?;
+        Ok((
+            block.status,
+            block.header.clone(),
+            block.body.clone(),
+            block.receipts.clone(),
+        ))
+    }
+
+    async fn get_state_update(&self, id: &BlockId) -> Result<v1alpha2::StateUpdate, Self::Error> {
+        let chain = self.lock_chain()?;
+        let block = chain
+            .find_block(id)
+            .ok_or(MockChainProviderError::BlockNotFound)?;
+        Ok(v1alpha2::StateUpdate {
+            new_root: block.header.new_root.clone(),
+            old_root: Some(block.old_root.clone()),
+            state_diff: Some(v1alpha2::StateDiff::default()),
+        })
+    }
+
+    async fn get_transaction_receipt(
+        &self,
+        hash: &v1alpha2::FieldElement,
+    ) -> Result<v1alpha2::TransactionReceipt, Self::Error> {
+        let chain = self.lock_chain()?;
+        chain
+            .find_receipt(hash)
+            .cloned()
+            .ok_or(MockChainProviderError::TransactionNotFound)
+    }
+}
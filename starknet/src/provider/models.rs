@@ -0,0 +1,408 @@
+//! Response types shared by the JSON-RPC and feeder gateway providers.
+//!
+//! Blocks and receipts have a different shape in the two APIs, but
+//! transactions, prices and events use the same representation.
+use apibara_core::starknet::v1alpha2::{self, FieldElement};
+use serde::{Deserialize, Deserializer};
+
+use super::ToProto;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BlockStatus {
+    Pending,
+    AcceptedOnL2,
+    AcceptedOnL1,
+    Rejected,
+    /// Only returned by the feeder gateway.
+    Aborted,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResourcePrice {
+    pub price_in_fri: FieldElement,
+    pub price_in_wei: FieldElement,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum L1DataAvailabilityMode {
+    Blob,
+    Calldata,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Transaction {
+    #[serde(alias = "INVOKE_FUNCTION")]
+    Invoke(InvokeTransaction),
+    Declare(DeclareTransaction),
+    Deploy(DeployTransaction),
+    DeployAccount(DeployAccountTransaction),
+    L1Handler(L1HandlerTransaction),
+}
+
+/// Fields shared by all transaction types.
+///
+/// Fields not used by a transaction type or version are missing.
+#[derive(Debug, Deserialize)]
+pub struct TransactionCommon {
+    /// Missing from the transactions returned by `starknet_getBlockWithReceipts`.
+    pub transaction_hash: Option<FieldElement>,
+    #[serde(default, deserialize_with = "deserialize_u64_hex")]
+    pub version: u64,
+    #[serde(default)]
+    pub signature: Vec<FieldElement>,
+    pub nonce: Option<FieldElement>,
+    pub max_fee: Option<FieldElement>,
+    pub resource_bounds: Option<ResourceBoundsMapping>,
+    #[serde(default, deserialize_with = "deserialize_u64_hex")]
+    pub tip: u64,
+    #[serde(default)]
+    pub paymaster_data: Vec<FieldElement>,
+    pub nonce_data_availability_mode: Option<DataAvailabilityMode>,
+    pub fee_data_availability_mode: Option<DataAvailabilityMode>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InvokeTransaction {
+    #[serde(flatten)]
+    pub common: TransactionCommon,
+    pub sender_address: Option<FieldElement>,
+    pub contract_address: Option<FieldElement>,
+    pub entry_point_selector: Option<FieldElement>,
+    #[serde(default)]
+    pub calldata: Vec<FieldElement>,
+    #[serde(default)]
+    pub account_deployment_data: Vec<FieldElement>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeclareTransaction {
+    #[serde(flatten)]
+    pub common: TransactionCommon,
+    pub class_hash: FieldElement,
+    pub sender_address: Option<FieldElement>,
+    pub compiled_class_hash: Option<FieldElement>,
+    #[serde(default)]
+    pub account_deployment_data: Vec<FieldElement>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeployTransaction {
+    #[serde(flatten)]
+    pub common: TransactionCommon,
+    /// Only returned by the feeder gateway.
+    pub contract_address: Option<FieldElement>,
+    pub contract_address_salt: FieldElement,
+    pub class_hash: FieldElement,
+    #[serde(default)]
+    pub constructor_calldata: Vec<FieldElement>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeployAccountTransaction {
+    #[serde(flatten)]
+    pub common: TransactionCommon,
+    /// Only returned by the feeder gateway.
+    pub contract_address: Option<FieldElement>,
+    pub contract_address_salt: FieldElement,
+    pub class_hash: FieldElement,
+    #[serde(default)]
+    pub constructor_calldata: Vec<FieldElement>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct L1HandlerTransaction {
+    #[serde(flatten)]
+    pub common: TransactionCommon,
+    pub contract_address: FieldElement,
+    pub entry_point_selector: FieldElement,
+    #[serde(default)]
+    pub calldata: Vec<FieldElement>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResourceBoundsMapping {
+    #[serde(alias = "L1_GAS")]
+    pub l1_gas: ResourceBounds,
+    #[serde(alias = "L2_GAS")]
+    pub l2_gas: ResourceBounds,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResourceBounds {
+    #[serde(deserialize_with = "deserialize_u64_hex")]
+    pub max_amount: u64,
+    #[serde(deserialize_with = "deserialize_u128_hex")]
+    pub max_price_per_unit: u128,
+}
+
+/// The data availability mode, either as a name or as a number.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum DataAvailabilityMode {
+    Name(String),
+    Number(u64),
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ExecutionStatus {
+    Succeeded,
+    Reverted,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct L2ToL1Message {
+    pub to_address: FieldElement,
+    #[serde(default)]
+    pub payload: Vec<FieldElement>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Event {
+    pub from_address: FieldElement,
+    #[serde(default)]
+    pub keys: Vec<FieldElement>,
+    #[serde(default)]
+    pub data: Vec<FieldElement>,
+}
+
+impl Transaction {
+    pub fn common(&self) -> &TransactionCommon {
+        match self {
+            Transaction::Invoke(tx) => &tx.common,
+            Transaction::Declare(tx) => &tx.common,
+            Transaction::Deploy(tx) => &tx.common,
+            Transaction::DeployAccount(tx) => &tx.common,
+            Transaction::L1Handler(tx) => &tx.common,
+        }
+    }
+
+    /// Returns the address of the contract deployed by the transaction, if known.
+    pub fn contract_address(&self) -> Option<FieldElement> {
+        match self {
+            Transaction::Deploy(tx) => tx.contract_address.clone(),
+            Transaction::DeployAccount(tx) => tx.contract_address.clone(),
+            _ => None,
+        }
+    }
+}
+
+impl ToProto<v1alpha2::BlockStatus> for BlockStatus {
+    fn to_proto(&self) -> v1alpha2::BlockStatus {
+        match self {
+            BlockStatus::Pending => v1alpha2::BlockStatus::Pending,
+            BlockStatus::AcceptedOnL2 => v1alpha2::BlockStatus::AcceptedOnL2,
+            BlockStatus::AcceptedOnL1 => v1alpha2::BlockStatus::AcceptedOnL1,
+            BlockStatus::Rejected | BlockStatus::Aborted => v1alpha2::BlockStatus::Rejected,
+        }
+    }
+}
+
+impl ToProto<v1alpha2::ResourcePrice> for ResourcePrice {
+    fn to_proto(&self) -> v1alpha2::ResourcePrice {
+        v1alpha2::ResourcePrice {
+            price_in_fri: Some(self.price_in_fri.clone()),
+            price_in_wei: Some(self.price_in_wei.clone()),
+        }
+    }
+}
+
+impl ToProto<v1alpha2::L1DataAvailabilityMode> for L1DataAvailabilityMode {
+    fn to_proto(&self) -> v1alpha2::L1DataAvailabilityMode {
+        match self {
+            L1DataAvailabilityMode::Blob => v1alpha2::L1DataAvailabilityMode::Blob,
+            L1DataAvailabilityMode::Calldata => v1alpha2::L1DataAvailabilityMode::Calldata,
+        }
+    }
+}
+
+impl ToProto<v1alpha2::Transaction> for Transaction {
+    fn to_proto(&self) -> v1alpha2::Transaction {
+        use v1alpha2::transaction::Transaction as ProtoTransaction;
+
+        let transaction = match self {
+            Transaction::Invoke(invoke) => {
+                let sender_address = invoke
+                    .sender_address
+                    .clone()
+                    .or_else(|| invoke.contract_address.clone());
+                let calldata = invoke.calldata.clone();
+                match invoke.common.version {
+                    0 => ProtoTransaction::InvokeV0(v1alpha2::InvokeTransactionV0 {
+                        contract_address: sender_address,
+                        entry_point_selector: invoke.entry_point_selector.clone(),
+                        calldata,
+                    }),
+                    1 | 2 => ProtoTransaction::InvokeV1(v1alpha2::InvokeTransactionV1 {
+                        sender_address,
+                        calldata,
+                    }),
+                    _ => ProtoTransaction::InvokeV3(v1alpha2::InvokeTransactionV3 {
+                        sender_address,
+                        calldata,
+                        account_deployment_data: invoke.account_deployment_data.clone(),
+                    }),
+                }
+            }
+            Transaction::Declare(declare) => match declare.common.version {
+                0 | 1 => ProtoTransaction::Declare(v1alpha2::DeclareTransaction {
+                    class_hash: Some(declare.class_hash.clone()),
+                    sender_address: declare.sender_address.clone(),
+                }),
+                2 => ProtoTransaction::DeclareV2(v1alpha2::DeclareTransactionV2 {
+                    class_hash: Some(declare.class_hash.clone()),
+                    sender_address: declare.sender_address.clone(),
+                    compiled_class_hash: declare.compiled_class_hash.clone(),
+                }),
+                _ => ProtoTransaction::DeclareV3(v1alpha2::DeclareTransactionV3 {
+                    class_hash: Some(declare.class_hash.clone()),
+                    sender_address: declare.sender_address.clone(),
+                    compiled_class_hash: declare.compiled_class_hash.clone(),
+                    account_deployment_data: declare.account_deployment_data.clone(),
+                }),
+            },
+            Transaction::Deploy(deploy) => ProtoTransaction::Deploy(v1alpha2::DeployTransaction {
+                constructor_calldata: deploy.constructor_calldata.clone(),
+                contract_address_salt: Some(deploy.contract_address_salt.clone()),
+                class_hash: Some(deploy.class_hash.clone()),
+            }),
+            Transaction::DeployAccount(deploy) => {
+                let constructor_calldata = deploy.constructor_calldata.clone();
+                let contract_address_salt = Some(deploy.contract_address_salt.clone());
+                let class_hash = Some(deploy.class_hash.clone());
+                if deploy.common.version < 3 {
+                    ProtoTransaction::DeployAccount(v1alpha2::DeployAccountTransaction {
+                        constructor_calldata,
+                        contract_address_salt,
+                        class_hash,
+                    })
+                } else {
+                    ProtoTransaction::DeployAccountV3(v1alpha2::DeployAccountTransactionV3 {
+                        constructor_calldata,
+                        contract_address_salt,
+                        class_hash,
+                    })
+                }
+            }
+            Transaction::L1Handler(l1_handler) => {
+                ProtoTransaction::L1Handler(v1alpha2::L1HandlerTransaction {
+                    contract_address: Some(l1_handler.contract_address.clone()),
+                    entry_point_selector: Some(l1_handler.entry_point_selector.clone()),
+                    calldata: l1_handler.calldata.clone(),
+                })
+            }
+        };
+
+        v1alpha2::Transaction {
+            meta: Some(self.common().to_proto()),
+            transaction: Some(transaction),
+        }
+    }
+}
+
+impl ToProto<v1alpha2::TransactionMeta> for TransactionCommon {
+    fn to_proto(&self) -> v1alpha2::TransactionMeta {
+        let resource_bounds =
+            self.resource_bounds
+                .as_ref()
+                .map(|bounds| v1alpha2::ResourceBoundsMapping {
+                    l1_gas: Some(bounds.l1_gas.to_proto()),
+                    l2_gas: Some(bounds.l2_gas.to_proto()),
+                });
+        let nonce_data_availability_mode = self
+            .nonce_data_availability_mode
+            .as_ref()
+            .map(|m| m.to_proto())
+            .unwrap_or(v1alpha2::DataAvailabilityMode::Unspecified);
+        let fee_data_availability_mode = self
+            .fee_data_availability_mode
+            .as_ref()
+            .map(|m| m.to_proto())
+            .unwrap_or(v1alpha2::DataAvailabilityMode::Unspecified);
+
+        v1alpha2::TransactionMeta {
+            hash: self.transaction_hash.clone(),
+            max_fee: self.max_fee.clone(),
+            signature: self.signature.clone(),
+            nonce: self.nonce.clone(),
+            version: self.version,
+            resource_bounds,
+            tip: self.tip,
+            paymaster_data: self.paymaster_data.clone(),
+            nonce_data_availability_mode: nonce_data_availability_mode as i32,
+            fee_data_availability_mode: fee_data_availability_mode as i32,
+        }
+    }
+}
+
+impl ToProto<v1alpha2::ResourceBounds> for ResourceBounds {
+    fn to_proto(&self) -> v1alpha2::ResourceBounds {
+        let max_price_per_unit = v1alpha2::Uint128 {
+            low: self.max_price_per_unit as u64,
+            high: (self.max_price_per_unit >> 64) as u64,
+        };
+        v1alpha2::ResourceBounds {
+            max_amount: self.max_amount,
+            max_price_per_unit: Some(max_price_per_unit),
+        }
+    }
+}
+
+impl ToProto<v1alpha2::DataAvailabilityMode> for DataAvailabilityMode {
+    fn to_proto(&self) -> v1alpha2::DataAvailabilityMode {
+        match self {
+            DataAvailabilityMode::Name(name) if name == "L1" => v1alpha2::DataAvailabilityMode::L1,
+            DataAvailabilityMode::Name(name) if name == "L2" => v1alpha2::DataAvailabilityMode::L2,
+            DataAvailabilityMode::Number(0) => v1alpha2::DataAvailabilityMode::L1,
+            DataAvailabilityMode::Number(1) => v1alpha2::DataAvailabilityMode::L2,
+            _ => v1alpha2::DataAvailabilityMode::Unspecified,
+        }
+    }
+}
+
+impl ToProto<v1alpha2::ExecutionStatus> for ExecutionStatus {
+    fn to_proto(&self) -> v1alpha2::ExecutionStatus {
+        match self {
+            ExecutionStatus::Succeeded => v1alpha2::ExecutionStatus::Succeeded,
+            ExecutionStatus::Reverted => v1alpha2::ExecutionStatus::Reverted,
+        }
+    }
+}
+
+impl ToProto<v1alpha2::L2ToL1Message> for L2ToL1Message {
+    fn to_proto(&self) -> v1alpha2::L2ToL1Message {
+        v1alpha2::L2ToL1Message {
+            to_address: Some(self.to_address.clone()),
+            payload: self.payload.clone(),
+        }
+    }
+}
+
+impl ToProto<v1alpha2::Event> for Event {
+    fn to_proto(&self) -> v1alpha2::Event {
+        v1alpha2::Event {
+            from_address: Some(self.from_address.clone()),
+            keys: self.keys.clone(),
+            data: self.data.clone(),
+        }
+    }
+}
+
+fn deserialize_u64_hex<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    u64::from_str_radix(s.trim_start_matches("0x"), 16).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_u128_hex<'de, D>(deserializer: D) -> Result<u128, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    u128::from_str_radix(s.trim_start_matches("0x"), 16).map_err(serde::de::Error::custom)
+}
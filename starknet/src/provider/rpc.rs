@@ -0,0 +1,758 @@
+//! JSON-RPC client with support for batch requests.
+//!
+//! The client provided by starknet-rs only knows about the methods of the RPC
+//! version it was written for, and sends one request at a time. This client
+//! is used for the methods it doesn't support and to batch requests together.
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use apibara_core::starknet::v1alpha2;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use url::Url;
+
+use crate::db::BlockBody;
+
+use super::{BlockId, HttpProviderError, ToProto, TryToProto};
+
+/// Error code returned by nodes that don't implement the requested method.
+const METHOD_NOT_FOUND_CODE: i64 = -32601;
+
+/// Error code returned by StarkNet nodes when a block is not found.
+const BLOCK_NOT_FOUND_CODE: i64 = 24;
+
+/// A JSON-RPC client over HTTP.
+pub struct RpcClient {
+    client: reqwest::Client,
+    rpc_url: Url,
+    next_id: AtomicU64,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RpcClientError {
+    #[error("rpc request failed")]
+    Request(#[from] reqwest::Error),
+    #[error("rpc error {code}: {message}")]
+    Rpc { code: i64, message: String },
+    #[error("rpc response has neither result nor error")]
+    MissingResult,
+    #[error("rpc batch response is missing request {0}")]
+    MissingResponse(u64),
+    #[error("rpc batch response contains unexpected id")]
+    UnexpectedResponseId,
+}
+
+#[derive(Debug, Serialize)]
+struct Request<'a, P> {
+    jsonrpc: &'static str,
+    id: u64,
+    method: &'a str,
+    params: P,
+}
+
+#[derive(Debug, Deserialize)]
+struct Response<R> {
+    id: Option<u64>,
+    result: Option<R>,
+    error: Option<ErrorObject>,
+}
+
+/// Nodes that don't support batches reply with a single error.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum BatchResponse<R> {
+    Batch(Vec<Response<R>>),
+    Single(Response<R>),
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorObject {
+    code: i64,
+    message: String,
+}
+
+impl RpcClient {
+    pub fn new(rpc_url: Url) -> Self {
+        RpcClient {
+            client: reqwest::Client::new(),
+            rpc_url,
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Sends a single request.
+    pub async fn call<P, R>(&self, method: &str, params: P) -> Result<R, RpcClientError>
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+    {
+        let request = Request::new(self.next_ids(1), method, params);
+        let response: Response<R> = self
+            .client
+            .post(self.rpc_url.clone())
+            .json(&request)
+            .send()
+            .await?
+            .json()
+            .await?;
+        response.into_result()
+    }
+
+    /// Sends one request for each item in `params` in a single batch.
+    ///
+    /// Results are returned in the same order as `params`.
+    pub async fn batch_call<P, R>(
+        &self,
+        method: &str,
+        params: Vec<P>,
+    ) -> Result<Vec<R>, RpcClientError>
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+    {
+        if params.is_empty() {
+            return Ok(Vec::default());
+        }
+
+        let size = params.len();
+        let first_id = self.next_ids(size as u64);
+        let requests = params
+            .into_iter()
+            .enumerate()
+            .map(|(index, params)| Request::new(first_id + index as u64, method, params))
+            .collect::<Vec<_>>();
+
+        let response: BatchResponse<R> = self
+            .client
+            .post(self.rpc_url.clone())
+            .json(&requests)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        let responses = match response {
+            BatchResponse::Batch(responses) => responses,
+            BatchResponse::Single(response) => {
+                // a single response is always an error, but be defensive.
+                response.into_result()?;
+                return Err(RpcClientError::MissingResponse(first_id));
+            }
+        };
+
+        // responses can be in any order.
+        let mut results = (0..size).map(|_| None).collect::<Vec<_>>();
+        for response in responses {
+            let index = response
+                .id
+                .and_then(|id| id.checked_sub(first_id))
+                .map(|index| index as usize)
+                .filter(|index| *index < size)
+                .ok_or(RpcClientError::UnexpectedResponseId)?;
+            results[index] = Some(response.into_result()?);
+        }
+
+        results
+            .into_iter()
+            .enumerate()
+            .map(|(index, result)| {
+                result.ok_or(RpcClientError::MissingResponse(first_id + index as u64))
+            })
+            .collect()
+    }
+
+    /// Get a block together with its transactions with `starknet_getBlockWithTxs`.
+    pub async fn get_block_with_txs(
+        &self,
+        id: &BlockId,
+    ) -> Result<models::BlockWithTxs, RpcClientError> {
+        let params = json!({ "block_id": id.to_rpc_param() });
+        self.call("starknet_getBlockWithTxs", params).await
+    }
+
+    /// Get a block together with its receipts with `starknet_getBlockWithReceipts`.
+    pub async fn get_block_with_receipts(
+        &self,
+        id: &BlockId,
+    ) -> Result<models::BlockWithReceipts, RpcClientError> {
+        let params = json!({ "block_id": id.to_rpc_param() });
+        self.call("starknet_getBlockWithReceipts", params).await
+    }
+
+    /// Get the receipt of a transaction with `starknet_getTransactionReceipt`.
+    pub async fn get_transaction_receipt(
+        &self,
+        hash: &v1alpha2::FieldElement,
+    ) -> Result<models::TransactionReceipt, RpcClientError> {
+        let params = json!({ "transaction_hash": hash.to_hex() });
+        self.call("starknet_getTransactionReceipt", params).await
+    }
+
+    /// Get the receipts of the given transactions with a batch of
+    /// `starknet_getTransactionReceipt` requests.
+    ///
+    /// Receipts are returned in the same order as `hashes`.
+    pub async fn get_transaction_receipts(
+        &self,
+        hashes: &[v1alpha2::FieldElement],
+    ) -> Result<Vec<models::TransactionReceipt>, RpcClientError> {
+        let params = hashes
+            .iter()
+            .map(|hash| json!({ "transaction_hash": hash.to_hex() }))
+            .collect();
+        self.batch_call("starknet_getTransactionReceipt", params)
+            .await
+    }
+
+    /// Reserves `count` consecutive request ids, returning the first one.
+    fn next_ids(&self, count: u64) -> u64 {
+        self.next_id.fetch_add(count, Ordering::Relaxed)
+    }
+}
+
+impl RpcClientError {
+    pub fn is_method_not_found(&self) -> bool {
+        matches!(self, RpcClientError::Rpc { code, .. } if *code == METHOD_NOT_FOUND_CODE)
+    }
+
+    pub fn is_block_not_found(&self) -> bool {
+        matches!(self, RpcClientError::Rpc { code, .. } if *code == BLOCK_NOT_FOUND_CODE)
+    }
+}
+
+impl<'a, P> Request<'a, P> {
+    fn new(id: u64, method: &'a str, params: P) -> Self {
+        Request {
+            jsonrpc: "2.0",
+            id,
+            method,
+            params,
+        }
+    }
+}
+
+impl<R> Response<R> {
+    fn into_result(self) -> Result<R, RpcClientError> {
+        match (self.result, self.error) {
+            (_, Some(error)) => Err(RpcClientError::Rpc {
+                code: error.code,
+                message: error.message,
+            }),
+            (Some(result), None) => Ok(result),
+            (None, None) => Err(RpcClientError::MissingResult),
+        }
+    }
+}
+
+impl BlockId {
+    /// Returns the block id as expected by the `block_id` parameter.
+    fn to_rpc_param(&self) -> serde_json::Value {
+        match self {
+            BlockId::Latest => json!("latest"),
+            BlockId::Pending => json!("pending"),
+            BlockId::Number(number) => json!({ "block_number": number }),
+            BlockId::Hash(hash) => {
+                json!({ "block_hash": v1alpha2::FieldElement::from(hash).to_hex() })
+            }
+        }
+    }
+}
+
+impl ToProto<v1alpha2::BlockStatus> for models::BlockHeader {
+    fn to_proto(&self) -> v1alpha2::BlockStatus {
+        self.status
+            .map(|status| status.to_proto())
+            .unwrap_or(v1alpha2::BlockStatus::Pending)
+    }
+}
+
+impl TryToProto<v1alpha2::BlockHeader> for models::BlockHeader {
+    type Error = HttpProviderError;
+
+    fn try_to_proto(&self) -> Result<v1alpha2::BlockHeader, Self::Error> {
+        // pending blocks don't have a hash or number yet.
+        let (block_hash, block_number) = match (&self.block_hash, self.block_number) {
+            (Some(block_hash), Some(block_number)) => (block_hash.clone(), block_number),
+            (None, None) => (v1alpha2::FieldElement::from_u64(0), u64::MAX),
+            _ => {
+                return Err(HttpProviderError::MalformedResponse(
+                    "missing block hash or number",
+                ))
+            }
+        };
+
+        let timestamp = pbjson_types::Timestamp {
+            nanos: 0,
+            seconds: self.timestamp as i64,
+        };
+
+        let l1_da_mode = self
+            .l1_da_mode
+            .as_ref()
+            .map(|m| m.to_proto())
+            .unwrap_or(v1alpha2::L1DataAvailabilityMode::Unspecified);
+
+        Ok(v1alpha2::BlockHeader {
+            block_hash: Some(block_hash),
+            parent_block_hash: Some(self.parent_hash.clone()),
+            block_number,
+            sequencer_address: Some(self.sequencer_address.clone()),
+            new_root: self.new_root.clone(),
+            timestamp: Some(timestamp),
+            l1_gas_price: self.l1_gas_price.as_ref().map(|p| p.to_proto()),
+            l1_data_gas_price: self.l1_data_gas_price.as_ref().map(|p| p.to_proto()),
+            starknet_version: self.starknet_version.clone().unwrap_or_default(),
+            l1_da_mode: l1_da_mode as i32,
+        })
+    }
+}
+
+impl ToProto<BlockBody> for models::BlockWithTxs {
+    fn to_proto(&self) -> BlockBody {
+        let transactions = self.transactions.iter().map(|tx| tx.to_proto()).collect();
+        BlockBody { transactions }
+    }
+}
+
+impl ToProto<BlockBody> for models::BlockWithReceipts {
+    fn to_proto(&self) -> BlockBody {
+        let transactions = self.transactions.iter().map(|tx| tx.to_proto()).collect();
+        BlockBody { transactions }
+    }
+}
+
+impl ToProto<Vec<v1alpha2::TransactionReceipt>> for models::BlockWithReceipts {
+    fn to_proto(&self) -> Vec<v1alpha2::TransactionReceipt> {
+        self.transactions
+            .iter()
+            .enumerate()
+            .map(|(index, tx)| {
+                let mut receipt = tx.receipt.to_proto();
+                receipt.transaction_index = index as u64;
+                receipt
+            })
+            .collect()
+    }
+}
+
+impl ToProto<v1alpha2::Transaction> for models::TransactionWithReceipt {
+    fn to_proto(&self) -> v1alpha2::Transaction {
+        let mut transaction = self.transaction.to_proto();
+        // the transaction hash is only guaranteed to be in the receipt.
+        if let Some(meta) = transaction.meta.as_mut() {
+            meta.hash = Some(self.receipt.transaction_hash.clone());
+        }
+        transaction
+    }
+}
+
+impl ToProto<v1alpha2::TransactionReceipt> for models::TransactionReceipt {
+    fn to_proto(&self) -> v1alpha2::TransactionReceipt {
+        let l2_to_l1_messages = self
+            .messages_sent
+            .iter()
+            .map(|msg| msg.to_proto())
+            .collect();
+        let events = self.events.iter().map(|ev| ev.to_proto()).collect();
+
+        // nodes that implement a RPC version older than v0.4 don't report the
+        // execution status, so it's unknown.
+        let execution_status = self
+            .execution_status
+            .map(|status| status.to_proto())
+            .unwrap_or(v1alpha2::ExecutionStatus::Unspecified);
+
+        v1alpha2::TransactionReceipt {
+            transaction_hash: Some(self.transaction_hash.clone()),
+            transaction_index: 0,
+            actual_fee: Some(self.actual_fee.amount().clone()),
+            l2_to_l1_messages,
+            events,
+            contract_address: self.contract_address.clone(),
+            execution_status: execution_status as i32,
+            revert_reason: self.revert_reason.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// RPC response types for the methods and RPC versions not supported by starknet-rs.
+mod models {
+    use apibara_core::starknet::v1alpha2::FieldElement;
+    use serde::Deserialize;
+
+    use crate::provider::models::{
+        BlockStatus, Event, ExecutionStatus, L1DataAvailabilityMode, L2ToL1Message, ResourcePrice,
+        Transaction,
+    };
+
+    /// The header fields of a block, shared by all block types.
+    #[derive(Debug, Deserialize)]
+    pub struct BlockHeader {
+        /// Missing for pending blocks.
+        pub status: Option<BlockStatus>,
+        pub block_hash: Option<FieldElement>,
+        pub block_number: Option<u64>,
+        pub new_root: Option<FieldElement>,
+        pub parent_hash: FieldElement,
+        pub timestamp: u64,
+        pub sequencer_address: FieldElement,
+        pub l1_gas_price: Option<ResourcePrice>,
+        pub l1_data_gas_price: Option<ResourcePrice>,
+        pub l1_da_mode: Option<L1DataAvailabilityMode>,
+        pub starknet_version: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct BlockWithTxs {
+        #[serde(flatten)]
+        pub header: BlockHeader,
+        #[serde(default)]
+        pub transactions: Vec<Transaction>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct BlockWithReceipts {
+        #[serde(flatten)]
+        pub header: BlockHeader,
+        #[serde(default)]
+        pub transactions: Vec<TransactionWithReceipt>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct TransactionWithReceipt {
+        pub transaction: Transaction,
+        pub receipt: TransactionReceipt,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct TransactionReceipt {
+        pub transaction_hash: FieldElement,
+        pub actual_fee: FeePayment,
+        pub execution_status: Option<ExecutionStatus>,
+        pub revert_reason: Option<String>,
+        #[serde(default)]
+        pub messages_sent: Vec<L2ToL1Message>,
+        #[serde(default)]
+        pub events: Vec<Event>,
+        /// Only for deploy and deploy account transactions.
+        pub contract_address: Option<FieldElement>,
+    }
+
+    /// The fee paid by a transaction.
+    ///
+    /// RPC versions before v0.6 return the amount only.
+    #[derive(Debug, Deserialize)]
+    #[serde(untagged)]
+    pub enum FeePayment {
+        Payment { amount: FieldElement },
+        Amount(FieldElement),
+    }
+
+    impl BlockHeader {
+        pub fn is_pending(&self) -> bool {
+            self.block_hash.is_none()
+        }
+    }
+
+    impl FeePayment {
+        pub fn amount(&self) -> &FieldElement {
+            match self {
+                FeePayment::Payment { amount } => amount,
+                FeePayment::Amount(amount) => amount,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use apibara_core::starknet::v1alpha2::{self, transaction::Transaction, FieldElement};
+    use assert_matches::assert_matches;
+    use hyper::{Body, Request, Response};
+    use serde_json::{json, Value};
+    use url::Url;
+
+    use crate::{
+        db::BlockBody,
+        provider::{
+            fixture_server::start_fixture_server, BlockId, HttpProvider, HttpProviderError,
+            Provider, ToProto, TryToProto,
+        },
+    };
+
+    use super::RpcClient;
+
+    const GET_BLOCK_WITH_RECEIPTS_100: &str =
+        include_str!("../../fixtures/rpc/get_block_with_receipts_100.json");
+    const GET_BLOCK_WITH_TXS_200: &str =
+        include_str!("../../fixtures/rpc/get_block_with_txs_200.json");
+
+    /// Answers a single JSON-RPC request.
+    ///
+    /// The `echo` method returns its params.
+    fn rpc_response(request: &Value) -> Value {
+        let id = request["id"].clone();
+        match request["method"].as_str() {
+            Some("echo") => json!({ "jsonrpc": "2.0", "id": id, "result": request["params"] }),
+            Some("starknet_getBlockWithReceipts") => {
+                serde_json::from_str(GET_BLOCK_WITH_RECEIPTS_100).expect("valid fixture")
+            }
+            Some("starknet_getBlockWithTxs") => {
+                serde_json::from_str(GET_BLOCK_WITH_TXS_200).expect("valid fixture")
+            }
+            Some("starknet_getTransactionReceipt") => {
+                let hash = request["params"]["transaction_hash"].clone();
+                json!({ "jsonrpc": "2.0", "id": id, "result": transaction_receipt(hash) })
+            }
+            _ => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32601, "message": "Method not found" }
+            }),
+        }
+    }
+
+    /// Returns the receipt of the transaction with the given hash.
+    ///
+    /// Receipts use the format of different RPC versions.
+    fn transaction_receipt(hash: Value) -> Value {
+        let short_hash = hash
+            .as_str()
+            .unwrap_or_default()
+            .trim_start_matches("0x")
+            .trim_start_matches('0');
+        match short_hash {
+            // v0.5, with the fee as a single amount.
+            "21" => json!({
+                "type": "INVOKE",
+                "transaction_hash": hash,
+                "actual_fee": "0x10",
+                "execution_status": "SUCCEEDED",
+                "finality_status": "ACCEPTED_ON_L2",
+                "messages_sent": [],
+                "events": [{ "from_address": "0x41", "keys": ["0x99"], "data": [] }]
+            }),
+            "22" => json!({
+                "type": "INVOKE",
+                "transaction_hash": hash,
+                "actual_fee": { "amount": "0x10", "unit": "WEI" },
+                "execution_status": "REVERTED",
+                "finality_status": "ACCEPTED_ON_L2",
+                "revert_reason": "Error in the called contract.",
+                "messages_sent": [],
+                "events": []
+            }),
+            // before v0.4, without the execution status.
+            _ => json!({
+                "type": "INVOKE",
+                "transaction_hash": hash,
+                "actual_fee": "0x10",
+                "status": "ACCEPTED_ON_L2",
+                "messages_sent": [],
+                "events": []
+            }),
+        }
+    }
+
+    async fn handle(req: Request<Body>) -> Response<Body> {
+        let body = hyper::body::to_bytes(req.into_body())
+            .await
+            .expect("request body");
+        let request: Value = serde_json::from_slice(&body).expect("json request");
+        let response = match request {
+            // answer batches in reverse order, like some nodes do.
+            Value::Array(requests) => {
+                Value::Array(requests.iter().rev().map(rpc_response).collect())
+            }
+            request => rpc_response(&request),
+        };
+        Response::new(Body::from(response.to_string()))
+    }
+
+    /// Starts a local JSON-RPC server, returning its url.
+    fn start_rpc_server() -> Url {
+        start_fixture_server("/rpc", handle)
+    }
+
+    #[tokio::test]
+    async fn test_batch_call_keeps_order() {
+        let client = RpcClient::new(start_rpc_server());
+        let params = (0..10u64).map(|n| vec![n]).collect::<Vec<_>>();
+        let results: Vec<Vec<u64>> = client.batch_call("echo", params.clone()).await.unwrap();
+        assert_eq!(results, params);
+    }
+
+    #[tokio::test]
+    async fn test_method_not_found() {
+        let client = RpcClient::new(start_rpc_server());
+        let err = client
+            .call::<_, Value>("starknet_unknownMethod", json!({}))
+            .await
+            .unwrap_err();
+        assert!(err.is_method_not_found());
+
+        let err = client
+            .batch_call::<_, Value>("starknet_unknownMethod", vec![json!({})])
+            .await
+            .unwrap_err();
+        assert!(err.is_method_not_found());
+    }
+
+    #[tokio::test]
+    async fn test_get_block_with_receipts() {
+        let client = RpcClient::new(start_rpc_server());
+        let block = client
+            .get_block_with_receipts(&BlockId::Number(100))
+            .await
+            .unwrap();
+        assert!(!block.header.is_pending());
+
+        let status: v1alpha2::BlockStatus = block.header.to_proto();
+        assert_eq!(status, v1alpha2::BlockStatus::AcceptedOnL2);
+
+        let header: v1alpha2::BlockHeader = block.header.try_to_proto().unwrap();
+        assert_eq!(header.block_number, 100);
+        assert_eq!(header.l1_da_mode(), v1alpha2::L1DataAvailabilityMode::Blob);
+
+        let body: BlockBody = block.to_proto();
+        let receipts: Vec<v1alpha2::TransactionReceipt> = block.to_proto();
+        assert_eq!(body.transactions.len(), 2);
+        assert_eq!(receipts.len(), 2);
+
+        let invoke = &body.transactions[0];
+        assert_matches!(invoke.transaction, Some(Transaction::InvokeV3(_)));
+        let meta = invoke.meta.as_ref().unwrap();
+        assert_eq!(meta.hash, Some(FieldElement::from_u64(0x12)));
+        assert_eq!(meta.version, 3);
+        assert_eq!(
+            meta.fee_data_availability_mode(),
+            v1alpha2::DataAvailabilityMode::L1
+        );
+        assert!(receipts[0].is_reverted());
+        assert_eq!(receipts[0].revert_reason, "Error in the called contract.");
+
+        let deploy = &body.transactions[1];
+        assert_matches!(deploy.transaction, Some(Transaction::DeployAccount(_)));
+        assert_eq!(receipts[1].transaction_index, 1);
+        assert_eq!(
+            receipts[1].contract_address,
+            Some(FieldElement::from_u64(0x44))
+        );
+        assert_eq!(receipts[1].events.len(), 1);
+        assert_eq!(receipts[1].l2_to_l1_messages.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_block_with_txs() {
+        let client = RpcClient::new(start_rpc_server());
+        let block = client
+            .get_block_with_txs(&BlockId::Number(200))
+            .await
+            .unwrap();
+        assert!(!block.header.is_pending());
+
+        let status: v1alpha2::BlockStatus = block.header.to_proto();
+        assert_eq!(status, v1alpha2::BlockStatus::AcceptedOnL1);
+
+        let body: BlockBody = block.to_proto();
+        assert_eq!(body.transactions.len(), 4);
+
+        let invoke = &body.transactions[0];
+        assert_matches!(invoke.transaction, Some(Transaction::InvokeV3(_)));
+        let meta = invoke.meta.as_ref().unwrap();
+        assert_eq!(meta.hash, Some(FieldElement::from_u64(0x31)));
+        assert_eq!(meta.version, 3);
+
+        let declare = &body.transactions[1];
+        assert_matches!(declare.transaction, Some(Transaction::DeclareV2(_)));
+        assert_eq!(declare.meta.as_ref().unwrap().version, 2);
+
+        let declare = &body.transactions[2];
+        assert_matches!(declare.transaction, Some(Transaction::DeclareV3(_)));
+        let meta = declare.meta.as_ref().unwrap();
+        assert_eq!(meta.version, 3);
+        assert!(meta.resource_bounds.is_some());
+
+        let deploy = &body.transactions[3];
+        assert_matches!(deploy.transaction, Some(Transaction::DeployAccountV3(_)));
+        assert_eq!(
+            deploy.meta.as_ref().unwrap().hash,
+            Some(FieldElement::from_u64(0x34))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_provider_block_header() {
+        let provider = HttpProvider::new(start_rpc_server());
+        let (status, header, body) = provider.get_block(&BlockId::Number(200)).await.unwrap();
+        assert_eq!(status, v1alpha2::BlockStatus::AcceptedOnL1);
+        assert_eq!(body.transactions.len(), 4);
+
+        assert_eq!(header.block_number, 200);
+        assert_eq!(header.timestamp.unwrap().seconds, 1700001000);
+        assert_eq!(header.starknet_version, "0.13.1");
+        assert_eq!(
+            header.l1_da_mode(),
+            v1alpha2::L1DataAvailabilityMode::Calldata
+        );
+        let l1_gas_price = header.l1_gas_price.unwrap();
+        assert_eq!(
+            l1_gas_price.price_in_wei,
+            Some(FieldElement::from_u64(0x3b9aca00))
+        );
+        assert_eq!(
+            l1_gas_price.price_in_fri,
+            Some(FieldElement::from_u64(0x5af3107a4000))
+        );
+        let l1_data_gas_price = header.l1_data_gas_price.unwrap();
+        assert_eq!(
+            l1_data_gas_price.price_in_wei,
+            Some(FieldElement::from_u64(1))
+        );
+
+        // the node returns a non-pending block.
+        let err = provider.get_block(&BlockId::Pending).await.unwrap_err();
+        assert_matches!(err, HttpProviderError::ExpectedPendingBlock);
+    }
+
+    #[tokio::test]
+    async fn test_get_transaction_receipts_execution_status() {
+        let client = RpcClient::new(start_rpc_server());
+        let hashes = vec![
+            FieldElement::from_u64(0x21),
+            FieldElement::from_u64(0x22),
+            FieldElement::from_u64(0x23),
+        ];
+        let receipts: Vec<v1alpha2::TransactionReceipt> = client
+            .get_transaction_receipts(&hashes)
+            .await
+            .unwrap()
+            .iter()
+            .map(|receipt| receipt.to_proto())
+            .collect();
+        assert_eq!(receipts.len(), 3);
+
+        assert_eq!(receipts[0].transaction_hash, Some(hashes[0].clone()));
+        assert_eq!(
+            receipts[0].execution_status(),
+            v1alpha2::ExecutionStatus::Succeeded
+        );
+        assert_eq!(receipts[0].actual_fee, Some(FieldElement::from_u64(0x10)));
+        assert_eq!(receipts[0].events.len(), 1);
+
+        assert!(receipts[1].is_reverted());
+        assert_eq!(receipts[1].revert_reason, "Error in the called contract.");
+
+        // the node doesn't report the status, so don't claim the transaction succeeded.
+        assert_eq!(
+            receipts[2].execution_status(),
+            v1alpha2::ExecutionStatus::Unspecified
+        );
+
+        let receipt = client
+            .get_transaction_receipt(&hashes[1])
+            .await
+            .unwrap()
+            .to_proto();
+        assert!(receipt.is_reverted());
+    }
+}
@@ -7,45 +7,91 @@ use apibara_node::db::{
     MdbxTransactionExt,
 };
 use tokio_util::sync::CancellationToken;
-use tonic_health::proto::health_server::{Health, HealthServer};
+use tonic_health::{
+    proto::health_server::{Health, HealthServer},
+    ServingStatus,
+};
 use tracing::warn;
 
-use crate::db::tables;
+use crate::{
+    db::{tables, DatabaseStorage, StorageReader},
+    ingestion::IngestionStreamClient,
+    provider::Provider,
+};
+
+/// Default number of blocks the accepted head can lag behind the RPC head.
+pub const DEFAULT_MAX_HEAD_LAG: u64 = 10;
 
-pub struct HealthReporter<E: EnvironmentKind> {
+/// Number of consecutive failures to fetch the RPC head before the stream is
+/// reported as not serving.
+const MAX_HEAD_ERRORS: usize = 3;
+
+/// Name used to report the health of the whole server.
+const SERVER_SERVICE_NAME: &str = "";
+
+pub struct HealthReporter<G: Provider, E: EnvironmentKind> {
     db: Arc<Environment<E>>,
-    _reporter: tonic_health::server::HealthReporter,
+    provider: Arc<G>,
+    ingestion: Arc<IngestionStreamClient>,
+    stream_service_name: &'static str,
+    max_head_lag: u64,
+    /// Number of consecutive failures to fetch the RPC head.
+    head_errors: usize,
+    reporter: tonic_health::server::HealthReporter,
 }
 
-impl<E> HealthReporter<E>
+impl<G, E> HealthReporter<G, E>
 where
+    G: Provider,
     E: EnvironmentKind,
 {
-    pub fn new(db: Arc<Environment<E>>) -> (Self, HealthServer<impl Health>) {
+    pub fn new(
+        db: Arc<Environment<E>>,
+        provider: Arc<G>,
+        ingestion: Arc<IngestionStreamClient>,
+        stream_service_name: &'static str,
+        max_head_lag: u64,
+    ) -> (Self, HealthServer<impl Health>) {
         let (reporter, service) = tonic_health::server::health_reporter();
         (
             HealthReporter {
                 db,
-                _reporter: reporter,
+                provider,
+                ingestion,
+                stream_service_name,
+                max_head_lag,
+                head_errors: 0,
+                reporter,
             },
             service,
         )
     }
 
     pub async fn start(&mut self, ct: CancellationToken) {
-        let interval = Duration::from_secs(1);
+        let interval = Duration::from_secs(5);
         loop {
             if ct.is_cancelled() {
                 return;
             }
 
-            if self.check_db().is_ok() {
-                self.set_serving().await;
-            } else {
-                self.set_not_serving().await;
-            }
+            // the server can answer requests as long as the database is accessible,
+            // but streams are useful only if the node is following the chain.
+            let db_ok = match self.check_db() {
+                Ok(_) => true,
+                Err(err) => {
+                    warn!(err = ?err, "database check failed");
+                    false
+                }
+            };
+            let stream_ok = db_ok && self.check_ingestion() && self.check_head_lag().await;
 
-            tokio::time::sleep(interval).await;
+            self.set_status(SERVER_SERVICE_NAME, db_ok).await;
+            self.set_status(self.stream_service_name, stream_ok).await;
+
+            tokio::select! {
+                _ = ct.cancelled() => {},
+                _ = tokio::time::sleep(interval) => {},
+            }
         }
     }
 
@@ -58,20 +104,170 @@ where
         Ok(())
     }
 
-    async fn set_serving(&mut self) {
-        /*
-        self.reporter
-            .set_serving::<pb::node_server::NodeServer<NodeServer<E>>>()
-            .await
-        */
+    fn check_ingestion(&self) -> bool {
+        let is_running = self.ingestion.is_running();
+        if !is_running {
+            warn!("block ingestion is not running");
+        }
+        is_running
+    }
+
+    /// Returns true if the accepted head is close enough to the RPC head.
+    ///
+    /// If the RPC is not reachable, the lag cannot be measured. The check keeps
+    /// passing until fetching the RPC head failed [MAX_HEAD_ERRORS] times in a row.
+    async fn check_head_lag(&mut self) -> bool {
+        let rpc_head = match self.provider.get_head().await {
+            Ok(head) => {
+                self.head_errors = 0;
+                head
+            }
+            Err(err) => {
+                self.head_errors += 1;
+                warn!(
+                    err = ?err,
+                    errors = self.head_errors,
+                    "failed to fetch rpc head"
+                );
+                return self.head_errors < MAX_HEAD_ERRORS;
+            }
+        };
+
+        let storage = DatabaseStorage::new(self.db.clone());
+        let accepted_head = match storage.highest_accepted_block() {
+            Ok(head) => head.map(|h| h.number()).unwrap_or_default(),
+            Err(err) => {
+                warn!(err = ?err, "failed to read accepted head");
+                return false;
+            }
+        };
+
+        let lag = rpc_head.number().saturating_sub(accepted_head);
+        if lag > self.max_head_lag {
+            warn!(
+                lag = lag,
+                max_lag = self.max_head_lag,
+                "accepted head is lagging behind rpc head"
+            );
+            return false;
+        }
+        true
     }
 
-    async fn set_not_serving(&mut self) {
-        warn!("server is not serving");
-        /*
-        self.reporter
-            .set_not_serving::<pb::node_server::NodeServer<NodeServer<E>>>()
-            .await
-        */
+    async fn set_status(&mut self, service_name: &str, is_serving: bool) {
+        let status = if is_serving {
+            ServingStatus::Serving
+        } else {
+            ServingStatus::NotServing
+        };
+        self.reporter.set_service_status(service_name, status).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use apibara_node::db::{
+        libmdbx::{Environment, NoWriteMap},
+        MdbxEnvironmentExt,
+    };
+    use tempfile::TempDir;
+
+    use crate::{
+        db::{tables, DatabaseStorage, StorageWriter},
+        ingestion::{BlockIngestion, BlockIngestionConfig},
+        provider::MockChainProvider,
+    };
+
+    use super::{HealthReporter, MAX_HEAD_ERRORS};
+
+    struct TestReporter {
+        provider: Arc<MockChainProvider>,
+        storage: DatabaseStorage<NoWriteMap>,
+        reporter: HealthReporter<MockChainProvider, NoWriteMap>,
+        _datadir: TempDir,
+    }
+
+    impl TestReporter {
+        fn new(provider: MockChainProvider, max_head_lag: u64) -> TestReporter {
+            let datadir = tempfile::tempdir().unwrap();
+            let db = Environment::<NoWriteMap>::builder()
+                .with_size_gib(1, 10)
+                .open(datadir.path())
+                .unwrap();
+            let txn = db.begin_rw_txn().unwrap();
+            tables::ensure(&txn).unwrap();
+            txn.commit().unwrap();
+
+            let db = Arc::new(db);
+            let provider = Arc::new(provider);
+            let (ingestion, _) = BlockIngestion::new(
+                provider.clone(),
+                db.clone(),
+                BlockIngestionConfig::default(),
+            );
+            let (reporter, _) = HealthReporter::new(
+                db.clone(),
+                provider.clone(),
+                Arc::new(ingestion),
+                "test",
+                max_head_lag,
+            );
+            TestReporter {
+                provider,
+                storage: DatabaseStorage::new(db),
+                reporter,
+                _datadir: datadir,
+            }
+        }
+
+        /// Marks the provider blocks up to `number` (inclusive) as ingested.
+        fn ingest(&self, number: u64) {
+            let mut txn = self.storage.begin_txn().unwrap();
+            for n in 0..=number {
+                txn.extend_canonical_chain(&self.provider.block_id(n).unwrap())
+                    .unwrap();
+            }
+            txn.commit().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_head_lag() {
+        let provider = MockChainProvider::new();
+        provider.produce_blocks(5);
+        let mut test = TestReporter::new(provider, 2);
+
+        test.ingest(2);
+        assert!(!test.reporter.check_head_lag().await);
+
+        test.ingest(3);
+        assert!(test.reporter.check_head_lag().await);
+    }
+
+    #[tokio::test]
+    async fn test_head_lag_fails_on_consecutive_rpc_errors() {
+        let provider = MockChainProvider::new();
+        provider.produce_blocks(5);
+        let mut test = TestReporter::new(provider, 2);
+        test.ingest(5);
+
+        // a few failed requests don't make the node unhealthy.
+        test.provider.fail_next_requests(MAX_HEAD_ERRORS - 1);
+        for _ in 1..MAX_HEAD_ERRORS {
+            assert!(test.reporter.check_head_lag().await);
+        }
+        assert!(test.reporter.check_head_lag().await);
+
+        test.provider.fail_next_requests(MAX_HEAD_ERRORS + 1);
+        for _ in 1..MAX_HEAD_ERRORS {
+            assert!(test.reporter.check_head_lag().await);
+        }
+        assert!(!test.reporter.check_head_lag().await);
+        assert!(!test.reporter.check_head_lag().await);
+
+        // healthy again once the rpc head is available.
+        assert!(test.reporter.check_head_lag().await);
     }
 }
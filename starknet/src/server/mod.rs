@@ -1,32 +1,43 @@
 mod health;
 mod metadata;
 mod stream;
+#[cfg(test)]
+mod tests;
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use apibara_core::node as node_pb;
 use apibara_node::db::libmdbx::{Environment, EnvironmentKind};
 use tokio::task::JoinError;
 use tokio_util::sync::CancellationToken;
-use tonic::transport::Server as TonicServer;
+use tonic::{
+    server::NamedService,
+    transport::{Server as TonicServer, ServerTlsConfig},
+};
 use tracing::{error, info, info_span};
 
 use crate::{
     db::DatabaseStorage, healer::HealerClient, ingestion::IngestionStreamClient,
-    server::stream::StreamService,
+    provider::Provider, server::stream::StreamService, stream::DEFAULT_PROGRESS_INTERVAL,
 };
 
 use self::health::HealthReporter;
 
+pub use self::health::DEFAULT_MAX_HEAD_LAG;
+
 pub use self::metadata::{
     MetadataKeyRequestObserver, RequestMeter, RequestObserver, SimpleRequestObserver,
 };
 
-pub struct Server<E: EnvironmentKind, O: RequestObserver> {
+pub struct Server<G: Provider, E: EnvironmentKind, O: RequestObserver> {
     db: Arc<Environment<E>>,
+    provider: Arc<G>,
     ingestion: Arc<IngestionStreamClient>,
     healer: Arc<HealerClient>,
     request_observer: O,
+    tls_config: Option<ServerTlsConfig>,
+    progress_interval: Duration,
+    max_head_lag: u64,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -39,57 +50,102 @@ pub enum ServerError {
     ReflectionServer(#[from] tonic_reflection::server::Error),
 }
 
-impl<E, O> Server<E, O>
+impl<G, E, O> Server<G, E, O>
 where
+    G: Provider + Send + Sync + 'static,
     E: EnvironmentKind,
     O: RequestObserver,
 {
     pub fn new(
         db: Arc<Environment<E>>,
+        provider: Arc<G>,
         ingestion: IngestionStreamClient,
         healer: HealerClient,
-    ) -> Server<E, SimpleRequestObserver> {
+    ) -> Server<G, E, SimpleRequestObserver> {
         let ingestion = Arc::new(ingestion);
         let healer = Arc::new(healer);
         let request_observer = SimpleRequestObserver::default();
         Server {
             db,
+            provider,
             ingestion,
             healer,
             request_observer,
+            tls_config: None,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+            max_head_lag: DEFAULT_MAX_HEAD_LAG,
         }
     }
 
     /// Creates a new Server with the given request observer.
-    pub fn with_request_observer<S: RequestObserver>(self, request_observer: S) -> Server<E, S> {
+    pub fn with_request_observer<S: RequestObserver>(self, request_observer: S) -> Server<G, E, S> {
         Server {
             db: self.db,
+            provider: self.provider,
             ingestion: self.ingestion,
             healer: self.healer,
             request_observer,
+            tls_config: self.tls_config,
+            progress_interval: self.progress_interval,
+            max_head_lag: self.max_head_lag,
         }
     }
 
+    /// Serve requests over TLS, using the given configuration.
+    pub fn with_tls_config(mut self, tls_config: ServerTlsConfig) -> Self {
+        self.tls_config = Some(tls_config);
+        self
+    }
+
+    /// Change how often streams send finalized batches without any data.
+    pub fn with_progress_interval(mut self, progress_interval: Duration) -> Self {
+        self.progress_interval = progress_interval;
+        self
+    }
+
+    /// Change how many blocks the node can lag behind the RPC head before
+    /// the stream service is reported as not serving.
+    pub fn with_max_head_lag(mut self, max_head_lag: u64) -> Self {
+        self.max_head_lag = max_head_lag;
+        self
+    }
+
     pub async fn start(self, addr: SocketAddr, ct: CancellationToken) -> Result<(), ServerError> {
-        let (mut health_reporter, health_service) = HealthReporter::new(self.db.clone());
+        let reflection_service = tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(node_pb::v1alpha2::node_file_descriptor_set())
+            .build()?;
+
+        let storage = DatabaseStorage::new(self.db.clone());
+        let stream_service = StreamService::new(
+            self.ingestion.clone(),
+            self.healer,
+            storage,
+            self.request_observer,
+            self.progress_interval,
+        )
+        .into_service();
+
+        let (mut health_reporter, health_service) = HealthReporter::new(
+            self.db,
+            self.provider,
+            self.ingestion,
+            service_name(&stream_service),
+            self.max_head_lag,
+        );
 
         let reporter_handle = tokio::spawn({
             let ct = ct.clone();
             async move { health_reporter.start(ct).await }
         });
 
-        let reflection_service = tonic_reflection::server::Builder::configure()
-            .register_encoded_file_descriptor_set(node_pb::v1alpha2::node_file_descriptor_set())
-            .build()?;
+        info!(addr = %addr, tls = self.tls_config.is_some(), "starting server");
 
-        let storage = DatabaseStorage::new(self.db);
-        let stream_service =
-            StreamService::new(self.ingestion, self.healer, storage, self.request_observer)
-                .into_service();
-
-        info!(addr = %addr, "starting server");
+        let mut server = TonicServer::builder();
+        if let Some(tls_config) = self.tls_config {
+            server = server.tls_config(tls_config)?;
+        }
 
-        TonicServer::builder()
+        server
             .trace_fn(|_| info_span!("node_server"))
             .add_service(health_service)
             .add_service(stream_service)
@@ -107,3 +163,8 @@ where
         Ok(())
     }
 }
+
+/// Returns the name of the given grpc service.
+fn service_name<S: NamedService>(_service: &S) -> &'static str {
+    S::NAME
+}
@@ -7,10 +7,18 @@ use std::{
     time::Duration,
 };
 
-use apibara_core::node::v1alpha2::{stream_server, StreamDataRequest, StreamDataResponse};
+use apibara_core::{
+    node::v1alpha2::{
+        stream_server, DataFinality, GetBlocksRequest, GetBlocksResponse,
+        GetReorganizationsRequest, GetReorganizationsResponse, StatusRequest, StatusResponse,
+        StreamDataRequest, StreamDataResponse,
+    },
+    starknet::v1alpha2::Filter,
+};
 use apibara_node::heartbeat::Heartbeat;
 use futures::Stream;
 use pin_project::pin_project;
+use prost::Message;
 use tonic::{Request, Response, Streaming};
 use tracing::warn;
 use tracing_futures::Instrument;
@@ -21,16 +29,20 @@ use crate::{
     healer::HealerClient,
     // stream::{BatchDataStream, BatchMessage, StreamError},
     ingestion::IngestionStreamClient,
-    stream::{DataStream, StreamConfigurationStream, StreamError},
+    stream::{data_in_range, DataStream, StreamConfigurationStream, StreamError},
 };
 
 use super::metadata::RequestObserver;
 
+/// Number of reorganizations returned if the request doesn't specify a limit.
+const DEFAULT_REORGANIZATIONS_LIMIT: u64 = 100;
+
 pub struct StreamService<R: StorageReader, O: RequestObserver> {
     ingestion: Arc<IngestionStreamClient>,
     healer: Arc<HealerClient>,
     storage: Arc<R>,
     request_observer: O,
+    progress_interval: Duration,
 }
 
 impl<R, O> StreamService<R, O>
@@ -43,6 +55,7 @@ where
         healer: Arc<HealerClient>,
         storage: R,
         request_observer: O,
+        progress_interval: Duration,
     ) -> Self {
         let storage = Arc::new(storage);
         StreamService {
@@ -50,12 +63,27 @@ where
             healer,
             storage,
             request_observer,
+            progress_interval,
         }
     }
 
     pub fn into_service(self) -> stream_server::StreamServer<Self> {
         stream_server::StreamServer::new(self)
     }
+
+    /// Reads from storage on the blocking thread pool, so that scanning
+    /// many blocks doesn't block the executor.
+    async fn with_storage<T, F>(&self, f: F) -> Result<T, tonic::Status>
+    where
+        T: Send + 'static,
+        F: FnOnce(Arc<R>) -> Result<T, StreamError> + Send + 'static,
+    {
+        let storage = self.storage.clone();
+        tokio::task::spawn_blocking(move || f(storage))
+            .await
+            .map_err(|err| status_from_error(StreamError::internal(err)))?
+            .map_err(status_from_error)
+    }
 }
 
 #[tonic::async_trait]
@@ -85,11 +113,103 @@ where
             self.storage.clone(),
             self.healer.clone(),
             Arc::new(stream_meter),
+            self.progress_interval,
         );
 
         let response = ResponseStream::new(data_stream).instrument(stream_span);
         Ok(Response::new(Box::pin(response)))
     }
+
+    async fn status(
+        &self,
+        _request: Request<StatusRequest>,
+    ) -> Result<Response<StatusResponse>, tonic::Status> {
+        let response = self
+            .with_storage(|storage| {
+                let current_head = storage
+                    .highest_accepted_block()
+                    .map_err(StreamError::internal)?;
+                let last_finalized = storage
+                    .highest_finalized_block()
+                    .map_err(StreamError::internal)?;
+                let oldest_block = storage
+                    .lowest_canonical_block()
+                    .map_err(StreamError::internal)?;
+
+                Ok(StatusResponse {
+                    current_head: current_head.map(|c| c.to_cursor()),
+                    last_finalized: last_finalized.map(|c| c.to_cursor()),
+                    oldest_block: oldest_block.map(|c| c.to_cursor()),
+                })
+            })
+            .await?;
+        Ok(Response::new(response))
+    }
+
+    async fn get_blocks(
+        &self,
+        request: Request<GetBlocksRequest>,
+    ) -> Result<Response<GetBlocksResponse>, tonic::Status> {
+        let meter = Arc::new(self.request_observer.stream_data_meter(request.metadata()));
+        let request = request.into_inner();
+
+        let finality = request
+            .finality
+            .and_then(DataFinality::from_i32)
+            .unwrap_or(DataFinality::DataStatusAccepted);
+
+        let filter = Filter::decode(request.filter.as_ref())
+            .map_err(|_| status_from_error(StreamError::client("invalid filter")))?;
+
+        let range = self
+            .with_storage(move |storage| {
+                data_in_range(
+                    storage,
+                    filter,
+                    request.from_block,
+                    request.to_block,
+                    finality,
+                    &meter,
+                )
+            })
+            .await?;
+
+        let response = GetBlocksResponse {
+            end_cursor: range.end_cursor.map(|c| c.to_cursor()),
+            finality: range.finality as i32,
+            data: range.data,
+        };
+        Ok(Response::new(response))
+    }
+
+    async fn get_reorganizations(
+        &self,
+        request: Request<GetReorganizationsRequest>,
+    ) -> Result<Response<GetReorganizationsResponse>, tonic::Status> {
+        let limit = request
+            .into_inner()
+            .limit
+            .unwrap_or(DEFAULT_REORGANIZATIONS_LIMIT);
+
+        let reorganizations = self
+            .storage
+            .read_reorganizations(limit as usize)
+            .map_err(|err| status_from_error(StreamError::internal(err)))?;
+
+        let response = GetReorganizationsResponse { reorganizations };
+        Ok(Response::new(response))
+    }
+}
+
+/// Converts a stream error into a status sent to the client.
+fn status_from_error(err: StreamError) -> tonic::Status {
+    match err {
+        StreamError::Client { message } => tonic::Status::invalid_argument(message),
+        StreamError::Internal(err) => {
+            warn!(err = ?err, "stream service error");
+            tonic::Status::internal("internal server error")
+        }
+    }
 }
 
 /// A simple adapter from a generic ingestion stream to the one used by the server/stream module.
@@ -182,18 +302,7 @@ where
                         };
                         Ok(response)
                     }
-                    Ok(Err(err)) => {
-                        let status = match err {
-                            StreamError::Client { message } => {
-                                tonic::Status::invalid_argument(message)
-                            }
-                            StreamError::Internal(err) => {
-                                warn!(err = ?err, "stream service error");
-                                tonic::Status::internal("internal server error")
-                            }
-                        };
-                        Err(status)
-                    }
+                    Ok(Err(err)) => Err(status_from_error(err)),
                     Ok(Ok(response)) => Ok(response),
                 };
                 Poll::Ready(Some(response))
@@ -0,0 +1,1092 @@
+//! Run block ingestion and the stream service against a simulated chain.
+//!
+//! Each test scripts the chain with a [MockChainProvider] and checks the exact
+//! sequence of messages received by a client connected to the stream service.
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use apibara_core::{
+    node::v1alpha2::{
+        stream_client::StreamClient, stream_data_response::Message as ResponseMessage,
+        ChainReorganization, DataFinality, GetBlocksRequest, GetBlocksResponse,
+        GetReorganizationsRequest, StreamDataRequest, StreamDataResponse,
+    },
+    starknet::v1alpha2::{Block, BlockStatus, FieldElement, Filter, HeaderFilter},
+};
+use apibara_node::db::{
+    libmdbx::{Environment, NoWriteMap},
+    MdbxEnvironmentExt,
+};
+use prost::Message;
+use tempfile::TempDir;
+use tokio::{net::TcpListener, sync::mpsc, task::JoinHandle};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tokio_util::sync::CancellationToken;
+use tonic::{transport::Server as TonicServer, Streaming};
+
+use crate::{
+    core::{BlockHash, GlobalBlockId},
+    db::{tables, DatabaseStorage, StorageReader, StorageWriter},
+    healer::{Healer, HealerConfig},
+    ingestion::{BlockIngestion, BlockIngestionConfig, BlockIngestionError},
+    provider::MockChainProvider,
+    stream::{DEFAULT_PROGRESS_INTERVAL, MAX_RANGE_SIZE, MAX_STREAMS_PER_CONNECTION},
+    supervisor::{Supervisor, SupervisorConfig},
+};
+
+use super::{stream::StreamService, SimpleRequestObserver};
+
+/// How long to wait for the node before failing the test.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Block ingestion and stream service running in the background.
+struct TestNode {
+    provider: Arc<MockChainProvider>,
+    storage: DatabaseStorage<NoWriteMap>,
+    ingestion: JoinHandle<Result<(), BlockIngestionError>>,
+    addr: SocketAddr,
+    ct: CancellationToken,
+    _datadir: TempDir,
+}
+
+/// A client connected to the stream service.
+struct TestClient {
+    configuration: mpsc::Sender<StreamDataRequest>,
+    stream: Streaming<StreamDataResponse>,
+}
+
+/// A simplified stream response, to compare with the expected one.
+#[derive(Debug, PartialEq)]
+enum Received {
+    Data {
+        cursor: Option<GlobalBlockId>,
+        end_cursor: GlobalBlockId,
+        finality: DataFinality,
+        blocks: Vec<GlobalBlockId>,
+    },
+    Invalidate(GlobalBlockId),
+    InvalidateWithRejected {
+        cursor: GlobalBlockId,
+        rejected: Vec<(GlobalBlockId, BlockStatus)>,
+    },
+    Error(String),
+}
+
+impl TestNode {
+    async fn start(provider: MockChainProvider) -> TestNode {
+        let config = BlockIngestionConfig {
+            head_refresh_interval: Duration::from_millis(10),
+            ..BlockIngestionConfig::default()
+        };
+        TestNode::start_with_config(provider, config, DEFAULT_PROGRESS_INTERVAL).await
+    }
+
+    async fn start_with_config(
+        provider: MockChainProvider,
+        config: BlockIngestionConfig,
+        progress_interval: Duration,
+    ) -> TestNode {
+        let datadir = tempfile::tempdir().unwrap();
+        let db = Environment::<NoWriteMap>::builder()
+            .with_size_gib(1, 10)
+            .open(datadir.path())
+            .unwrap();
+        let txn = db.begin_rw_txn().unwrap();
+        tables::ensure(&txn).unwrap();
+        txn.commit().unwrap();
+
+        let db = Arc::new(db);
+        let provider = Arc::new(provider);
+        let ct = CancellationToken::new();
+
+        let (ingestion_client, mut ingestion) =
+            BlockIngestion::new(provider.clone(), db.clone(), config);
+        let (healer_client, mut healer) =
+            Healer::new(provider.clone(), db.clone(), HealerConfig::default());
+
+        let ingestion = tokio::spawn({
+            let ct = ct.clone();
+            async move {
+                let supervisor = Supervisor::new("ingestion", supervisor_config());
+                supervisor.run(&mut ingestion, ct).await
+            }
+        });
+
+        tokio::spawn({
+            let ct = ct.clone();
+            async move {
+                let supervisor = Supervisor::new("healer", supervisor_config());
+                supervisor.run(&mut healer, ct).await
+            }
+        });
+
+        let stream_service = StreamService::new(
+            Arc::new(ingestion_client),
+            Arc::new(healer_client),
+            DatabaseStorage::new(db.clone()),
+            SimpleRequestObserver::default(),
+            progress_interval,
+        )
+        .into_service();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn({
+            let ct = ct.clone();
+            async move {
+                TonicServer::builder()
+                    .add_service(stream_service)
+                    .serve_with_incoming_shutdown(TcpListenerStream::new(listener), ct.cancelled())
+                    .await
+            }
+        });
+
+        TestNode {
+            provider,
+            storage: DatabaseStorage::new(db),
+            ingestion,
+            addr,
+            ct,
+            _datadir: datadir,
+        }
+    }
+
+    /// Waits until the node ingested the current head of the simulated chain.
+    async fn wait_for_head(&self) {
+        let head = self.provider.head();
+        tokio::time::timeout(TIMEOUT, async {
+            while self.storage.highest_accepted_block().unwrap() != Some(head) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("timeout waiting for ingestion");
+    }
+
+    /// Waits until block ingestion stops, returning its error.
+    async fn wait_for_ingestion_error(&mut self) -> BlockIngestionError {
+        tokio::time::timeout(TIMEOUT, &mut self.ingestion)
+            .await
+            .expect("timeout waiting for ingestion to stop")
+            .unwrap()
+            .expect_err("ingestion stopped without error")
+    }
+
+    /// Returns the ids of the canonical blocks up to `number` (inclusive).
+    fn blocks(&self, number: u64) -> Vec<GlobalBlockId> {
+        (0..=number)
+            .map(|n| self.provider.block_id(n).unwrap())
+            .collect()
+    }
+
+    /// Returns the chain reorganizations reported by the stream service.
+    async fn reorganizations(&self) -> Vec<ChainReorganization> {
+        let mut client = StreamClient::connect(format!("http://{}", self.addr))
+            .await
+            .unwrap();
+        client
+            .get_reorganizations(GetReorganizationsRequest { limit: None })
+            .await
+            .unwrap()
+            .into_inner()
+            .reorganizations
+    }
+
+    /// Returns the data in a range of blocks.
+    async fn get_blocks(&self, request: GetBlocksRequest) -> GetBlocksResponse {
+        let mut client = StreamClient::connect(format!("http://{}", self.addr))
+            .await
+            .unwrap();
+        client.get_blocks(request).await.unwrap().into_inner()
+    }
+
+    async fn connect(
+        &self,
+        finality: DataFinality,
+        starting_cursor: Option<GlobalBlockId>,
+    ) -> TestClient {
+        let filter = Filter::default().with_header(HeaderFilter::new());
+        let request = StreamDataRequest {
+            stream_id: None,
+            batch_size: Some(10),
+            starting_cursor: starting_cursor.map(|c| c.to_cursor()),
+            finality: Some(finality as i32),
+            filter: filter.encode_to_vec(),
+            include_rejected: None,
+            close: None,
+            initial_credits: None,
+            credits: None,
+        };
+        self.connect_with_request(request).await
+    }
+
+    async fn connect_with_request(&self, request: StreamDataRequest) -> TestClient {
+        let mut client = StreamClient::connect(format!("http://{}", self.addr))
+            .await
+            .unwrap();
+        let (tx, rx) = mpsc::channel(128);
+        tx.send(request).await.unwrap();
+        let stream = client
+            .stream_data(ReceiverStream::new(rx))
+            .await
+            .unwrap()
+            .into_inner();
+
+        TestClient {
+            configuration: tx,
+            stream,
+        }
+    }
+}
+
+impl Drop for TestNode {
+    fn drop(&mut self) {
+        self.ct.cancel();
+    }
+}
+
+impl TestClient {
+    /// Sends a new request on the same connection.
+    async fn send(&self, request: StreamDataRequest) {
+        self.configuration.send(request).await.unwrap();
+    }
+
+    async fn next(&mut self) -> Received {
+        self.next_with_stream_id().await.1
+    }
+
+    /// Returns the next message, together with the id of the stream that sent it.
+    async fn next_with_stream_id(&mut self) -> (u64, Received) {
+        let response = tokio::time::timeout(TIMEOUT, self.stream.message())
+            .await
+            .expect("timeout waiting for stream message")
+            .unwrap()
+            .expect("stream closed");
+
+        let received = match response.message.expect("missing message") {
+            ResponseMessage::Data(data) => Received::Data {
+                cursor: data.cursor.map(|c| GlobalBlockId::from_cursor(&c).unwrap()),
+                end_cursor: GlobalBlockId::from_cursor(&data.end_cursor.unwrap()).unwrap(),
+                finality: DataFinality::from_i32(data.finality).unwrap(),
+                blocks: block_ids(&data.data),
+            },
+            ResponseMessage::Invalidate(invalidate) => {
+                let cursor = GlobalBlockId::from_cursor(&invalidate.cursor.unwrap()).unwrap();
+                if invalidate.rejected_data.is_empty() {
+                    Received::Invalidate(cursor)
+                } else {
+                    let rejected = invalidate
+                        .rejected_data
+                        .iter()
+                        .map(|bytes| {
+                            let block = Block::decode(bytes.as_slice()).unwrap();
+                            let status = block.status();
+                            let id =
+                                GlobalBlockId::from_block_header(&block.header.unwrap()).unwrap();
+                            (id, status)
+                        })
+                        .collect();
+                    Received::InvalidateWithRejected { cursor, rejected }
+                }
+            }
+            ResponseMessage::Error(error) => Received::Error(error.message),
+            message => panic!("unexpected message: {:?}", message),
+        };
+        (response.stream_id, received)
+    }
+}
+
+/// Returns the ids of the encoded blocks.
+fn block_ids(data: &[Vec<u8>]) -> Vec<GlobalBlockId> {
+    data.iter()
+        .map(|bytes| {
+            let block = Block::decode(bytes.as_slice()).unwrap();
+            GlobalBlockId::from_block_header(&block.header.unwrap()).unwrap()
+        })
+        .collect()
+}
+
+/// Returns a request for the block headers in `from..to`.
+fn get_blocks_request(from: u64, to: Option<u64>, finality: DataFinality) -> GetBlocksRequest {
+    GetBlocksRequest {
+        from_block: from,
+        to_block: to,
+        finality: Some(finality as i32),
+        filter: Filter::default()
+            .with_header(HeaderFilter::new())
+            .encode_to_vec(),
+    }
+}
+
+/// Returns a request for accepted block headers on the given stream.
+fn stream_request(stream_id: u64, starting_cursor: Option<GlobalBlockId>) -> StreamDataRequest {
+    StreamDataRequest {
+        stream_id: Some(stream_id),
+        batch_size: Some(10),
+        starting_cursor: starting_cursor.map(|c| c.to_cursor()),
+        finality: Some(DataFinality::DataStatusAccepted as i32),
+        filter: Filter::default()
+            .with_header(HeaderFilter::new())
+            .encode_to_vec(),
+        include_rejected: None,
+        close: None,
+        initial_credits: None,
+        credits: None,
+    }
+}
+
+/// Returns a request for finalized blocks with events with the given first keys.
+///
+/// Every block of the simulated chain has one event, keyed by the block number.
+fn finalized_events_request(keys: &[u64]) -> StreamDataRequest {
+    let filter = keys.iter().fold(
+        Filter::default().with_header(HeaderFilter::weak()),
+        |filter, key| filter.add_event(|ev| ev.with_keys(vec![FieldElement::from_u64(*key)])),
+    );
+    StreamDataRequest {
+        stream_id: None,
+        batch_size: Some(1),
+        starting_cursor: None,
+        finality: Some(DataFinality::DataStatusFinalized as i32),
+        filter: filter.encode_to_vec(),
+        include_rejected: None,
+        close: None,
+        initial_credits: None,
+        credits: None,
+    }
+}
+
+fn supervisor_config() -> SupervisorConfig {
+    SupervisorConfig {
+        initial_interval: Duration::from_millis(1),
+        max_interval: Duration::from_millis(10),
+        max_elapsed_time: TIMEOUT,
+        reset_after: Duration::from_secs(60),
+    }
+}
+
+fn accepted(cursor: Option<GlobalBlockId>, block: GlobalBlockId) -> Received {
+    Received::Data {
+        cursor,
+        end_cursor: block,
+        finality: DataFinality::DataStatusAccepted,
+        blocks: vec![block],
+    }
+}
+
+fn finalized(cursor: Option<GlobalBlockId>, blocks: &[GlobalBlockId]) -> Received {
+    Received::Data {
+        cursor,
+        end_cursor: *blocks.last().unwrap(),
+        finality: DataFinality::DataStatusFinalized,
+        blocks: blocks.to_vec(),
+    }
+}
+
+#[tokio::test]
+async fn test_stream_accepted_blocks() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(3);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(3);
+    let mut client = node.connect(DataFinality::DataStatusAccepted, None).await;
+    assert_eq!(client.next().await, accepted(None, blocks[0]));
+    for pair in blocks.windows(2) {
+        assert_eq!(client.next().await, accepted(Some(pair[0]), pair[1]));
+    }
+
+    let new_block = node.provider.produce_block();
+    assert_eq!(client.next().await, accepted(Some(blocks[3]), new_block));
+}
+
+#[tokio::test]
+async fn test_stream_finalized_blocks() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(5);
+    provider.finalize(3);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(5);
+    let mut accepted_client = node.connect(DataFinality::DataStatusAccepted, None).await;
+    assert_eq!(accepted_client.next().await, finalized(None, &blocks[0..4]));
+    assert_eq!(
+        accepted_client.next().await,
+        accepted(Some(blocks[3]), blocks[4])
+    );
+    assert_eq!(
+        accepted_client.next().await,
+        accepted(Some(blocks[4]), blocks[5])
+    );
+
+    let mut finalized_client = node.connect(DataFinality::DataStatusFinalized, None).await;
+    assert_eq!(
+        finalized_client.next().await,
+        finalized(None, &blocks[0..4])
+    );
+
+    // finality only advances when the head changes.
+    node.provider.finalize(5);
+    let new_block = node.provider.produce_block();
+    assert_eq!(
+        finalized_client.next().await,
+        finalized(Some(blocks[3]), &blocks[4..6])
+    );
+    assert_eq!(
+        accepted_client.next().await,
+        accepted(Some(blocks[5]), new_block)
+    );
+}
+
+#[tokio::test]
+async fn test_finalize_all_ingested_blocks() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(3);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    // finality reaches the last ingested block before the new head is ingested.
+    let blocks = node.blocks(3);
+    node.provider.finalize(3);
+    node.provider.produce_block();
+    node.wait_for_head().await;
+
+    let mut client = node.connect(DataFinality::DataStatusFinalized, None).await;
+    assert_eq!(client.next().await, finalized(None, &blocks[0..4]));
+}
+
+#[tokio::test]
+async fn test_heal_finalized_block_status() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(3);
+    provider.finalize(3);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    // the node lost the finalized status of one block.
+    let blocks = node.blocks(3);
+    let mut txn = node.storage.begin_txn().unwrap();
+    txn.write_status(&blocks[1], BlockStatus::AcceptedOnL2)
+        .unwrap();
+    txn.commit().unwrap();
+
+    // the stream stops before the block and asks the healer to fix it.
+    let mut client = node.connect(DataFinality::DataStatusFinalized, None).await;
+    assert_eq!(client.next().await, finalized(None, &blocks[0..1]));
+    tokio::time::timeout(TIMEOUT, async {
+        while !node
+            .storage
+            .read_status(&blocks[1])
+            .unwrap()
+            .unwrap()
+            .is_finalized()
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("timeout waiting for the healer");
+
+    // the stream resumes on the next block.
+    node.provider.produce_block();
+    assert_eq!(
+        client.next().await,
+        finalized(Some(blocks[0]), &blocks[1..4])
+    );
+}
+
+#[tokio::test]
+async fn test_stream_finalized_blocks_skips_empty_batches() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(12);
+    provider.finalize(12);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(12);
+    let mut client = node
+        .connect_with_request(finalized_events_request(&[5, 14]))
+        .await;
+    assert_eq!(client.next().await, finalized(None, &blocks[5..6]));
+
+    // blocks 6 to 13 don't have any data, so the next batch starts where the
+    // previous one ended.
+    let new_blocks = node.provider.produce_blocks(2);
+    node.provider.finalize(14);
+    node.provider.produce_block();
+    assert_eq!(
+        client.next().await,
+        finalized(Some(blocks[5]), &new_blocks[1..2])
+    );
+}
+
+#[tokio::test]
+async fn test_stream_finalized_blocks_progress_interval() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(12);
+    provider.finalize(12);
+    let config = BlockIngestionConfig {
+        head_refresh_interval: Duration::from_millis(10),
+        ..BlockIngestionConfig::default()
+    };
+    let node = TestNode::start_with_config(provider, config, Duration::from_millis(50)).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(12);
+    let mut client = node
+        .connect_with_request(finalized_events_request(&[5]))
+        .await;
+    assert_eq!(client.next().await, finalized(None, &blocks[5..6]));
+
+    // empty batches are sent once the client hasn't received data in a while.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    node.provider.produce_blocks(2);
+    node.provider.finalize(14);
+    node.provider.produce_block();
+
+    let mut previous_end_cursor = blocks[5];
+    while previous_end_cursor.number() < 14 {
+        match client.next().await {
+            Received::Data {
+                cursor,
+                end_cursor,
+                finality,
+                blocks,
+            } => {
+                assert_eq!(cursor, Some(previous_end_cursor));
+                assert_eq!(finality, DataFinality::DataStatusFinalized);
+                assert!(blocks.is_empty());
+                previous_end_cursor = end_cursor;
+            }
+            message => panic!("unexpected message: {:?}", message),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_get_blocks_in_range() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(5);
+    provider.finalize(3);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(5);
+    let range = node
+        .get_blocks(get_blocks_request(
+            1,
+            Some(4),
+            DataFinality::DataStatusAccepted,
+        ))
+        .await;
+    assert_eq!(block_ids(&range.data), blocks[1..4].to_vec());
+    assert_eq!(range.end_cursor, Some(blocks[3].to_cursor()));
+    assert_eq!(range.finality(), DataFinality::DataStatusFinalized);
+
+    // the range ends at the most recent block with the requested finality.
+    let range = node
+        .get_blocks(get_blocks_request(
+            2,
+            None,
+            DataFinality::DataStatusAccepted,
+        ))
+        .await;
+    assert_eq!(block_ids(&range.data), blocks[2..6].to_vec());
+    assert_eq!(range.end_cursor, Some(blocks[5].to_cursor()));
+    assert_eq!(range.finality(), DataFinality::DataStatusAccepted);
+
+    let range = node
+        .get_blocks(get_blocks_request(
+            2,
+            None,
+            DataFinality::DataStatusFinalized,
+        ))
+        .await;
+    assert_eq!(block_ids(&range.data), blocks[2..4].to_vec());
+    assert_eq!(range.end_cursor, Some(blocks[3].to_cursor()));
+
+    // empty ranges.
+    for (from, to) in [(3, Some(3)), (4, Some(2)), (6, None)] {
+        let range = node
+            .get_blocks(get_blocks_request(
+                from,
+                to,
+                DataFinality::DataStatusAccepted,
+            ))
+            .await;
+        assert!(range.data.is_empty());
+        assert_eq!(range.end_cursor, None);
+    }
+}
+
+#[tokio::test]
+async fn test_get_blocks_skips_blocks_without_data() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(5);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(5);
+    let request = GetBlocksRequest {
+        filter: Filter::default()
+            .with_header(HeaderFilter::weak())
+            .add_event(|ev| ev.with_keys(vec![FieldElement::from_u64(2)]))
+            .encode_to_vec(),
+        ..get_blocks_request(0, None, DataFinality::DataStatusAccepted)
+    };
+    let range = node.get_blocks(request).await;
+    assert_eq!(block_ids(&range.data), blocks[2..3].to_vec());
+    // blocks after the data are part of the range too.
+    assert_eq!(range.end_cursor, Some(blocks[5].to_cursor()));
+}
+
+#[tokio::test]
+async fn test_get_blocks_limits_range_size() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(MAX_RANGE_SIZE as usize + 4);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(MAX_RANGE_SIZE + 4);
+    let range = node
+        .get_blocks(get_blocks_request(
+            0,
+            None,
+            DataFinality::DataStatusAccepted,
+        ))
+        .await;
+    assert_eq!(range.data.len(), MAX_RANGE_SIZE as usize);
+    let end_cursor = blocks[MAX_RANGE_SIZE as usize - 1];
+    assert_eq!(range.end_cursor, Some(end_cursor.to_cursor()));
+
+    // clients continue from the end of the truncated range.
+    let range = node
+        .get_blocks(get_blocks_request(
+            end_cursor.number() + 1,
+            None,
+            DataFinality::DataStatusAccepted,
+        ))
+        .await;
+    assert_eq!(
+        block_ids(&range.data),
+        blocks[MAX_RANGE_SIZE as usize..].to_vec()
+    );
+}
+
+#[tokio::test]
+async fn test_stream_chain_reorganization() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(3);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(3);
+    let mut client = node.connect(DataFinality::DataStatusAccepted, None).await;
+    for _ in 0..4 {
+        client.next().await;
+    }
+
+    node.provider.reorg(2);
+    let new_blocks = node.provider.produce_blocks(3);
+
+    assert_eq!(client.next().await, Received::Invalidate(blocks[1]));
+    assert_eq!(
+        client.next().await,
+        accepted(Some(blocks[1]), new_blocks[0])
+    );
+    assert_eq!(
+        client.next().await,
+        accepted(Some(new_blocks[0]), new_blocks[1])
+    );
+    assert_eq!(
+        client.next().await,
+        accepted(Some(new_blocks[1]), new_blocks[2])
+    );
+}
+
+#[tokio::test]
+async fn test_stream_from_invalidated_cursor() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(3);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(3);
+    node.provider.reorg(2);
+    let new_blocks = node.provider.produce_blocks(3);
+    node.wait_for_head().await;
+
+    // client was disconnected while the reorg happened.
+    let mut client = node
+        .connect(DataFinality::DataStatusAccepted, Some(blocks[3]))
+        .await;
+    assert_eq!(client.next().await, Received::Invalidate(blocks[1]));
+    assert_eq!(
+        client.next().await,
+        accepted(Some(blocks[1]), new_blocks[0])
+    );
+    assert_eq!(
+        client.next().await,
+        accepted(Some(new_blocks[0]), new_blocks[1])
+    );
+}
+
+#[tokio::test]
+async fn test_stream_from_deeply_invalidated_cursor() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(5);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(5);
+    node.provider.reorg(4);
+    let new_blocks = node.provider.produce_blocks(5);
+    node.wait_for_head().await;
+
+    // the stream walks back through all rejected blocks to find the new root.
+    let mut client = node
+        .connect(DataFinality::DataStatusAccepted, Some(blocks[5]))
+        .await;
+    assert_eq!(client.next().await, Received::Invalidate(blocks[1]));
+    assert_eq!(
+        client.next().await,
+        accepted(Some(blocks[1]), new_blocks[0])
+    );
+}
+
+#[tokio::test]
+async fn test_stream_pending_block() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(3);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(3);
+    let mut client = node.connect(DataFinality::DataStatusPending, None).await;
+    for _ in 0..4 {
+        client.next().await;
+    }
+
+    node.provider.produce_pending();
+    let pending = GlobalBlockId::new(4, BlockHash::zero());
+    assert_eq!(
+        client.next().await,
+        Received::Data {
+            cursor: Some(blocks[3]),
+            end_cursor: pending,
+            finality: DataFinality::DataStatusPending,
+            blocks: vec![pending],
+        }
+    );
+
+    let new_block = node.provider.produce_block();
+    assert_eq!(client.next().await, accepted(Some(blocks[3]), new_block));
+}
+
+#[tokio::test]
+async fn test_recover_from_transient_errors() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(3);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(3);
+    let mut client = node.connect(DataFinality::DataStatusAccepted, None).await;
+    for _ in 0..4 {
+        client.next().await;
+    }
+
+    // ingestion fails and is restarted without affecting connected clients.
+    node.provider.fail_next_requests(5);
+    let new_blocks = node.provider.produce_blocks(2);
+    assert_eq!(
+        client.next().await,
+        accepted(Some(blocks[3]), new_blocks[0])
+    );
+    assert_eq!(
+        client.next().await,
+        accepted(Some(new_blocks[0]), new_blocks[1])
+    );
+}
+
+#[tokio::test]
+async fn test_stream_rejected_block_data() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(3);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(3);
+    let request = StreamDataRequest {
+        stream_id: None,
+        batch_size: Some(10),
+        starting_cursor: None,
+        finality: Some(DataFinality::DataStatusAccepted as i32),
+        filter: Filter::default()
+            .with_header(HeaderFilter::new())
+            .encode_to_vec(),
+        include_rejected: Some(true),
+        close: None,
+        initial_credits: None,
+        credits: None,
+    };
+    let mut client = node.connect_with_request(request.clone()).await;
+    for _ in 0..4 {
+        client.next().await;
+    }
+
+    node.provider.reorg(2);
+    let new_blocks = node.provider.produce_blocks(3);
+
+    let expected_invalidate = Received::InvalidateWithRejected {
+        cursor: blocks[1],
+        rejected: vec![
+            (blocks[2], BlockStatus::Rejected),
+            (blocks[3], BlockStatus::Rejected),
+        ],
+    };
+    assert_eq!(client.next().await, expected_invalidate);
+    assert_eq!(
+        client.next().await,
+        accepted(Some(blocks[1]), new_blocks[0])
+    );
+
+    // clients that reconnect with an invalidated cursor receive the same data.
+    node.wait_for_head().await;
+    let request = StreamDataRequest {
+        starting_cursor: Some(blocks[3].to_cursor()),
+        ..request
+    };
+    let mut client = node.connect_with_request(request).await;
+    assert_eq!(client.next().await, expected_invalidate);
+}
+
+#[tokio::test]
+async fn test_record_chain_reorganizations() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(3);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+    assert!(node.reorganizations().await.is_empty());
+
+    let blocks = node.blocks(3);
+    node.provider.reorg(2);
+    let new_blocks = node.provider.produce_blocks(3);
+    node.wait_for_head().await;
+
+    let reorganizations = node.reorganizations().await;
+    assert_eq!(reorganizations.len(), 1);
+    let reorg = &reorganizations[0];
+    assert_eq!(reorg.old_tip, Some(blocks[3].to_cursor()));
+    assert_eq!(reorg.common_ancestor, Some(blocks[1].to_cursor()));
+    assert_eq!(reorg.new_tip, Some(new_blocks[2].to_cursor()));
+    assert_eq!(reorg.depth, 2);
+    assert!(!reorg.rejected);
+    assert!(reorg.timestamp > 0);
+}
+
+#[tokio::test]
+async fn test_halt_on_deep_chain_reorganization() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(3);
+    let config = BlockIngestionConfig {
+        head_refresh_interval: Duration::from_millis(10),
+        max_reorg_depth: 1,
+    };
+    let mut node = TestNode::start_with_config(provider, config, DEFAULT_PROGRESS_INTERVAL).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(3);
+    node.provider.reorg(2);
+    node.provider.produce_blocks(3);
+
+    // ingestion is not restarted, since it would find the same reorg again.
+    let err = node.wait_for_ingestion_error().await;
+    assert!(matches!(
+        err,
+        BlockIngestionError::ReorgTooDeep { max_depth: 1 }
+    ));
+    assert_eq!(
+        node.storage.highest_accepted_block().unwrap(),
+        Some(blocks[3])
+    );
+
+    // the rejected reorganization is logged for operators.
+    let reorganizations = node.reorganizations().await;
+    assert_eq!(reorganizations.len(), 1);
+    let reorg = &reorganizations[0];
+    assert!(reorg.rejected);
+    assert_eq!(reorg.old_tip, Some(blocks[3].to_cursor()));
+    assert_eq!(reorg.common_ancestor, None);
+    assert_eq!(reorg.depth, 2);
+}
+
+#[tokio::test]
+async fn test_multiple_streams_per_connection() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(3);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(3);
+    let mut client = node.connect_with_request(stream_request(1, None)).await;
+    client.send(stream_request(2, Some(blocks[1]))).await;
+
+    // streams progress independently, each from its own cursor.
+    let mut received = (Vec::new(), Vec::new());
+    while received.0.len() < 4 || received.1.len() < 2 {
+        match client.next_with_stream_id().await {
+            (1, message) => received.0.push(message),
+            (2, message) => received.1.push(message),
+            (stream_id, _) => panic!("unexpected stream id {stream_id}"),
+        }
+    }
+    assert_eq!(received.0[0], accepted(None, blocks[0]));
+    assert_eq!(received.0[3], accepted(Some(blocks[2]), blocks[3]));
+    assert_eq!(
+        received.1,
+        vec![
+            accepted(Some(blocks[1]), blocks[2]),
+            accepted(Some(blocks[2]), blocks[3])
+        ]
+    );
+
+    // closed streams don't receive new data.
+    client
+        .send(StreamDataRequest {
+            stream_id: Some(1),
+            close: Some(true),
+            ..StreamDataRequest::default()
+        })
+        .await;
+    let new_block = node.provider.produce_block();
+    assert_eq!(
+        client.next_with_stream_id().await,
+        (2, accepted(Some(blocks[3]), new_block))
+    );
+}
+
+#[tokio::test]
+async fn test_stream_waits_for_credits() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(3);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(3);
+    let request = StreamDataRequest {
+        initial_credits: Some(1),
+        ..stream_request(1, None)
+    };
+    let mut client = node.connect_with_request(request).await;
+    client.send(stream_request(2, None)).await;
+
+    // the stream without credits doesn't pause the other stream.
+    let mut received = (Vec::new(), Vec::new());
+    while received.0.is_empty() || received.1.len() < 4 {
+        match client.next_with_stream_id().await {
+            (1, message) => received.0.push(message),
+            (2, message) => received.1.push(message),
+            (stream_id, _) => panic!("unexpected stream id {stream_id}"),
+        }
+    }
+    assert_eq!(received.0, vec![accepted(None, blocks[0])]);
+
+    let new_block = node.provider.produce_block();
+    assert_eq!(
+        client.next_with_stream_id().await,
+        (2, accepted(Some(blocks[3]), new_block))
+    );
+
+    // the stream resumes once the client grants more credits.
+    client
+        .send(StreamDataRequest {
+            stream_id: Some(1),
+            credits: Some(1),
+            ..StreamDataRequest::default()
+        })
+        .await;
+    assert_eq!(
+        client.next_with_stream_id().await,
+        (1, accepted(Some(blocks[0]), blocks[1]))
+    );
+}
+
+#[tokio::test]
+async fn test_limit_streams_per_connection() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(3);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(3);
+    let mut client = node.connect_with_request(stream_request(0, None)).await;
+    for stream_id in 1..=MAX_STREAMS_PER_CONNECTION as u64 {
+        client
+            .send(stream_request(stream_id, Some(blocks[3])))
+            .await;
+    }
+
+    // only the stream over the limit fails.
+    let last_stream_id = MAX_STREAMS_PER_CONNECTION as u64;
+    loop {
+        if let (stream_id, Received::Error(message)) = client.next_with_stream_id().await {
+            assert_eq!(stream_id, last_stream_id);
+            assert!(message.starts_with("too many streams"));
+            break;
+        }
+    }
+
+    let new_block = node.provider.produce_block();
+    let mut received = Vec::new();
+    while received.len() < MAX_STREAMS_PER_CONNECTION {
+        let (stream_id, message) = client.next_with_stream_id().await;
+        assert_ne!(stream_id, last_stream_id);
+        if message == accepted(Some(blocks[3]), new_block) {
+            received.push(stream_id);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_stream_error_only_closes_stream() {
+    let provider = MockChainProvider::new();
+    provider.produce_blocks(3);
+    let node = TestNode::start(provider).await;
+    node.wait_for_head().await;
+
+    let blocks = node.blocks(3);
+    let mut client = node
+        .connect_with_request(stream_request(0, Some(blocks[2])))
+        .await;
+    assert_eq!(client.next().await, accepted(Some(blocks[2]), blocks[3]));
+
+    // a cursor of a block the node doesn't know.
+    let unknown_cursor = GlobalBlockId::new(2, FieldElement::from_u64(u64::MAX).into());
+    client.send(stream_request(1, Some(unknown_cursor))).await;
+    assert_eq!(
+        client.next_with_stream_id().await,
+        (1, Received::Error("cursor not found".to_string()))
+    );
+
+    // invalid requests only fail the stream they configure.
+    client
+        .send(StreamDataRequest {
+            filter: vec![0xff],
+            ..stream_request(2, None)
+        })
+        .await;
+    assert_eq!(
+        client.next_with_stream_id().await,
+        (2, Received::Error("invalid filter".to_string()))
+    );
+
+    let new_block = node.provider.produce_block();
+    assert_eq!(
+        client.next_with_stream_id().await,
+        (0, accepted(Some(blocks[3]), new_block))
+    );
+}
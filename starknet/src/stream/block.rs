@@ -5,7 +5,11 @@ use std::sync::Arc;
 use apibara_core::starknet::v1alpha2;
 use tracing::trace;
 
-use crate::{core::GlobalBlockId, db::StorageReader, server::RequestMeter};
+use crate::{
+    core::GlobalBlockId,
+    db::{BlockBlooms, Bloom, StorageReader},
+    server::RequestMeter,
+};
 
 pub trait BlockDataFilter {
     type Error: std::error::Error + Send + Sync + 'static;
@@ -37,6 +41,25 @@ struct DataCounter {
     pub nonce_update: usize,
 }
 
+/// Data to send together with an item matched by a filter.
+///
+/// If more than one filter matches the same item, the data included is the
+/// union of what each filter requested.
+#[derive(Debug, Clone, Copy)]
+struct Inclusion {
+    pub transaction: bool,
+    pub receipt: bool,
+}
+
+impl Inclusion {
+    pub fn merge(self, other: Inclusion) -> Inclusion {
+        Inclusion {
+            transaction: self.transaction || other.transaction,
+            receipt: self.receipt || other.receipt,
+        }
+    }
+}
+
 impl DataCounter {
     pub fn update_meter<M: RequestMeter>(&self, meter: &Arc<M>) {
         meter.increment_counter("header", self.header as u64);
@@ -58,6 +81,104 @@ where
         DatabaseBlockDataFilter { storage, filter }
     }
 
+    /// Returns the first block in `from..=to` that may contain data for the filter.
+    ///
+    /// Blocks that are skipped are guaranteed not to produce any data. If the
+    /// filter requests data that is not indexed, this always returns `from`.
+    pub fn next_candidate_block(&self, from: u64, to: u64) -> Result<Option<u64>, R::Error> {
+        if from > to {
+            return Ok(None);
+        }
+
+        // only events are indexed.
+        if !self.has_weak_header()
+            || !self.filter.transactions.is_empty()
+            || !self.filter.messages.is_empty()
+            || self.filter.state_update.is_some()
+        {
+            return Ok(Some(from));
+        }
+
+        if self.filter.events.is_empty() {
+            return Ok(None);
+        }
+
+        // blocks ingested before the indices existed must be checked one by one.
+        match self.storage.event_index_start()? {
+            Some(start) if start <= from => {}
+            _ => return Ok(Some(from)),
+        }
+
+        let mut candidate = None;
+        for filter in &self.filter.events {
+            let next = self.next_block_with_event(filter, from, to)?;
+            candidate = match (candidate, next) {
+                (None, next) => next,
+                (Some(current), Some(next)) => Some(u64::min(current, next)),
+                (current, None) => current,
+            };
+            if candidate == Some(from) {
+                break;
+            }
+        }
+
+        Ok(candidate.filter(|block_number| *block_number <= to))
+    }
+
+    /// Returns the first block, starting at `from`, that contains events for both
+    /// the address and first key of the filter.
+    fn next_block_with_event(
+        &self,
+        filter: &v1alpha2::EventFilter,
+        from: u64,
+        to: u64,
+    ) -> Result<Option<u64>, R::Error> {
+        let address = filter.from_address.as_ref();
+        let keys = event_filter_first_keys(filter);
+        match (address, keys.is_empty()) {
+            (None, true) => Ok(Some(from)),
+            (Some(address), true) => self.storage.next_block_with_event_address(address, from),
+            (None, false) => self.next_block_with_any_event_key(&keys, from),
+            (Some(address), false) => {
+                let mut current = from;
+                while current <= to {
+                    let by_address = match self
+                        .storage
+                        .next_block_with_event_address(address, current)?
+                    {
+                        None => return Ok(None),
+                        Some(block_number) => block_number,
+                    };
+                    let by_key = match self.next_block_with_any_event_key(&keys, by_address)? {
+                        None => return Ok(None),
+                        Some(block_number) => block_number,
+                    };
+                    if by_address == by_key {
+                        return Ok(Some(by_key));
+                    }
+                    current = by_key;
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Returns the first block, starting at `from`, that contains an event with any
+    /// of the given first keys.
+    fn next_block_with_any_event_key(
+        &self,
+        keys: &[&v1alpha2::FieldElement],
+        from: u64,
+    ) -> Result<Option<u64>, R::Error> {
+        let mut next = None;
+        for key in keys {
+            if let Some(block_number) = self.storage.next_block_with_event_key(key, from)? {
+                next = Some(next.map_or(block_number, |n: u64| n.min(block_number)));
+            }
+        }
+        Ok(next)
+    }
+
     fn status(&self, block_id: &GlobalBlockId) -> Result<v1alpha2::BlockStatus, R::Error> {
         let status = self
             .storage
@@ -87,14 +208,30 @@ where
     fn transactions(
         &self,
         block_id: &GlobalBlockId,
+        blooms: &BlockBlooms,
         meter: &mut DataCounter,
     ) -> Result<Vec<v1alpha2::TransactionWithReceipt>, R::Error> {
         if self.filter.transactions.is_empty() {
             return Ok(Vec::default());
         }
 
+        // quickly check if any transaction would match using bloom filter
+        if let Some(bloom) = &blooms.transactions {
+            let has_match = self
+                .filter
+                .transactions
+                .iter()
+                .any(|f| bloom_contains_all(bloom, transaction_filter_values(f)));
+
+            // bail out early
+            if !has_match {
+                trace!("bloom did not match any transaction.");
+                return Ok(Vec::default());
+            }
+        }
+
         let transactions = self.storage.read_body(block_id)?;
-        let (mut receipts, _) = self.storage.read_receipts(block_id)?;
+        let mut receipts = self.storage.read_receipts(block_id)?;
 
         assert!(transactions.len() == receipts.len());
         receipts.sort_by(|a, b| a.transaction_index.cmp(&b.transaction_index));
@@ -103,14 +240,12 @@ where
             .into_iter()
             .zip(receipts.into_iter())
             .flat_map(|(tx, rx)| {
-                if self.filter_transaction(&tx) {
-                    Some(v1alpha2::TransactionWithReceipt {
+                self.filter_transaction(&tx, &rx).map(|inclusion| {
+                    v1alpha2::TransactionWithReceipt {
                         transaction: Some(tx),
-                        receipt: Some(rx),
-                    })
-                } else {
-                    None
-                }
+                        receipt: inclusion.receipt.then_some(rx),
+                    }
+                })
             })
             .collect();
 
@@ -122,39 +257,20 @@ where
     fn events(
         &self,
         block_id: &GlobalBlockId,
+        blooms: &BlockBlooms,
         meter: &mut DataCounter,
     ) -> Result<Vec<v1alpha2::EventWithTransaction>, R::Error> {
         if self.filter.events.is_empty() {
             return Ok(Vec::default());
         }
 
-        let transactions = self.storage.read_body(block_id)?;
-        let (mut receipts, bloom) = self.storage.read_receipts(block_id)?;
-
         // quickly check if any event would match using bloom filter
-        if let Some(bloom) = bloom {
-            let mut has_match = false;
-            for filter in &self.filter.events {
-                match &filter.from_address {
-                    None => {
-                        // an empty filter matches any address
-                        has_match = true;
-                        break;
-                    }
-                    Some(address) => {
-                        if bloom.check(address) {
-                            has_match = true;
-                            break;
-                        }
-                    }
-                }
-                for key in &filter.keys {
-                    if bloom.check(key) {
-                        has_match = true;
-                        break;
-                    }
-                }
-            }
+        if let Some(bloom) = &blooms.events {
+            let has_match = self
+                .filter
+                .events
+                .iter()
+                .any(|f| event_filter_may_match(bloom, f));
 
             // bail out early
             if !has_match {
@@ -163,6 +279,9 @@ where
             }
         }
 
+        let transactions = self.storage.read_body(block_id)?;
+        let mut receipts = self.storage.read_receipts(block_id)?;
+
         assert!(transactions.len() == receipts.len());
         receipts.sort_by(|a, b| a.transaction_index.cmp(&b.transaction_index));
 
@@ -170,14 +289,14 @@ where
         for receipt in &receipts {
             let transaction = &transactions[receipt.transaction_index as usize];
             for event in &receipt.events {
-                if self.filter_event(event) {
-                    let transaction = transaction.clone();
-                    let receipt = receipt.clone();
+                if let Some(inclusion) = self.filter_event(event, receipt) {
+                    let transaction = inclusion.transaction.then(|| transaction.clone());
+                    let receipt = inclusion.receipt.then(|| receipt.clone());
                     let event = event.clone();
 
                     events.push(v1alpha2::EventWithTransaction {
-                        transaction: Some(transaction),
-                        receipt: Some(receipt),
+                        transaction,
+                        receipt,
                         event: Some(event),
                     });
                 }
@@ -192,14 +311,30 @@ where
     fn l2_to_l1_messages(
         &self,
         block_id: &GlobalBlockId,
+        blooms: &BlockBlooms,
         meter: &mut DataCounter,
     ) -> Result<Vec<v1alpha2::L2ToL1MessageWithTransaction>, R::Error> {
         if self.filter.messages.is_empty() {
             return Ok(Vec::default());
         }
 
+        // quickly check if any message would match using bloom filter
+        if let Some(bloom) = &blooms.messages {
+            let has_match = self
+                .filter
+                .messages
+                .iter()
+                .any(|f| bloom_contains_all(bloom, f.to_address.as_ref()));
+
+            // bail out early
+            if !has_match {
+                trace!("bloom did not match any message.");
+                return Ok(Vec::default());
+            }
+        }
+
         let transactions = self.storage.read_body(block_id)?;
-        let (mut receipts, _) = self.storage.read_receipts(block_id)?;
+        let mut receipts = self.storage.read_receipts(block_id)?;
 
         assert!(transactions.len() == receipts.len());
         receipts.sort_by(|a, b| a.transaction_index.cmp(&b.transaction_index));
@@ -208,14 +343,14 @@ where
         for receipt in &receipts {
             let transaction = &transactions[receipt.transaction_index as usize];
             for message in &receipt.l2_to_l1_messages {
-                if self.filter_l2_to_l1_message(message) {
-                    let transaction = transaction.clone();
-                    let receipt = receipt.clone();
+                if let Some(inclusion) = self.filter_l2_to_l1_message(message) {
+                    let transaction = inclusion.transaction.then(|| transaction.clone());
+                    let receipt = inclusion.receipt.then(|| receipt.clone());
                     let message = message.clone();
 
                     messages.push(v1alpha2::L2ToL1MessageWithTransaction {
-                        transaction: Some(transaction),
-                        receipt: Some(receipt),
+                        transaction,
+                        receipt,
                         message: Some(message),
                     });
                 }
@@ -230,6 +365,7 @@ where
     fn state_update(
         &self,
         block_id: &GlobalBlockId,
+        blooms: &BlockBlooms,
         meter: &mut DataCounter,
     ) -> Result<Option<v1alpha2::StateUpdate>, R::Error> {
         let filter = if let Some(filter) = self.filter.state_update.as_ref() {
@@ -238,6 +374,15 @@ where
             return Ok(None);
         };
 
+        // quickly check if any state diff would match using bloom filter
+        if let Some(bloom) = &blooms.state_update {
+            // bail out early
+            if !state_update_filter_may_match(bloom, filter) {
+                trace!("bloom did not match any state diff.");
+                return Ok(None);
+            }
+        }
+
         let original_state_update =
             if let Some(update) = self.storage.read_state_update(block_id)? {
                 update
@@ -303,16 +448,51 @@ where
         }
     }
 
-    fn filter_transaction(&self, tx: &v1alpha2::Transaction) -> bool {
-        self.filter.transactions.iter().any(|f| f.matches(tx))
+    /// Returns what data to include with the transaction, or `None` if no filter matches.
+    fn filter_transaction(
+        &self,
+        tx: &v1alpha2::Transaction,
+        receipt: &v1alpha2::TransactionReceipt,
+    ) -> Option<Inclusion> {
+        self.filter
+            .transactions
+            .iter()
+            .filter(|f| f.matches_execution_status(receipt) && f.matches(tx))
+            .map(|f| Inclusion {
+                transaction: true,
+                receipt: f.should_include_receipt(),
+            })
+            .reduce(Inclusion::merge)
     }
 
-    fn filter_event(&self, event: &v1alpha2::Event) -> bool {
-        self.filter.events.iter().any(|f| f.matches(event))
+    /// Returns what data to include with the event, or `None` if no filter matches.
+    fn filter_event(
+        &self,
+        event: &v1alpha2::Event,
+        receipt: &v1alpha2::TransactionReceipt,
+    ) -> Option<Inclusion> {
+        self.filter
+            .events
+            .iter()
+            .filter(|f| f.matches_execution_status(receipt) && f.matches(event))
+            .map(|f| Inclusion {
+                transaction: f.should_include_transaction(),
+                receipt: f.should_include_receipt(),
+            })
+            .reduce(Inclusion::merge)
     }
 
-    fn filter_l2_to_l1_message(&self, message: &v1alpha2::L2ToL1Message) -> bool {
-        self.filter.messages.iter().any(|f| f.matches(message))
+    /// Returns what data to include with the message, or `None` if no filter matches.
+    fn filter_l2_to_l1_message(&self, message: &v1alpha2::L2ToL1Message) -> Option<Inclusion> {
+        self.filter
+            .messages
+            .iter()
+            .filter(|f| f.matches(message))
+            .map(|f| Inclusion {
+                transaction: f.should_include_transaction(),
+                receipt: f.should_include_receipt(),
+            })
+            .reduce(Inclusion::merge)
     }
 
     fn filter_storage_diff(
@@ -376,16 +556,18 @@ where
             has_data |= header.is_some();
         }
 
-        let transactions = self.transactions(block_id, &mut data_counter)?;
+        let blooms = self.storage.read_blooms(block_id)?;
+
+        let transactions = self.transactions(block_id, &blooms, &mut data_counter)?;
         has_data |= !transactions.is_empty();
 
-        let events = self.events(block_id, &mut data_counter)?;
+        let events = self.events(block_id, &blooms, &mut data_counter)?;
         has_data |= !events.is_empty();
 
-        let l2_to_l1_messages = self.l2_to_l1_messages(block_id, &mut data_counter)?;
+        let l2_to_l1_messages = self.l2_to_l1_messages(block_id, &blooms, &mut data_counter)?;
         has_data |= !l2_to_l1_messages.is_empty();
 
-        let state_update = self.state_update(block_id, &mut data_counter)?;
+        let state_update = self.state_update(block_id, &blooms, &mut data_counter)?;
         has_data |= state_update.is_some();
 
         let data = v1alpha2::Block {
@@ -407,3 +589,677 @@ where
         }
     }
 }
+
+/// Returns true if the bloom filter may contain all the given values.
+fn bloom_contains_all<'a>(
+    bloom: &Bloom,
+    values: impl IntoIterator<Item = &'a v1alpha2::FieldElement>,
+) -> bool {
+    values.into_iter().all(|value| bloom.check(value))
+}
+
+/// Returns true if the block may contain an event matching the filter.
+///
+/// An event matches only if it has both the filter address and keys, so all of
+/// them must be in the bloom filter. Key patterns need at least one of their
+/// values in the bloom filter.
+fn event_filter_may_match(bloom: &Bloom, filter: &v1alpha2::EventFilter) -> bool {
+    let values = filter.from_address.iter().chain(filter.keys.iter());
+    bloom_contains_all(bloom, values)
+        && filter
+            .key_patterns
+            .iter()
+            .filter(|pattern| !pattern.is_any())
+            .all(|pattern| pattern.values.iter().any(|value| bloom.check(value)))
+}
+
+/// Returns true if the block may contain a state diff matching the filter.
+fn state_update_filter_may_match(bloom: &Bloom, filter: &v1alpha2::StateUpdateFilter) -> bool {
+    filter
+        .storage_diffs
+        .iter()
+        .any(|f| bloom_contains_all(bloom, f.contract_address.as_ref()))
+        || filter
+            .declared_contracts
+            .iter()
+            .any(|f| bloom_contains_all(bloom, f.class_hash.as_ref()))
+        || filter.deployed_contracts.iter().any(|f| {
+            let values = f.contract_address.iter().chain(f.class_hash.iter());
+            bloom_contains_all(bloom, values)
+        })
+        || filter
+            .nonces
+            .iter()
+            .any(|f| bloom_contains_all(bloom, f.contract_address.as_ref()))
+}
+
+/// Returns the values accepted as the first key of an event matching the filter.
+///
+/// An empty list means any first key is accepted.
+fn event_filter_first_keys(filter: &v1alpha2::EventFilter) -> Vec<&v1alpha2::FieldElement> {
+    if let Some(key) = filter.keys.first() {
+        return vec![key];
+    }
+    filter
+        .key_patterns
+        .first()
+        .map(|pattern| pattern.values.iter().collect())
+        .unwrap_or_default()
+}
+
+/// Returns the values a transaction must contain to match the filter.
+fn transaction_filter_values(filter: &v1alpha2::TransactionFilter) -> Vec<&v1alpha2::FieldElement> {
+    use v1alpha2::transaction_filter::Filter;
+
+    let values = match filter.filter.as_ref() {
+        None => return Vec::default(),
+        Some(Filter::InvokeV0(f)) => [
+            f.contract_address.as_ref(),
+            f.entry_point_selector.as_ref(),
+            None,
+        ],
+        Some(Filter::InvokeV1(f)) => [f.sender_address.as_ref(), None, None],
+        Some(Filter::Deploy(f)) => [
+            f.class_hash.as_ref(),
+            f.contract_address_salt.as_ref(),
+            None,
+        ],
+        Some(Filter::Declare(f)) => [f.class_hash.as_ref(), f.sender_address.as_ref(), None],
+        Some(Filter::L1Handler(f)) => [
+            f.contract_address.as_ref(),
+            f.entry_point_selector.as_ref(),
+            None,
+        ],
+        Some(Filter::DeployAccount(f)) => [
+            f.class_hash.as_ref(),
+            f.contract_address_salt.as_ref(),
+            None,
+        ],
+        Some(Filter::InvokeV3(f)) => [f.sender_address.as_ref(), None, None],
+        Some(Filter::DeclareV2(f)) => [
+            f.class_hash.as_ref(),
+            f.sender_address.as_ref(),
+            f.compiled_class_hash.as_ref(),
+        ],
+        Some(Filter::DeclareV3(f)) => [
+            f.class_hash.as_ref(),
+            f.sender_address.as_ref(),
+            f.compiled_class_hash.as_ref(),
+        ],
+        Some(Filter::DeployAccountV3(f)) => [
+            f.class_hash.as_ref(),
+            f.contract_address_salt.as_ref(),
+            None,
+        ],
+    };
+    values.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use apibara_core::starknet::v1alpha2;
+    use apibara_node::db::{
+        libmdbx::{Environment, NoWriteMap},
+        MdbxEnvironmentExt,
+    };
+    use quickcheck_macros::quickcheck;
+    use tempfile::TempDir;
+
+    use crate::{
+        core::GlobalBlockId,
+        db::{
+            new_bloom, tables, BlockBody, Bloom, DatabaseStorage, StorageReader, StorageWriter,
+            EVENT_INDEX_RANGE,
+        },
+        server::RequestMeter,
+    };
+
+    use super::{
+        bloom_contains_all, event_filter_may_match, state_update_filter_may_match,
+        transaction_filter_values, BlockDataFilter, DatabaseBlockDataFilter,
+    };
+
+    struct NoopMeter;
+
+    impl RequestMeter for NoopMeter {
+        fn increment_counter(&self, _name: &'static str, _amount: u64) {}
+    }
+
+    struct TestStorage {
+        storage: Arc<DatabaseStorage<NoWriteMap>>,
+        _datadir: TempDir,
+    }
+
+    impl TestStorage {
+        fn new() -> TestStorage {
+            let datadir = tempfile::tempdir().unwrap();
+            let db = Environment::<NoWriteMap>::builder()
+                .with_size_gib(1, 10)
+                .open(datadir.path())
+                .unwrap();
+            let txn = db.begin_rw_txn().unwrap();
+            tables::ensure(&txn).unwrap();
+            txn.commit().unwrap();
+
+            TestStorage {
+                storage: Arc::new(DatabaseStorage::new(Arc::new(db))),
+                _datadir: datadir,
+            }
+        }
+
+        /// Writes a block with the given transactions and receipts to the canonical chain.
+        fn write_block(
+            &self,
+            number: u64,
+            transactions: Vec<v1alpha2::Transaction>,
+            receipts: Vec<v1alpha2::TransactionReceipt>,
+        ) -> GlobalBlockId {
+            let block_id =
+                GlobalBlockId::new(number, v1alpha2::FieldElement::from_u64(number + 1).into());
+            let header = v1alpha2::BlockHeader {
+                block_number: number,
+                ..Default::default()
+            };
+            let mut txn = self.storage.begin_txn().unwrap();
+            txn.write_status(&block_id, v1alpha2::BlockStatus::AcceptedOnL2)
+                .unwrap();
+            txn.write_header(&block_id, header).unwrap();
+            txn.write_body(&block_id, BlockBody { transactions })
+                .unwrap();
+            txn.write_receipts(&block_id, receipts).unwrap();
+            txn.extend_canonical_chain(&block_id).unwrap();
+            txn.commit().unwrap();
+            block_id
+        }
+
+        /// Writes a block with a single transaction that emits the given events.
+        fn write_events(&self, number: u64, events: Vec<v1alpha2::Event>) -> GlobalBlockId {
+            self.write_block(
+                number,
+                vec![new_invoke(1)],
+                vec![new_receipt(events, vec![])],
+            )
+        }
+
+        fn next_candidate_block(
+            &self,
+            filter: v1alpha2::Filter,
+            from: u64,
+            to: u64,
+        ) -> Option<u64> {
+            DatabaseBlockDataFilter::new(self.storage.clone(), filter)
+                .next_candidate_block(from, to)
+                .unwrap()
+        }
+
+        fn data_for_block(
+            &self,
+            block_id: &GlobalBlockId,
+            filter: v1alpha2::Filter,
+        ) -> Option<v1alpha2::Block> {
+            DatabaseBlockDataFilter::new(self.storage.clone(), filter)
+                .data_for_block(block_id, &Arc::new(NoopMeter))
+                .unwrap()
+        }
+    }
+
+    fn new_invoke(sender: u8) -> v1alpha2::Transaction {
+        v1alpha2::Transaction {
+            meta: None,
+            transaction: Some(v1alpha2::transaction::Transaction::InvokeV1(
+                v1alpha2::InvokeTransactionV1 {
+                    sender_address: Some(felt(sender)),
+                    ..Default::default()
+                },
+            )),
+        }
+    }
+
+    fn new_receipt(
+        events: Vec<v1alpha2::Event>,
+        messages: Vec<v1alpha2::L2ToL1Message>,
+    ) -> v1alpha2::TransactionReceipt {
+        v1alpha2::TransactionReceipt {
+            execution_status: v1alpha2::ExecutionStatus::Succeeded as i32,
+            events,
+            l2_to_l1_messages: messages,
+            ..Default::default()
+        }
+    }
+
+    fn new_message(to_address: u8) -> v1alpha2::L2ToL1Message {
+        v1alpha2::L2ToL1Message {
+            to_address: Some(felt(to_address)),
+            ..Default::default()
+        }
+    }
+
+    fn felt(value: u8) -> v1alpha2::FieldElement {
+        v1alpha2::FieldElement::from_u64(value as u64)
+    }
+
+    fn new_event(address: u8, keys: &[u8]) -> v1alpha2::Event {
+        v1alpha2::Event {
+            from_address: Some(felt(address)),
+            keys: keys.iter().copied().map(felt).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn new_filter(address: Option<u8>, keys: &[u8]) -> v1alpha2::EventFilter {
+        v1alpha2::EventFilter {
+            from_address: address.map(felt),
+            keys: keys.iter().copied().map(felt).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Builds the events bloom from the same values as the storage writer.
+    fn events_bloom(events: &[v1alpha2::Event]) -> Bloom {
+        let values = events
+            .iter()
+            .flat_map(|event| event.from_address.iter().chain(event.keys.iter()))
+            .collect();
+        new_bloom(values)
+    }
+
+    #[quickcheck]
+    fn test_event_bloom_has_no_false_negatives(
+        events: Vec<(u8, Vec<u8>)>,
+        address: Option<u8>,
+        keys: Vec<u8>,
+    ) -> bool {
+        let events: Vec<_> = events
+            .iter()
+            .map(|(address, keys)| new_event(*address, keys))
+            .collect();
+        let filter = new_filter(address, &keys);
+        let bloom = events_bloom(&events);
+
+        let has_match = events.iter().any(|event| filter.matches(event));
+        !has_match || event_filter_may_match(&bloom, &filter)
+    }
+
+    #[quickcheck]
+    fn test_event_bloom_with_key_patterns_has_no_false_negatives(
+        events: Vec<(u8, Vec<u8>)>,
+        address: Option<u8>,
+        patterns: Vec<Vec<u8>>,
+    ) -> bool {
+        let events: Vec<_> = events
+            .iter()
+            .map(|(address, keys)| new_event(*address, keys))
+            .collect();
+        let patterns = patterns
+            .into_iter()
+            .map(|values| v1alpha2::KeyPattern::one_of(values.into_iter().map(felt).collect()))
+            .collect();
+        let filter = new_filter(address, &[]).with_key_patterns(patterns);
+        let bloom = events_bloom(&events);
+
+        let has_match = events.iter().any(|event| filter.matches(event));
+        !has_match || event_filter_may_match(&bloom, &filter)
+    }
+
+    #[quickcheck]
+    fn test_event_bloom_matches_own_event(address: u8, keys: Vec<u8>, prefix_len: usize) -> bool {
+        let event = new_event(address, &keys);
+        let prefix_len = prefix_len % (keys.len() + 1);
+        let filter = new_filter(Some(address), &keys[..prefix_len]);
+        let bloom = events_bloom(&[event.clone()]);
+
+        filter.matches(&event) && event_filter_may_match(&bloom, &filter)
+    }
+
+    #[test]
+    fn test_event_bloom_requires_address_and_keys() {
+        let events = vec![new_event(1, &[10]), new_event(2, &[20])];
+        let bloom = events_bloom(&events);
+
+        assert!(event_filter_may_match(&bloom, &new_filter(Some(1), &[10])));
+        assert!(event_filter_may_match(&bloom, &new_filter(None, &[20])));
+        // key is in the block, but the address is not.
+        assert!(!event_filter_may_match(&bloom, &new_filter(Some(3), &[10])));
+        // address is in the block, but not all keys are.
+        assert!(!event_filter_may_match(
+            &bloom,
+            &new_filter(Some(1), &[10, 30])
+        ));
+    }
+
+    #[test]
+    fn test_transaction_receipt_is_omitted_unless_included() {
+        let test = TestStorage::new();
+        let block_id = test.write_block(0, vec![new_invoke(1)], vec![new_receipt(vec![], vec![])]);
+
+        let filter = v1alpha2::Filter::default().add_transaction(|tx| tx);
+        let block = test.data_for_block(&block_id, filter).unwrap();
+        assert_eq!(block.transactions.len(), 1);
+        assert!(block.transactions[0].receipt.is_some());
+
+        let filter =
+            v1alpha2::Filter::default().add_transaction(|tx| tx.with_include_receipt(false));
+        let block = test.data_for_block(&block_id, filter).unwrap();
+        assert_eq!(block.transactions.len(), 1);
+        assert!(block.transactions[0].transaction.is_some());
+        assert!(block.transactions[0].receipt.is_none());
+    }
+
+    #[test]
+    fn test_event_transaction_and_receipt_are_omitted_unless_included() {
+        let test = TestStorage::new();
+        let block_id = test.write_block(
+            0,
+            vec![new_invoke(1)],
+            vec![new_receipt(vec![new_event(2, &[10])], vec![])],
+        );
+
+        let filter = v1alpha2::Filter::default().add_event(|ev| {
+            ev.with_from_address(felt(2))
+                .with_include_transaction(false)
+                .with_include_receipt(false)
+        });
+        let block = test.data_for_block(&block_id, filter).unwrap();
+        assert_eq!(block.events.len(), 1);
+        assert!(block.events[0].event.is_some());
+        assert!(block.events[0].transaction.is_none());
+        assert!(block.events[0].receipt.is_none());
+
+        // the event is sent once, with the data requested by any matching filter.
+        let filter = v1alpha2::Filter::default()
+            .add_event(|ev| {
+                ev.with_from_address(felt(2))
+                    .with_include_transaction(false)
+                    .with_include_receipt(false)
+            })
+            .add_event(|ev| {
+                ev.with_keys(vec![felt(10)])
+                    .with_include_transaction(false)
+                    .with_include_receipt(true)
+            });
+        let block = test.data_for_block(&block_id, filter).unwrap();
+        assert_eq!(block.events.len(), 1);
+        assert!(block.events[0].transaction.is_none());
+        assert!(block.events[0].receipt.is_some());
+    }
+
+    #[test]
+    fn test_message_transaction_and_receipt_are_omitted_unless_included() {
+        let test = TestStorage::new();
+        let block_id = test.write_block(
+            0,
+            vec![new_invoke(1)],
+            vec![new_receipt(vec![], vec![new_message(3)])],
+        );
+
+        let filter = v1alpha2::Filter::default().add_message(|msg| msg.with_to_address(felt(3)));
+        let block = test.data_for_block(&block_id, filter).unwrap();
+        assert_eq!(block.l2_to_l1_messages.len(), 1);
+        assert!(block.l2_to_l1_messages[0].transaction.is_some());
+        assert!(block.l2_to_l1_messages[0].receipt.is_some());
+
+        let filter = v1alpha2::Filter::default().add_message(|msg| {
+            msg.with_to_address(felt(3))
+                .with_include_transaction(false)
+                .with_include_receipt(false)
+        });
+        let block = test.data_for_block(&block_id, filter).unwrap();
+        assert_eq!(block.l2_to_l1_messages.len(), 1);
+        assert!(block.l2_to_l1_messages[0].message.is_some());
+        assert!(block.l2_to_l1_messages[0].transaction.is_none());
+        assert!(block.l2_to_l1_messages[0].receipt.is_none());
+    }
+
+    #[test]
+    fn test_next_candidate_block_skips_blocks_without_events() {
+        let test = TestStorage::new();
+        test.write_events(0, vec![]);
+        for number in [2, 5, 9] {
+            test.write_events(number, vec![new_event(1, &[10])]);
+        }
+        // same address or key, but not both in the same event.
+        test.write_events(3, vec![new_event(1, &[11])]);
+        test.write_events(4, vec![new_event(2, &[10])]);
+
+        let filter = v1alpha2::Filter::default()
+            .add_event(|ev| ev.with_from_address(felt(1)).with_keys(vec![felt(10)]));
+        assert_eq!(test.next_candidate_block(filter.clone(), 0, 10), Some(2));
+        assert_eq!(test.next_candidate_block(filter.clone(), 2, 10), Some(2));
+        assert_eq!(test.next_candidate_block(filter.clone(), 3, 10), Some(5));
+        assert_eq!(test.next_candidate_block(filter.clone(), 6, 10), Some(9));
+        assert_eq!(test.next_candidate_block(filter.clone(), 10, 20), None);
+
+        let filter = v1alpha2::Filter::default().add_event(|ev| ev.with_from_address(felt(2)));
+        assert_eq!(test.next_candidate_block(filter, 0, 10), Some(4));
+
+        let filter = v1alpha2::Filter::default().add_event(|ev| ev.with_keys(vec![felt(11)]));
+        assert_eq!(test.next_candidate_block(filter, 0, 10), Some(3));
+
+        // key patterns match any of their values.
+        let filter = v1alpha2::Filter::default().add_event(|ev| {
+            ev.with_key_patterns(vec![v1alpha2::KeyPattern::one_of(vec![felt(11), felt(12)])])
+        });
+        assert_eq!(test.next_candidate_block(filter, 4, 10), None);
+
+        // the earliest block of all filters.
+        let filter = v1alpha2::Filter::default()
+            .add_event(|ev| ev.with_from_address(felt(1)).with_keys(vec![felt(10)]))
+            .add_event(|ev| ev.with_from_address(felt(2)));
+        assert_eq!(test.next_candidate_block(filter, 3, 10), Some(4));
+    }
+
+    #[test]
+    fn test_next_candidate_block_range_boundaries() {
+        let test = TestStorage::new();
+        test.write_events(1, vec![new_event(1, &[10])]);
+        test.write_events(EVENT_INDEX_RANGE - 1, vec![new_event(1, &[10])]);
+        test.write_events(EVENT_INDEX_RANGE + 3, vec![new_event(1, &[10])]);
+        test.write_events(3 * EVENT_INDEX_RANGE, vec![new_event(1, &[10])]);
+
+        let filter = v1alpha2::Filter::default()
+            .add_event(|ev| ev.with_from_address(felt(1)).with_keys(vec![felt(10)]));
+
+        // the next block is in the same index entry, or in the following ones.
+        assert_eq!(
+            test.next_candidate_block(filter.clone(), 2, 10 * EVENT_INDEX_RANGE),
+            Some(EVENT_INDEX_RANGE - 1)
+        );
+        assert_eq!(
+            test.next_candidate_block(filter.clone(), EVENT_INDEX_RANGE, 10 * EVENT_INDEX_RANGE),
+            Some(EVENT_INDEX_RANGE + 3)
+        );
+        assert_eq!(
+            test.next_candidate_block(
+                filter.clone(),
+                EVENT_INDEX_RANGE + 4,
+                10 * EVENT_INDEX_RANGE
+            ),
+            Some(3 * EVENT_INDEX_RANGE)
+        );
+
+        // blocks after `to` are not candidates.
+        assert_eq!(
+            test.next_candidate_block(filter.clone(), 2, EVENT_INDEX_RANGE - 2),
+            None
+        );
+        assert_eq!(
+            test.next_candidate_block(filter.clone(), 2, EVENT_INDEX_RANGE - 1),
+            Some(EVENT_INDEX_RANGE - 1)
+        );
+        assert_eq!(test.next_candidate_block(filter.clone(), 5, 4), None);
+    }
+
+    #[test]
+    fn test_next_candidate_block_without_index() {
+        let test = TestStorage::new();
+        // the indices start at the first block written with its receipts.
+        test.write_events(5, vec![new_event(1, &[10])]);
+        test.write_events(8, vec![new_event(1, &[10])]);
+
+        let filter = v1alpha2::Filter::default().add_event(|ev| ev.with_from_address(felt(1)));
+        assert_eq!(test.next_candidate_block(filter.clone(), 2, 10), Some(2));
+        assert_eq!(test.next_candidate_block(filter.clone(), 6, 10), Some(8));
+
+        // transactions are not indexed.
+        let filter = filter.add_transaction(|tx| tx);
+        assert_eq!(test.next_candidate_block(filter, 6, 10), Some(6));
+
+        // strong headers are sent for every block.
+        let filter = v1alpha2::Filter::default()
+            .with_header(v1alpha2::HeaderFilter::new())
+            .add_event(|ev| ev.with_from_address(felt(1)));
+        assert_eq!(test.next_candidate_block(filter, 6, 10), Some(6));
+
+        // nothing to send.
+        let filter = v1alpha2::Filter::default();
+        assert_eq!(test.next_candidate_block(filter, 6, 10), None);
+    }
+
+    #[test]
+    fn test_transaction_bloom_rejects_other_transactions() {
+        use v1alpha2::transaction_filter::Filter;
+
+        let test = TestStorage::new();
+        let declare = v1alpha2::Transaction {
+            meta: None,
+            transaction: Some(v1alpha2::transaction::Transaction::DeclareV2(
+                v1alpha2::DeclareTransactionV2 {
+                    class_hash: Some(felt(5)),
+                    sender_address: Some(felt(2)),
+                    compiled_class_hash: Some(felt(6)),
+                },
+            )),
+        };
+        let block_id = test.write_block(
+            0,
+            vec![new_invoke(1), declare],
+            vec![new_receipt(vec![], vec![]), new_receipt(vec![], vec![])],
+        );
+        let bloom = test
+            .storage
+            .read_blooms(&block_id)
+            .unwrap()
+            .transactions
+            .unwrap();
+
+        let invoke = |sender: u8| v1alpha2::TransactionFilter {
+            filter: Some(Filter::InvokeV1(
+                v1alpha2::InvokeTransactionV1Filter::default().with_sender_address(felt(sender)),
+            )),
+            ..Default::default()
+        };
+        assert!(bloom_contains_all(
+            &bloom,
+            transaction_filter_values(&invoke(1))
+        ));
+        assert!(!bloom_contains_all(
+            &bloom,
+            transaction_filter_values(&invoke(3))
+        ));
+
+        let declare = |compiled_class_hash: u8| v1alpha2::TransactionFilter {
+            filter: Some(Filter::DeclareV2(
+                v1alpha2::DeclareTransactionV2Filter::default()
+                    .with_class_hash(felt(5))
+                    .with_compiled_class_hash(felt(compiled_class_hash)),
+            )),
+            ..Default::default()
+        };
+        assert!(bloom_contains_all(
+            &bloom,
+            transaction_filter_values(&declare(6))
+        ));
+        assert!(!bloom_contains_all(
+            &bloom,
+            transaction_filter_values(&declare(7))
+        ));
+    }
+
+    #[test]
+    fn test_message_bloom_rejects_other_messages() {
+        let test = TestStorage::new();
+        let block_id = test.write_block(
+            0,
+            vec![new_invoke(1)],
+            vec![new_receipt(vec![], vec![new_message(3)])],
+        );
+        let bloom = test
+            .storage
+            .read_blooms(&block_id)
+            .unwrap()
+            .messages
+            .unwrap();
+
+        assert!(bloom_contains_all(&bloom, Some(&felt(3))));
+        assert!(!bloom_contains_all(&bloom, Some(&felt(4))));
+    }
+
+    #[test]
+    fn test_state_update_bloom_rejects_other_state_diffs() {
+        let test = TestStorage::new();
+        let block_id = test.write_block(0, vec![], vec![]);
+        let state_update = v1alpha2::StateUpdate {
+            state_diff: Some(v1alpha2::StateDiff {
+                storage_diffs: vec![v1alpha2::StorageDiff {
+                    contract_address: Some(felt(1)),
+                    ..Default::default()
+                }],
+                declared_contracts: vec![v1alpha2::DeclaredContract {
+                    class_hash: Some(felt(2)),
+                }],
+                deployed_contracts: vec![v1alpha2::DeployedContract {
+                    contract_address: Some(felt(3)),
+                    class_hash: Some(felt(4)),
+                }],
+                nonces: vec![v1alpha2::NonceUpdate {
+                    contract_address: Some(felt(5)),
+                    nonce: Some(felt(6)),
+                }],
+            }),
+            ..Default::default()
+        };
+        let mut txn = test.storage.begin_txn().unwrap();
+        txn.write_state_update(&block_id, state_update).unwrap();
+        txn.commit().unwrap();
+
+        let bloom = test
+            .storage
+            .read_blooms(&block_id)
+            .unwrap()
+            .state_update
+            .unwrap();
+        let may_match =
+            |filter: v1alpha2::StateUpdateFilter| state_update_filter_may_match(&bloom, &filter);
+        let filter = v1alpha2::StateUpdateFilter::default;
+
+        assert!(may_match(
+            filter().add_storage_diff(|f| f.with_contract_address(felt(1)))
+        ));
+        assert!(!may_match(
+            filter().add_storage_diff(|f| f.with_contract_address(felt(7)))
+        ));
+
+        assert!(may_match(
+            filter().add_declared_contract(|f| f.with_class_hash(felt(2)))
+        ));
+        assert!(!may_match(
+            filter().add_declared_contract(|f| f.with_class_hash(felt(7)))
+        ));
+
+        assert!(may_match(filter().add_deployed_contract(|f| {
+            f.with_contract_address(felt(3)).with_class_hash(felt(4))
+        })));
+        assert!(!may_match(filter().add_deployed_contract(|f| {
+            f.with_contract_address(felt(3)).with_class_hash(felt(7))
+        })));
+
+        assert!(may_match(
+            filter().add_nonce_update(|f| f.with_contract_address(felt(5)))
+        ));
+        assert!(!may_match(
+            filter().add_nonce_update(|f| f.with_contract_address(felt(7)))
+        ));
+    }
+}
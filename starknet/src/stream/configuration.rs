@@ -29,6 +29,22 @@ pub struct StreamConfiguration {
     pub finality: DataFinality,
     pub starting_cursor: Option<GlobalBlockId>,
     pub filter: Filter,
+    pub include_rejected: bool,
+    /// Number of messages the stream can send, if flow controlled.
+    pub initial_credits: Option<u64>,
+}
+
+/// A change to the streams of a connection requested by the client.
+#[derive(Debug, Clone)]
+pub enum ConfigurationMessage {
+    /// Start a new stream, or replace the one with the same id.
+    Configure(StreamConfiguration),
+    /// Stop the stream with the given id.
+    Close { stream_id: u64 },
+    /// Let the stream with the given id send more messages.
+    Credit { stream_id: u64, credits: u64 },
+    /// The request for the stream with the given id is invalid.
+    Invalid { stream_id: u64, message: String },
 }
 
 #[derive(Default)]
@@ -61,10 +77,17 @@ where
 }
 
 impl StreamConfigurationStreamState {
-    fn handle_request(
-        &mut self,
-        request: StreamDataRequest,
-    ) -> Result<StreamConfiguration, StreamError> {
+    fn handle_request(&mut self, request: StreamDataRequest) -> ConfigurationMessage {
+        let stream_id = request.stream_id.unwrap_or_default();
+
+        if request.close.unwrap_or_default() {
+            return ConfigurationMessage::Close { stream_id };
+        }
+
+        if let Some(credits) = request.credits {
+            return ConfigurationMessage::Credit { stream_id, credits };
+        }
+
         let batch_size = request.batch_size.unwrap_or(DEFAULT_BATCH_SIZE as u64) as usize;
         let batch_size = batch_size.clamp(MIN_BATCH_SIZE, MAX_BATCH_SIZE);
 
@@ -73,16 +96,21 @@ impl StreamConfigurationStreamState {
             .and_then(DataFinality::from_i32)
             .unwrap_or(DataFinality::DataStatusAccepted);
 
-        let stream_id = request.stream_id.unwrap_or_default();
-
-        let filter = Filter::decode(request.filter.as_ref())
-            .map_err(|_| StreamError::client("invalid filter"))?;
+        let filter = match Filter::decode(request.filter.as_ref()) {
+            Ok(filter) => filter,
+            Err(_) => return ConfigurationMessage::invalid(stream_id, "invalid filter"),
+        };
 
-        let starting_cursor = request
+        let starting_cursor = match request
             .starting_cursor
             .map(|c| GlobalBlockId::from_cursor(&c))
             .transpose()
-            .map_err(|_| StreamError::client("invalid stream cursor"))?;
+        {
+            Ok(starting_cursor) => starting_cursor,
+            Err(_) => return ConfigurationMessage::invalid(stream_id, "invalid stream cursor"),
+        };
+
+        let include_rejected = request.include_rejected.unwrap_or_default();
 
         let configuration = StreamConfiguration {
             batch_size,
@@ -90,11 +118,22 @@ impl StreamConfigurationStreamState {
             stream_id,
             filter,
             starting_cursor,
+            include_rejected,
+            initial_credits: request.initial_credits,
         };
 
         self.current = Some(configuration.clone());
 
-        Ok(configuration)
+        ConfigurationMessage::Configure(configuration)
+    }
+}
+
+impl ConfigurationMessage {
+    fn invalid(stream_id: u64, message: impl Into<String>) -> Self {
+        ConfigurationMessage::Invalid {
+            stream_id,
+            message: message.into(),
+        }
     }
 }
 
@@ -103,7 +142,7 @@ where
     S: Stream<Item = Result<StreamDataRequest, E>>,
     E: std::error::Error + Send + Sync + 'static,
 {
-    type Item = Result<StreamConfiguration, StreamError>;
+    type Item = Result<ConfigurationMessage, StreamError>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
         let this = self.project();
@@ -116,8 +155,8 @@ where
                 Poll::Ready(Some(err))
             }
             Poll::Ready(Some(Ok(request))) => {
-                let result = this.state.handle_request(request);
-                Poll::Ready(Some(result))
+                let message = this.state.handle_request(request);
+                Poll::Ready(Some(Ok(message)))
             }
         }
     }
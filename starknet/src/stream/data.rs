@@ -4,6 +4,7 @@ use std::{
     pin::Pin,
     sync::Arc,
     task::{self, Poll},
+    time::Duration,
 };
 
 use apibara_core::node::v1alpha2::StreamDataResponse;
@@ -15,7 +16,7 @@ use crate::{
     core::IngestionMessage, db::StorageReader, healer::HealerClient, server::RequestMeter,
 };
 
-use super::{configuration::StreamConfiguration, filtered::FilteredDataStream, StreamError};
+use super::{configuration::ConfigurationMessage, filtered::FilteredDataStream, StreamError};
 
 #[derive(Debug, thiserror::Error)]
 pub enum DataStreamError {
@@ -28,7 +29,7 @@ pub enum DataStreamError {
 #[pin_project]
 pub struct DataStream<C, L, R, M>
 where
-    C: Stream<Item = Result<StreamConfiguration, StreamError>>,
+    C: Stream<Item = Result<ConfigurationMessage, StreamError>>,
     L: Stream<Item = Result<IngestionMessage, StreamError>>,
     R: StorageReader,
     M: RequestMeter,
@@ -43,7 +44,7 @@ where
 
 impl<C, L, R, M> DataStream<C, L, R, M>
 where
-    C: Stream<Item = Result<StreamConfiguration, StreamError>>,
+    C: Stream<Item = Result<ConfigurationMessage, StreamError>>,
     L: Stream<Item = Result<IngestionMessage, StreamError>>,
     R: StorageReader,
     M: RequestMeter,
@@ -55,18 +56,19 @@ where
         storage: Arc<R>,
         healer: Arc<HealerClient>,
         meter: Arc<M>,
+        progress_interval: Duration,
     ) -> Self {
         DataStream {
             configuration_stream,
             ingestion_stream,
-            inner: FilteredDataStream::new(storage, healer, meter),
+            inner: FilteredDataStream::new(storage, healer, meter, progress_interval),
         }
     }
 }
 
 impl<C, L, R, M> Stream for DataStream<C, L, R, M>
 where
-    C: Stream<Item = Result<StreamConfiguration, StreamError>>,
+    C: Stream<Item = Result<ConfigurationMessage, StreamError>>,
     L: Stream<Item = Result<IngestionMessage, StreamError>>,
     R: StorageReader,
     M: RequestMeter,
@@ -93,16 +95,25 @@ where
                 // forward configuration error
                 return Poll::Ready(Some(Err(err)));
             }
-            Poll::Ready(Some(Ok(configuration))) => {
+            Poll::Ready(Some(Ok(message))) => {
                 // configuration changed.
-                // update and restart, or return error
-                match this.inner.reconfigure_data_stream(configuration) {
-                    Ok(_) => {
-                        cx.waker().wake_by_ref();
-                        return Poll::Pending;
+                // update and restart. errors only affect the configured stream.
+                match message {
+                    ConfigurationMessage::Configure(configuration) => {
+                        this.inner.reconfigure_data_stream(configuration)
+                    }
+                    ConfigurationMessage::Close { stream_id } => {
+                        this.inner.close_data_stream(stream_id)
+                    }
+                    ConfigurationMessage::Credit { stream_id, credits } => {
+                        this.inner.add_data_stream_credits(stream_id, credits)
                     }
-                    Err(err) => return Poll::Ready(Some(Err(err))),
+                    ConfigurationMessage::Invalid { stream_id, message } => this
+                        .inner
+                        .fail_data_stream(stream_id, StreamError::client(message)),
                 }
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
             }
         }
 
@@ -123,13 +134,9 @@ where
             }
             Poll::Ready(Some(Ok(message))) => {
                 // update state based on ingestion message.
-                match this.inner.handle_ingestion_message(message) {
-                    Ok(_) => {
-                        cx.waker().wake_by_ref();
-                        return Poll::Pending;
-                    }
-                    Err(err) => return Poll::Ready(Some(Err(err))),
-                }
+                this.inner.handle_ingestion_message(message);
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
             }
         }
 
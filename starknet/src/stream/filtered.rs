@@ -1,20 +1,22 @@
 //! Filtered data stream.
 
 use std::{
+    collections::{BTreeMap, VecDeque},
     pin::Pin,
     sync::Arc,
     task::{self, Poll, Waker},
+    time::{Duration, Instant},
 };
 
 use apibara_core::node::v1alpha2::{
-    stream_data_response, Data, DataFinality, Invalidate, StreamDataResponse,
+    self, stream_data_response, Data, DataFinality, Invalidate, StreamDataResponse,
 };
 use futures::Stream;
 use prost::Message;
-use tracing::debug;
+use tracing::{debug, warn};
 
 use crate::{
-    core::{GlobalBlockId, IngestionMessage},
+    core::{GlobalBlockId, IngestionMessage, InvalidBlock},
     db::StorageReader,
     healer::HealerClient,
     server::RequestMeter,
@@ -26,8 +28,20 @@ use super::{
     StreamError,
 };
 
-const MAX_BATCH_ITER: i32 = 5_000;
+/// Default interval between empty finalized batches.
+pub const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
 
+/// Maximum time spent scanning finalized blocks before yielding to the executor.
+const MAX_SCAN_DURATION: Duration = Duration::from_millis(100);
+
+/// Maximum number of streams open on a single connection.
+pub const MAX_STREAMS_PER_CONNECTION: usize = 64;
+
+/// Sends the data of all the streams of a connection.
+///
+/// Streams take turns sending responses. Streams configured with credits
+/// only send responses while they have credits left, so that a stream whose
+/// client is slow doesn't pause the others.
 pub struct FilteredDataStream<R, M>
 where
     R: StorageReader,
@@ -36,8 +50,14 @@ where
     storage: Arc<R>,
     meter: Arc<M>,
     healer: Arc<HealerClient>,
+    progress_interval: Duration,
     waker: Option<Waker>,
-    inner: Option<InnerDataStream<R, M>>,
+    /// Streams configured by the client, by stream id.
+    streams: BTreeMap<u64, InnerDataStream<R, M>>,
+    /// Id of the first stream polled for data, so that streams take turns.
+    next_stream_id: u64,
+    /// Errors of the streams that failed, sent before any data.
+    errors: VecDeque<StreamDataResponse>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -55,14 +75,25 @@ struct InnerDataStream<R: StorageReader, M: RequestMeter> {
     batch_size: usize,
     data_finality: DataFinality,
     previous_iter_cursor: Option<GlobalBlockId>,
+    /// End cursor of the last data sent to the client.
+    ///
+    /// Empty batches that are skipped don't change it, so that the cursor of
+    /// each batch is the end cursor of the previous one.
+    last_sent_cursor: Option<GlobalBlockId>,
     finalized_cursor: Option<GlobalBlockId>,
     accepted_cursor: GlobalBlockId,
     pending_cursor: Option<GlobalBlockId>,
     filter: DatabaseBlockDataFilter<R>,
     storage: Arc<R>,
     healer: Arc<HealerClient>,
-    invalidated: Option<GlobalBlockId>,
+    include_rejected: bool,
+    invalidated: Option<Invalidate>,
     meter: Arc<M>,
+    progress_interval: Duration,
+    last_response_at: Instant,
+    scan_interrupted: bool,
+    /// Number of responses the stream can send, `None` if not flow controlled.
+    credits: Option<u64>,
 }
 
 impl<R, M> FilteredDataStream<R, M>
@@ -70,22 +101,54 @@ where
     R: StorageReader,
     M: RequestMeter,
 {
-    pub fn new(storage: Arc<R>, healer: Arc<HealerClient>, meter: Arc<M>) -> Self {
+    /// Creates a new filtered data stream.
+    ///
+    /// Finalized batches without any data are only sent once every
+    /// `progress_interval`, to let clients know the stream is progressing.
+    pub fn new(
+        storage: Arc<R>,
+        healer: Arc<HealerClient>,
+        meter: Arc<M>,
+        progress_interval: Duration,
+    ) -> Self {
         FilteredDataStream {
             storage,
             healer,
             meter,
-            inner: None,
+            progress_interval,
+            streams: BTreeMap::default(),
+            next_stream_id: 0,
+            errors: VecDeque::default(),
             waker: None,
         }
     }
 
-    pub fn reconfigure_data_stream(
+    /// Starts streaming data with the given configuration.
+    ///
+    /// If a stream with the same id exists, it's replaced. If the stream
+    /// cannot be started, it fails without affecting the other streams.
+    pub fn reconfigure_data_stream(&mut self, configuration: StreamConfiguration) {
+        let stream_id = configuration.stream_id;
+        if let Err(err) = self.configure_data_stream(configuration) {
+            self.fail_data_stream(stream_id, err);
+        }
+    }
+
+    fn configure_data_stream(
         &mut self,
         configuration: StreamConfiguration,
     ) -> Result<(), StreamError> {
-        // use finalized and accepted cursors from previous config, if any
-        let (finalized_cursor, accepted_cursor) = if let Some(inner) = self.inner.take() {
+        let is_new_stream = !self.streams.contains_key(&configuration.stream_id);
+        if is_new_stream && self.streams.len() >= MAX_STREAMS_PER_CONNECTION {
+            return Err(StreamError::client(format!(
+                "too many streams, maximum is {MAX_STREAMS_PER_CONNECTION}"
+            )));
+        }
+
+        // all streams receive the same ingestion messages, so use the finalized
+        // and accepted cursors from any existing stream.
+        let (finalized_cursor, accepted_cursor) = if let Some(inner) = self.streams.values().next()
+        {
             (inner.finalized_cursor, inner.accepted_cursor)
         } else {
             let finalized_cursor = self
@@ -114,11 +177,13 @@ where
 
         let filter = DatabaseBlockDataFilter::new(self.storage.clone(), configuration.filter);
 
+        let stream_id = configuration.stream_id;
         let inner = InnerDataStream {
-            stream_id: configuration.stream_id,
+            stream_id,
             batch_size: configuration.batch_size,
             data_finality: configuration.finality,
             previous_iter_cursor: configuration.starting_cursor,
+            last_sent_cursor: configuration.starting_cursor,
             finalized_cursor,
             accepted_cursor,
             pending_cursor: None,
@@ -126,51 +191,70 @@ where
             storage: self.storage.clone(),
             healer: self.healer.clone(),
             meter: self.meter.clone(),
+            include_rejected: configuration.include_rejected,
             invalidated: None,
+            progress_interval: self.progress_interval,
+            last_response_at: Instant::now(),
+            scan_interrupted: false,
+            credits: configuration.initial_credits,
         };
 
-        self.inner = Some(inner);
+        self.streams.insert(stream_id, inner);
         self.wake();
 
         Ok(())
     }
 
-    pub fn handle_ingestion_message(
-        &mut self,
-        message: IngestionMessage,
-    ) -> Result<(), StreamError> {
-        if let Some(inner) = &mut self.inner {
-            match message {
-                IngestionMessage::Accepted(block_id) => {
-                    inner.accepted_cursor = block_id;
-                    inner.pending_cursor = None;
-                    self.wake();
-                }
-                IngestionMessage::Finalized(block_id) => {
-                    inner.finalized_cursor = Some(block_id);
-                    self.wake();
-                }
-                IngestionMessage::Pending(block_id) => {
-                    inner.pending_cursor = Some(block_id);
-                    self.wake()
-                }
-                IngestionMessage::Invalidate(new_chain_root) => {
-                    inner.accepted_cursor = new_chain_root;
-                    inner.pending_cursor = None;
-                    // only reset client cursor if the stream already sent a block
-                    // _belonging to_ the now invalidated chain.
-                    if let Some(previous_iter_cursor) = inner.previous_iter_cursor {
-                        if previous_iter_cursor.number() > new_chain_root.number() {
-                            inner.previous_iter_cursor = Some(new_chain_root);
-                            inner.invalidated = Some(new_chain_root);
-                        }
-                    }
-                    self.wake()
-                }
+    /// Stops streaming data for the given stream.
+    pub fn close_data_stream(&mut self, stream_id: u64) {
+        if self.streams.remove(&stream_id).is_none() {
+            debug!(stream_id = stream_id, "close unknown stream");
+        }
+    }
+
+    /// Lets the given stream send `credits` more responses.
+    pub fn add_data_stream_credits(&mut self, stream_id: u64, credits: u64) {
+        let inner = match self.streams.get_mut(&stream_id) {
+            None => {
+                debug!(stream_id = stream_id, "credits for unknown stream");
+                return;
             }
+            Some(inner) => inner,
+        };
+
+        // streams that are not flow controlled can send responses already.
+        if let Some(current) = inner.credits.as_mut() {
+            *current = current.saturating_add(credits);
+            self.wake();
         }
+    }
 
-        Ok(())
+    /// Stops the given stream and sends the error to the client.
+    pub fn fail_data_stream(&mut self, stream_id: u64, err: StreamError) {
+        self.streams.remove(&stream_id);
+        self.errors.push_back(error_response(stream_id, err));
+        self.wake();
+    }
+
+    pub fn handle_ingestion_message(&mut self, message: IngestionMessage) {
+        let failed = self
+            .streams
+            .iter_mut()
+            .filter_map(|(stream_id, inner)| {
+                inner
+                    .handle_ingestion_message(&message)
+                    .err()
+                    .map(|err| (*stream_id, err))
+            })
+            .collect::<Vec<_>>();
+
+        for (stream_id, err) in failed {
+            self.fail_data_stream(stream_id, err);
+        }
+
+        if !self.streams.is_empty() {
+            self.wake();
+        }
     }
 
     fn wake(&mut self) {
@@ -185,6 +269,57 @@ where
     R: StorageReader,
     M: RequestMeter,
 {
+    fn handle_ingestion_message(&mut self, message: &IngestionMessage) -> Result<(), StreamError> {
+        match *message {
+            IngestionMessage::Accepted(block_id) => {
+                self.accepted_cursor = block_id;
+                self.pending_cursor = None;
+            }
+            IngestionMessage::Finalized(block_id) => {
+                self.finalized_cursor = Some(block_id);
+            }
+            IngestionMessage::Pending(block_id) => {
+                self.pending_cursor = Some(block_id);
+            }
+            IngestionMessage::Invalidate(new_chain_root) => {
+                self.accepted_cursor = new_chain_root;
+                self.pending_cursor = None;
+                // only reset client cursor if the stream already sent a block
+                // _belonging to_ the now invalidated chain.
+                if let Some(previous_iter_cursor) = self.previous_iter_cursor {
+                    if previous_iter_cursor.number() > new_chain_root.number() {
+                        let invalidate = self.invalidate(previous_iter_cursor, new_chain_root)?;
+                        self.previous_iter_cursor = Some(new_chain_root);
+                        self.last_sent_cursor = Some(new_chain_root);
+                        self.invalidated = Some(invalidate);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the next response to send to the client, if any.
+    fn next_response(&mut self) -> Result<Option<StreamDataResponse>, StreamError> {
+        // if the stream received an invalidate message in the previous tick, then
+        // forward it to the client.
+        if let Some(invalidate) = self.invalidated.take() {
+            use stream_data_response::Message;
+            let response = StreamDataResponse {
+                stream_id: self.stream_id,
+                message: Some(Message::Invalidate(invalidate)),
+            };
+            return Ok(Some(response));
+        }
+
+        let response = self.advance_to_next_batch()?;
+        if response.is_some() {
+            self.last_response_at = Instant::now();
+        }
+        Ok(response)
+    }
+
     pub fn advance_to_next_batch(&mut self) -> Result<Option<StreamDataResponse>, StreamError> {
         // if next block is still in the finalized range, send a batch
         // if it's between finalized and accepted, send a single block
@@ -276,16 +411,14 @@ where
             "send finalized batch"
         );
 
-        let batch_start_cursor = self.previous_iter_cursor.map(|c| c.to_cursor());
+        let batch_start_cursor = self.last_sent_cursor.map(|c| c.to_cursor());
 
+        let scan_started_at = Instant::now();
         let mut batch = Vec::with_capacity(self.batch_size);
         let mut batch_end_cursor = None;
         let mut current_cursor = first_cursor;
 
-        let mut iter = 0;
-        while batch.len() < self.batch_size && iter < MAX_BATCH_ITER {
-            iter += 1;
-
+        while batch.len() < self.batch_size {
             // check the next block is still finalized.
             // if not, stop iterating.
             let block_status = self
@@ -293,15 +426,12 @@ where
                 .read_status(&current_cursor)
                 .map_err(StreamError::internal)?
                 .ok_or_else(|| {
-                    StreamError::internal(FilteredDataStreamError::MissingBlockStatus(
-                        current_cursor,
-                    ))
+                    self.missing_data(FilteredDataStreamError::MissingBlockStatus(current_cursor))
                 })?;
 
             if !block_status.is_finalized() {
                 if current_cursor.number() < finalized_cursor.number() {
                     self.healer.status_finalized_expected(current_cursor);
-                    continue;
                 }
                 break;
             }
@@ -316,9 +446,25 @@ where
                 batch.push(data.encode_to_vec());
             }
 
+            // use the indices to skip blocks that cannot contain any data.
+            let next_block_number = match self
+                .filter
+                .next_candidate_block(current_cursor.number() + 1, finalized_cursor.number())
+                .map_err(StreamError::internal)?
+            {
+                None => {
+                    // no more data up to the finalized block.
+                    if finalized_cursor.number() > current_cursor.number() {
+                        batch_end_cursor = Some(*finalized_cursor);
+                    }
+                    break;
+                }
+                Some(block_number) => block_number,
+            };
+
             match self
                 .storage
-                .canonical_block_id(current_cursor.number() + 1)
+                .canonical_block_id(next_block_number)
                 .map_err(StreamError::internal)?
             {
                 None => {
@@ -333,28 +479,43 @@ where
                     current_cursor = cursor;
                 }
             }
+
+            // don't block the executor on long scans over sparse data.
+            if scan_started_at.elapsed() >= MAX_SCAN_DURATION {
+                break;
+            }
         }
 
-        if batch_end_cursor.is_some() {
-            // update iter cursor to the latest ingested block.
-            self.previous_iter_cursor = batch_end_cursor;
+        let batch_end_cursor = match batch_end_cursor {
+            None => return Ok(None),
+            Some(cursor) => cursor,
+        };
 
-            let data = Data {
-                cursor: batch_start_cursor,
-                end_cursor: batch_end_cursor.map(|c| c.to_cursor()),
-                finality: DataFinality::DataStatusFinalized as i32,
-                data: batch,
-            };
+        // update iter cursor to the latest ingested block.
+        self.previous_iter_cursor = Some(batch_end_cursor);
 
-            let response = StreamDataResponse {
-                stream_id: self.stream_id,
-                message: Some(Message::Data(data)),
-            };
-
-            Ok(Some(response))
-        } else {
-            Ok(None)
+        // skip empty batches, unless the client hasn't received anything in a while.
+        if batch.is_empty() && self.last_response_at.elapsed() < self.progress_interval {
+            // resume scanning on the next poll if there are more finalized blocks.
+            self.scan_interrupted = batch_end_cursor.number() < finalized_cursor.number();
+            return Ok(None);
         }
+
+        self.last_sent_cursor = Some(batch_end_cursor);
+
+        let data = Data {
+            cursor: batch_start_cursor,
+            end_cursor: Some(batch_end_cursor.to_cursor()),
+            finality: DataFinality::DataStatusFinalized as i32,
+            data: batch,
+        };
+
+        let response = StreamDataResponse {
+            stream_id: self.stream_id,
+            message: Some(Message::Data(data)),
+        };
+
+        Ok(Some(response))
     }
 
     /// Send a batch of accepted data, starting from the given cursor (inclusive).
@@ -364,7 +525,7 @@ where
     ) -> Result<Option<StreamDataResponse>, StreamError> {
         use stream_data_response::Message;
 
-        let batch_start_cursor = self.previous_iter_cursor.map(|c| c.to_cursor());
+        let batch_start_cursor = self.last_sent_cursor.map(|c| c.to_cursor());
         self.previous_iter_cursor = Some(first_cursor);
 
         // read data at cursor
@@ -378,6 +539,8 @@ where
             return Ok(None);
         };
 
+        self.last_sent_cursor = Some(first_cursor);
+
         let data = Data {
             cursor: batch_start_cursor,
             end_cursor: Some(first_cursor.to_cursor()),
@@ -439,8 +602,9 @@ where
                 .storage
                 .read_status(&new_root)
                 .map_err(StreamError::internal)?
-                .ok_or(FilteredDataStreamError::MissingBlockStatus(new_root))
-                .map_err(StreamError::internal)?;
+                .ok_or_else(|| {
+                    self.missing_data(FilteredDataStreamError::MissingBlockStatus(new_root))
+                })?;
 
             // check if `new_root` is the new root.
             if status.is_accepted() || status.is_finalized() {
@@ -451,17 +615,23 @@ where
                 .storage
                 .read_header(&new_root)
                 .map_err(StreamError::internal)?
-                .ok_or(FilteredDataStreamError::MissingBlockHeader(new_root))
-                .map_err(StreamError::internal)?;
+                .ok_or_else(|| {
+                    self.missing_data(FilteredDataStreamError::MissingBlockHeader(new_root))
+                })?;
 
-            new_root = GlobalBlockId::from_block_header(&header).map_err(StreamError::internal)?;
+            // move to the parent block and check again.
+            let parent_hash = header
+                .parent_block_hash
+                .as_ref()
+                .ok_or(InvalidBlock::MissingHash)
+                .map_err(StreamError::internal)?;
+            new_root = GlobalBlockId::new(header.block_number - 1, parent_hash.into());
         }
 
+        let invalidate = self.invalidate(cursor, new_root)?;
         self.previous_iter_cursor = Some(new_root);
+        self.last_sent_cursor = Some(new_root);
 
-        let invalidate = Invalidate {
-            cursor: Some(new_root.to_cursor()),
-        };
         let response = StreamDataResponse {
             stream_id: self.stream_id,
             message: Some(Message::Invalidate(invalidate)),
@@ -469,6 +639,103 @@ where
 
         Ok(Some(response))
     }
+
+    /// Asks the healer to repair the block with missing data, then returns
+    /// the error to send to the client.
+    fn missing_data(&self, err: FilteredDataStreamError) -> StreamError {
+        match err {
+            FilteredDataStreamError::MissingBlockHeader(block_id)
+            | FilteredDataStreamError::MissingBlockStatus(block_id) => {
+                self.healer.block_data_missing(block_id);
+            }
+            FilteredDataStreamError::NoFinalizedBlockIngested => {}
+        }
+        StreamError::internal(err)
+    }
+
+    /// Creates the message that invalidates the data sent after `new_root`,
+    /// up to `cursor` (inclusive).
+    fn invalidate(
+        &self,
+        cursor: GlobalBlockId,
+        new_root: GlobalBlockId,
+    ) -> Result<Invalidate, StreamError> {
+        let rejected_data = if self.include_rejected {
+            self.rejected_data(cursor, new_root)?
+        } else {
+            Vec::default()
+        };
+
+        Ok(Invalidate {
+            cursor: Some(new_root.to_cursor()),
+            rejected_data,
+        })
+    }
+
+    /// Returns the data of the blocks after `new_root`, up to `cursor` (inclusive).
+    ///
+    /// Blocks are walked back from `cursor` by following their parent hash, so
+    /// the data is that of the chain the client received, not the new one.
+    fn rejected_data(
+        &self,
+        cursor: GlobalBlockId,
+        new_root: GlobalBlockId,
+    ) -> Result<Vec<Vec<u8>>, StreamError> {
+        let mut rejected_data = Vec::default();
+
+        // a cursor without hash doesn't identify a block the client received.
+        if cursor.hash().is_zero() {
+            return Ok(rejected_data);
+        }
+
+        let mut current = cursor;
+        while current.number() > new_root.number() {
+            if let Some(data) = self
+                .filter
+                .data_for_block(&current, &self.meter)
+                .map_err(StreamError::internal)?
+            {
+                rejected_data.push(data.encode_to_vec());
+            }
+
+            let header = self
+                .storage
+                .read_header(&current)
+                .map_err(StreamError::internal)?
+                .ok_or_else(|| {
+                    self.missing_data(FilteredDataStreamError::MissingBlockHeader(current))
+                })?;
+
+            let parent_hash = header
+                .parent_block_hash
+                .as_ref()
+                .ok_or(InvalidBlock::MissingHash)
+                .map_err(StreamError::internal)?;
+            current = GlobalBlockId::new(header.block_number - 1, parent_hash.into());
+        }
+
+        // send data in chain order, like data batches.
+        rejected_data.reverse();
+        Ok(rejected_data)
+    }
+}
+
+/// Creates the message that tells the client the stream failed.
+fn error_response(stream_id: u64, err: StreamError) -> StreamDataResponse {
+    use stream_data_response::Message;
+
+    let message = match err {
+        StreamError::Client { message } => message,
+        StreamError::Internal(err) => {
+            warn!(stream_id = stream_id, err = ?err, "data stream error");
+            "internal server error".to_string()
+        }
+    };
+
+    StreamDataResponse {
+        stream_id,
+        message: Some(Message::Error(v1alpha2::Error { message })),
+    }
 }
 
 impl<R, M> Stream for FilteredDataStream<R, M>
@@ -483,43 +750,69 @@ where
         // state changes
         self.waker = Some(cx.waker().clone());
 
-        // if `inner` is missing, then the block was never configured.
-        // nothing to do.
-        let inner = if let Some(inner) = &mut self.inner {
-            inner
-        } else {
-            return Poll::Pending;
-        };
-
-        // if the stream received an invalidate message in the previous tick, then
-        // forward it to the client.
-        if let Some(new_root) = inner.invalidated.take() {
-            use stream_data_response::Message;
-            let invalidate = Invalidate {
-                cursor: Some(new_root.to_cursor()),
-            };
-            let response = StreamDataResponse {
-                stream_id: inner.stream_id,
-                message: Some(Message::Invalidate(invalidate)),
-            };
+        // failed streams are closed, tell the client first.
+        let this = &mut *self;
+        if let Some(response) = this.errors.pop_front() {
             return Poll::Ready(Some(Ok(response)));
         }
 
-        match inner.advance_to_next_batch() {
-            Err(err) => Poll::Ready(Some(Err(err))),
-            Ok(None) => Poll::Pending,
-            Ok(Some(data)) => Poll::Ready(Some(Ok(data))),
+        // start from the stream after the one that sent the last response, so that
+        // a stream with a lot of data doesn't starve the others.
+        // if no stream was configured, there is nothing to do.
+        let stream_ids = this
+            .streams
+            .range(this.next_stream_id..)
+            .chain(this.streams.range(..this.next_stream_id))
+            .map(|(stream_id, _)| *stream_id)
+            .collect::<Vec<_>>();
+
+        let mut scan_interrupted = false;
+        for stream_id in stream_ids {
+            let inner = this
+                .streams
+                .get_mut(&stream_id)
+                .expect("stream id is in the map");
+            // wait for the client to grant more credits.
+            if inner.credits == Some(0) {
+                continue;
+            }
+            match inner.next_response() {
+                Err(err) => {
+                    // only the failing stream is closed.
+                    this.streams.remove(&stream_id);
+                    this.next_stream_id = stream_id.wrapping_add(1);
+                    return Poll::Ready(Some(Ok(error_response(stream_id, err))));
+                }
+                Ok(None) => {
+                    // the stream stopped scanning to yield, poll again as soon as possible.
+                    scan_interrupted |= std::mem::take(&mut inner.scan_interrupted);
+                }
+                Ok(Some(response)) => {
+                    if let Some(credits) = inner.credits.as_mut() {
+                        *credits -= 1;
+                    }
+                    this.next_stream_id = stream_id.wrapping_add(1);
+                    return Poll::Ready(Some(Ok(response)));
+                }
+            }
         }
+
+        if scan_interrupted {
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        if let Some(inner) = &self.inner {
-            let current = inner.previous_iter_cursor.map(|c| c.number()).unwrap_or(0);
-            let head = inner.accepted_cursor.number();
-            let difference = (head - current) as usize;
-            (difference, None)
-        } else {
-            (0, None)
-        }
+        let difference = self
+            .streams
+            .values()
+            .map(|inner| {
+                let current = inner.previous_iter_cursor.map(|c| c.number()).unwrap_or(0);
+                let head = inner.accepted_cursor.number();
+                head.saturating_sub(current) as usize
+            })
+            .sum();
+        (difference, None)
     }
 }
@@ -4,5 +4,12 @@ mod configuration;
 mod data;
 mod error;
 mod filtered;
+mod range;
 
-pub use self::{configuration::StreamConfigurationStream, data::DataStream, error::StreamError};
+pub use self::{
+    configuration::StreamConfigurationStream,
+    data::DataStream,
+    error::StreamError,
+    filtered::{DEFAULT_PROGRESS_INTERVAL, MAX_STREAMS_PER_CONNECTION},
+    range::{data_in_range, BlockRangeData, MAX_RANGE_SIZE},
+};
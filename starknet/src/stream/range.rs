@@ -0,0 +1,152 @@
+//! Filter data in a range of blocks.
+
+use std::sync::Arc;
+
+use apibara_core::{node::v1alpha2::DataFinality, starknet::v1alpha2};
+use prost::Message;
+
+use crate::{core::GlobalBlockId, db::StorageReader, server::RequestMeter};
+
+use super::{
+    block::{BlockDataFilter, DatabaseBlockDataFilter},
+    StreamError,
+};
+
+/// Maximum number of blocks scanned by a single range request.
+pub const MAX_RANGE_SIZE: u64 = 1_000;
+
+/// Data in a range of blocks.
+#[derive(Debug)]
+pub struct BlockRangeData {
+    /// The last block in the range, `None` if the range is empty.
+    pub end_cursor: Option<GlobalBlockId>,
+    /// The finality of the last block in the range.
+    pub finality: DataFinality,
+    /// The encoded data, one item for each block with data.
+    pub data: Vec<Vec<u8>>,
+}
+
+impl BlockRangeData {
+    fn empty() -> Self {
+        BlockRangeData {
+            end_cursor: None,
+            finality: DataFinality::DataStatusUnknown,
+            data: Vec::default(),
+        }
+    }
+}
+
+/// Returns the data matching `filter` in the canonical blocks `from..to`.
+///
+/// The range is truncated to the most recent block with the requested finality
+/// and to at most [MAX_RANGE_SIZE] blocks.
+pub fn data_in_range<R, M>(
+    storage: Arc<R>,
+    filter: v1alpha2::Filter,
+    from: u64,
+    to: Option<u64>,
+    finality: DataFinality,
+    meter: &Arc<M>,
+) -> Result<BlockRangeData, StreamError>
+where
+    R: StorageReader,
+    M: RequestMeter,
+{
+    let finalized = storage
+        .highest_finalized_block()
+        .map_err(StreamError::internal)?;
+
+    let head = if finality == DataFinality::DataStatusFinalized {
+        finalized
+    } else {
+        storage
+            .highest_accepted_block()
+            .map_err(StreamError::internal)?
+    };
+
+    let head = match head {
+        None => return Ok(BlockRangeData::empty()),
+        Some(head) => head,
+    };
+
+    // `to` is exclusive.
+    let mut last = u64::min(head.number(), from.saturating_add(MAX_RANGE_SIZE - 1));
+    if let Some(to) = to {
+        if to <= from {
+            return Ok(BlockRangeData::empty());
+        }
+        last = u64::min(last, to - 1);
+    }
+
+    if from > last {
+        return Ok(BlockRangeData::empty());
+    }
+
+    let block_filter = DatabaseBlockDataFilter::new(storage.clone(), filter);
+
+    let mut data = Vec::new();
+    let mut end_cursor = None;
+    let mut truncated = false;
+
+    let mut next_block_number = block_filter
+        .next_candidate_block(from, last)
+        .map_err(StreamError::internal)?;
+
+    while let Some(block_number) = next_block_number {
+        let block_id = match storage
+            .canonical_block_id(block_number)
+            .map_err(StreamError::internal)?
+        {
+            None => {
+                // the chain shrank because of a reorg. return what we have.
+                truncated = true;
+                break;
+            }
+            Some(block_id) => block_id,
+        };
+
+        end_cursor = Some(block_id);
+
+        if let Some(block) = block_filter
+            .data_for_block(&block_id, meter)
+            .map_err(StreamError::internal)?
+        {
+            data.push(block.encode_to_vec());
+        }
+
+        next_block_number = block_filter
+            .next_candidate_block(block_number + 1, last)
+            .map_err(StreamError::internal)?;
+    }
+
+    // blocks after the last candidate don't contain any data.
+    if !truncated {
+        if let Some(block_id) = storage
+            .canonical_block_id(last)
+            .map_err(StreamError::internal)?
+        {
+            end_cursor = Some(block_id);
+        }
+    }
+
+    let end_cursor = match end_cursor {
+        None => return Ok(BlockRangeData::empty()),
+        Some(cursor) => cursor,
+    };
+
+    let is_finalized = finalized
+        .map(|finalized| end_cursor.number() <= finalized.number())
+        .unwrap_or(false);
+
+    let finality = if is_finalized {
+        DataFinality::DataStatusFinalized
+    } else {
+        DataFinality::DataStatusAccepted
+    };
+
+    Ok(BlockRangeData {
+        end_cursor: Some(end_cursor),
+        finality,
+        data,
+    })
+}
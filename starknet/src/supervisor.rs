@@ -0,0 +1,219 @@
+//! Restart failed tasks with exponential backoff.
+
+use std::time::{Duration, Instant};
+
+use apibara_node::o11y::{self, Counter, KeyValue};
+use backoff::{backoff::Backoff, ExponentialBackoff, ExponentialBackoffBuilder};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+/// A long running task that can be restarted after it fails.
+#[apibara_node::async_trait]
+pub trait Task {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Runs the task until it's cancelled or fails.
+    async fn run(&mut self, ct: CancellationToken) -> Result<(), Self::Error>;
+
+    /// Returns true if the task can recover from `err` by restarting.
+    ///
+    /// Tasks that fail with a permanent error are not restarted.
+    fn is_transient(&self, _err: &Self::Error) -> bool {
+        true
+    }
+}
+
+/// Configure how tasks are restarted.
+#[derive(Debug, Clone)]
+pub struct SupervisorConfig {
+    /// Delay before the first restart.
+    pub initial_interval: Duration,
+    /// Maximum delay between restarts.
+    pub max_interval: Duration,
+    /// Give up restarting a task that keeps failing for longer than this.
+    pub max_elapsed_time: Duration,
+    /// A task that runs for at least this long before failing was healthy, so
+    /// the backoff starts again from `initial_interval`.
+    pub reset_after: Duration,
+}
+
+/// Runs a task, restarting it when it fails.
+pub struct Supervisor {
+    name: &'static str,
+    config: SupervisorConfig,
+    restart_counter: Counter<u64>,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        SupervisorConfig {
+            initial_interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(60),
+            max_elapsed_time: Duration::from_secs(15 * 60),
+            reset_after: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+impl Supervisor {
+    pub fn new(name: &'static str, config: SupervisorConfig) -> Self {
+        let restart_counter = new_restart_counter();
+        Supervisor {
+            name,
+            config,
+            restart_counter,
+        }
+    }
+
+    /// Runs the task until it's cancelled.
+    ///
+    /// Failed tasks are restarted after a randomized, exponentially increasing
+    /// delay. If the task is crash looping or fails with a permanent error, it
+    /// returns the error.
+    pub async fn run<T: Task>(&self, task: &mut T, ct: CancellationToken) -> Result<(), T::Error> {
+        let mut backoff = self.new_backoff();
+        loop {
+            let started_at = Instant::now();
+            let err = match task.run(ct.clone()).await {
+                Ok(_) => return Ok(()),
+                Err(err) => err,
+            };
+
+            if ct.is_cancelled() {
+                warn!(task = self.name, error = ?err, "task failed while shutting down");
+                return Ok(());
+            }
+
+            if !task.is_transient(&err) {
+                error!(task = self.name, error = ?err, "task failed with a permanent error");
+                return Err(err);
+            }
+
+            if started_at.elapsed() >= self.config.reset_after {
+                backoff.reset();
+            }
+
+            let delay = match backoff.next_backoff() {
+                None => {
+                    error!(task = self.name, error = ?err, "task is crash looping");
+                    return Err(err);
+                }
+                Some(delay) => delay,
+            };
+
+            warn!(task = self.name, error = ?err, delay = ?delay, "task failed");
+
+            let cx = o11y::Context::current();
+            self.restart_counter
+                .add(&cx, 1, &[KeyValue::new("task", self.name)]);
+
+            tokio::select! {
+                _ = ct.cancelled() => {
+                    return Ok(());
+                }
+                _ = tokio::time::sleep(delay) => {}
+            }
+
+            info!(task = self.name, "restarting task");
+        }
+    }
+
+    fn new_backoff(&self) -> ExponentialBackoff {
+        ExponentialBackoffBuilder::new()
+            .with_initial_interval(self.config.initial_interval)
+            .with_max_interval(self.config.max_interval)
+            .with_max_elapsed_time(Some(self.config.max_elapsed_time))
+            .build()
+    }
+}
+
+fn new_restart_counter() -> Counter<u64> {
+    let meter = o11y::meter("supervisor");
+    meter.u64_counter("task_restart").init()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio_util::sync::CancellationToken;
+
+    use super::{Supervisor, SupervisorConfig, Task};
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("task failed")]
+    struct TaskError;
+
+    /// A task that fails the first `failures` times it runs.
+    struct FailingTask {
+        failures: usize,
+        permanent: bool,
+        runs: usize,
+    }
+
+    #[apibara_node::async_trait]
+    impl Task for FailingTask {
+        type Error = TaskError;
+
+        async fn run(&mut self, _ct: CancellationToken) -> Result<(), Self::Error> {
+            self.runs += 1;
+            if self.runs <= self.failures {
+                Err(TaskError)
+            } else {
+                Ok(())
+            }
+        }
+
+        fn is_transient(&self, _err: &Self::Error) -> bool {
+            !self.permanent
+        }
+    }
+
+    fn test_config(max_elapsed_time: Duration) -> SupervisorConfig {
+        SupervisorConfig {
+            initial_interval: Duration::from_millis(1),
+            max_interval: Duration::from_millis(5),
+            max_elapsed_time,
+            reset_after: Duration::from_secs(60),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restart_failed_task() {
+        let supervisor = Supervisor::new("test", test_config(Duration::from_secs(10)));
+        let mut task = FailingTask {
+            failures: 3,
+            permanent: false,
+            runs: 0,
+        };
+        let result = supervisor.run(&mut task, CancellationToken::new()).await;
+        assert!(result.is_ok());
+        assert_eq!(task.runs, 4);
+    }
+
+    #[tokio::test]
+    async fn test_give_up_on_crash_loop() {
+        let supervisor = Supervisor::new("test", test_config(Duration::from_millis(20)));
+        let mut task = FailingTask {
+            failures: usize::MAX,
+            permanent: false,
+            runs: 0,
+        };
+        let result = supervisor.run(&mut task, CancellationToken::new()).await;
+        assert!(result.is_err());
+        assert!(task.runs > 1);
+    }
+
+    #[tokio::test]
+    async fn test_stop_on_permanent_error() {
+        let supervisor = Supervisor::new("test", test_config(Duration::from_secs(10)));
+        let mut task = FailingTask {
+            failures: 3,
+            permanent: true,
+            runs: 0,
+        };
+        let result = supervisor.run(&mut task, CancellationToken::new()).await;
+        assert!(result.is_err());
+        assert_eq!(task.runs, 1);
+    }
+}